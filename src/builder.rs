@@ -3,6 +3,7 @@ use crate::{
     credentials::{AccountCredentials, ApplicationCredentials},
     error::Error,
     error::Result,
+    util::SymbolLoading,
 };
 
 use rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore};
@@ -32,6 +33,8 @@ pub struct ClientBuilder {
     connect_retry_delay: Option<Duration>,
     application_credentials: Option<ApplicationCredentials>,
     account_credentials: Option<AccountCredentials>,
+    symbol_loading: Option<SymbolLoading>,
+    include_archived_symbols: Option<bool>,
 }
 
 impl ClientBuilder {
@@ -57,7 +60,13 @@ impl ClientBuilder {
         let account = self.account_credentials.clone().ok_or(Error::String(
             "You must set a account credential for the client".into(),
         ))?;
-        Ok(Session::new(app, account, opts))
+        Ok(Session::new(
+            app,
+            account,
+            opts,
+            self.symbol_loading.clone().unwrap_or_default(),
+            self.include_archived_symbols.unwrap_or(false),
+        ))
     }
 
     pub fn set_url_string(&mut self, url: &str) -> Result<&mut Self> {
@@ -92,6 +101,23 @@ impl ClientBuilder {
         self
     }
 
+    /// Set how the symbol store is filled on connect.
+    ///
+    /// The default is `SymbolLoading::Eager`.
+    pub fn set_symbol_loading(&mut self, symbol_loading: SymbolLoading) -> &mut Self {
+        self.symbol_loading = Some(symbol_loading);
+        self
+    }
+
+    /// Set whether the symbol store also keeps the archived symbols, so historical deals on
+    /// delisted instruments can be resolved.
+    ///
+    /// The default is false.
+    pub fn set_include_archived_symbols(&mut self, include_archived_symbols: bool) -> &mut Self {
+        self.include_archived_symbols = Some(include_archived_symbols);
+        self
+    }
+
     pub fn set_server_keep_alive(&mut self, keep_alive: Duration) -> &mut Self {
         self.server_keep_alive = Some(keep_alive);
         self
@@ -1,13 +1,16 @@
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 
 use super::Session;
 use crate::protos::spotware_message::*;
+use crate::util::SymbolUpdate;
 
 #[derive(Debug, Clone)]
 pub enum NotifyEvent {
     None,
     TrailingSlChangedEvent(ProtoOaTrailingSlChangedEvent),
     SymbolChangedEvent(ProtoOaSymbolChangedEvent),
+    /// The store was refreshed after a `SymbolChangedEvent`, or by a background refresh
+    SymbolUpdated(Vec<SymbolUpdate>),
     // TODO: second-largest variant 224bytes
     AccoutDataUpdateEvent(ProtoOaTraderUpdatedEvent),
     // TODO: largest variant 1224bytes
@@ -38,9 +41,28 @@ impl Session {
 
     // Event that is sent when the symbol is changed on the Server side.
     // pub symbol_id: Vec<i64>,
-    pub fn notify_symbol_changed_event(&mut self, msg: ProtoMessage) -> NotifyEvent {
+    // The changed symbols are fetched again and replaced in the store, the event becomes a
+    // SymbolUpdated with the changed fields. The raw event is passed on when the fetch fails.
+    pub async fn notify_symbol_changed_event(&mut self, msg: ProtoMessage) -> NotifyEvent {
         let event = ProtoOaSymbolChangedEvent::from(msg);
-        NotifyEvent::SymbolChangedEvent(event)
+        match self.symbol_by_id(event.symbol_id.clone()).await {
+            Ok(res) => {
+                let updates = self.store.apply_symbols(res.symbol);
+                self.save_symbol_cache();
+                // a background refresh started before the change would bring the old fields back
+                if self.symbol_refresh.is_some() {
+                    if let Err(e) = self.spawn_symbol_refresh() {
+                        warn!("restart symbol refresh failed: {}", e);
+                        self.symbol_refresh = None;
+                    }
+                }
+                NotifyEvent::SymbolUpdated(updates)
+            }
+            Err(e) => {
+                warn!("refresh symbols {:?} failed: {}", event.symbol_id, e);
+                NotifyEvent::SymbolChangedEvent(event)
+            }
+        }
     }
 
     // Event that is sent when a Trader is updated on Server side.
@@ -147,6 +169,14 @@ impl Session {
     }
 
     pub async fn dispatch_event(&mut self, msg: ProtoMessage) -> NotifyEvent {
+        if let Some(updates) = self.poll_symbol_refresh() {
+            info!("symbol store refreshed, {} symbols changed", updates.len());
+            if !updates.is_empty() {
+                // the message comes back from the next listen
+                self.deferred_message = Some(msg);
+                return NotifyEvent::SymbolUpdated(updates);
+            }
+        }
         match msg.payload_type {
             50 => self.notify_proto_error_res(msg),
             51 => self.notify_proto_heartbeat_event(msg), //unrearchable!
@@ -155,7 +185,7 @@ impl Session {
             2148 => self.notify_client_disconnect_event(msg),
 
             2107 => return self.notify_trailing_sl_changed_event(msg),
-            2120 => return self.notify_symbol_changed_event(msg),
+            2120 => return self.notify_symbol_changed_event(msg).await,
             2123 => return self.notify_trader_updated_event(msg),
             2126 => return self.notify_execution_event(msg),
             2131 => return self.notify_spot_event(msg),
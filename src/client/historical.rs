@@ -1,6 +1,39 @@
 use super::Session;
 use crate::{protos::spotware_message::*, Error};
 
+// latest timestamp accepted by the server (19th Jan 2038)
+pub const MAX_HISTORY_TIMESTAMP: i64 = 2147483646000;
+// order, deal, cash flow and tick requests cover at most one week
+pub const MAX_HISTORY_RANGE_MS: i64 = 604800000;
+
+// Validate a history request range, instead of having the server reject it.
+pub(crate) fn check_time_range(
+    from_timestamp: i64,
+    to_timestamp: i64,
+    max_range: i64,
+) -> Result<(), Error> {
+    if from_timestamp < 0
+        || to_timestamp >= MAX_HISTORY_TIMESTAMP
+        || to_timestamp < from_timestamp
+        || to_timestamp - from_timestamp > max_range
+    {
+        return Err(Error::TimeRangeError(from_timestamp, to_timestamp));
+    }
+    Ok(())
+}
+
+// Trend bar range accepted at once for a period: M1..M5 5 weeks, M10..H1 35 weeks,
+// H4..D1 1 year, W1 and MN1 5 years.
+pub(crate) fn max_trend_bar_range_ms(period: i32) -> Result<i64, Error> {
+    match period {
+        1..=5 => Ok(3_024_000_000),
+        6..=9 => Ok(21_168_000_000),
+        10..=12 => Ok(31_622_400_000),
+        13 | 14 => Ok(158_112_000_000),
+        _ => Err(Error::PeriodParamError(period)),
+    }
+}
+
 impl Session {
     //+------------------------------------------------------------------+
     //|                           Historical                             |
@@ -12,7 +45,7 @@ impl Session {
         from_timestamp: i64,
         to_timestamp: i64,
     ) -> Result<ProtoOaOrderListRes, Error> {
-        let req = self.make_order_list_req(from_timestamp, to_timestamp);
+        let req = self.make_order_list_req(from_timestamp, to_timestamp)?;
 
         self.connection
             .send_historical_request(req.into())
@@ -27,7 +60,7 @@ impl Session {
         to_timestamp: i64,
         max_rows: Option<i32>,
     ) -> Result<ProtoOaDealListRes, Error> {
-        let req = self.make_deal_list_req(from_timestamp, to_timestamp, max_rows);
+        let req = self.make_deal_list_req(from_timestamp, to_timestamp, max_rows)?;
 
         self.connection
             .send_historical_request(req.into())
@@ -41,7 +74,7 @@ impl Session {
         from_timestamp: i64,
         to_timestamp: i64,
     ) -> Result<ProtoOaCashFlowHistoryListRes, Error> {
-        let req = self.make_cash_flow_history_list_req(from_timestamp, to_timestamp);
+        let req = self.make_cash_flow_history_list_req(from_timestamp, to_timestamp)?;
 
         self.connection
             .send_historical_request(req.into())
@@ -58,7 +91,8 @@ impl Session {
         symbol_id: i64,
         count: Option<u32>,
     ) -> Result<ProtoOaGetTrendbarsRes, Error> {
-        let req = self.make_trend_bars_req(from_timestamp, to_timestamp, period, symbol_id, count);
+        let req =
+            self.make_trend_bars_req(from_timestamp, to_timestamp, period, symbol_id, count)?;
         self.connection
             .send_historical_request(req.into())
             .await
@@ -73,7 +107,7 @@ impl Session {
         from_timestamp: i64,
         to_timestamp: i64,
     ) -> Result<ProtoOaGetTickDataRes, Error> {
-        let req = self.make_tick_data_req(symbol_id, r#type, from_timestamp, to_timestamp);
+        let req = self.make_tick_data_req(symbol_id, r#type, from_timestamp, to_timestamp)?;
 
         self.connection
             .send_historical_request(req.into())
@@ -87,16 +121,14 @@ impl Session {
         &self,
         from_timestamp: i64,
         to_timestamp: i64,
-    ) -> ProtoOaOrderListReq {
-        assert!(from_timestamp >= 0);
-        assert!(to_timestamp < 2147483646000);
-        assert!(to_timestamp - from_timestamp <= 604800000); // 1 week
-        ProtoOaOrderListReq {
+    ) -> Result<ProtoOaOrderListReq, Error> {
+        check_time_range(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        Ok(ProtoOaOrderListReq {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
             from_timestamp,
             to_timestamp,
-        }
+        })
     }
 
     pub fn make_deal_list_req(
@@ -104,33 +136,29 @@ impl Session {
         from_timestamp: i64,
         to_timestamp: i64,
         max_rows: Option<i32>,
-    ) -> ProtoOaDealListReq {
-        assert!(from_timestamp >= 0);
-        assert!(to_timestamp < 2147483646000);
-        assert!(to_timestamp - from_timestamp <= 604800000); // 1 week
-        ProtoOaDealListReq {
+    ) -> Result<ProtoOaDealListReq, Error> {
+        check_time_range(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        Ok(ProtoOaDealListReq {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
             from_timestamp,
             to_timestamp,
             max_rows,
-        }
+        })
     }
 
     pub fn make_cash_flow_history_list_req(
         &self,
         from_timestamp: i64,
         to_timestamp: i64,
-    ) -> ProtoOaCashFlowHistoryListReq {
-        assert!(from_timestamp >= 0);
-        assert!(to_timestamp < 2147483646000);
-        assert!(to_timestamp - from_timestamp <= 604800000); // 1 week
-        ProtoOaCashFlowHistoryListReq {
+    ) -> Result<ProtoOaCashFlowHistoryListReq, Error> {
+        check_time_range(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        Ok(ProtoOaCashFlowHistoryListReq {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
             from_timestamp,
             to_timestamp,
-        }
+        })
     }
 
     pub fn make_trend_bars_req(
@@ -140,21 +168,15 @@ impl Session {
         period: i32,
         symbol_id: i64,
         count: Option<u32>,
-    ) -> ProtoOaGetTrendbarsReq {
+    ) -> Result<ProtoOaGetTrendbarsReq, Error> {
         // from_timestamp:The Unix time in milliseconds from which the search starts. Must be bigger or equal to zero (1st Jan 1970).
         // to_timestamp:The Unix time in milliseconds of finishing the search. Smaller or equal to 2147483646000 (19th Jan 2038).
         // Validation: toTimestamp - fromTimestamp <= X, where X depends on series period: M1, M2, M3, M4, M5: 3024000000 (5 weeks);
         // M10, M15, M30, H1: 21168000000 (35 weeks),
         // H4, H12, D1: 31622400000 (1 year); W1, MN1: 158112000000 (5 years).
-        assert!(from_timestamp >= 0);
-        assert!(to_timestamp < 2147483646000);
-        assert!(
-            (period <= 5 && to_timestamp - from_timestamp <= 302400000)
-                || ((6..=9).contains(&period) && to_timestamp - from_timestamp <= 21168000000)
-                || ((10..=12).contains(&period) && to_timestamp - from_timestamp <= 31622400000)
-                || ((13..=14).contains(&period) && to_timestamp - from_timestamp <= 158112000000)
-        );
-        ProtoOaGetTrendbarsReq {
+        let max_range = max_trend_bar_range_ms(period)?;
+        check_time_range(from_timestamp, to_timestamp, max_range)?;
+        Ok(ProtoOaGetTrendbarsReq {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
             from_timestamp,
@@ -162,7 +184,7 @@ impl Session {
             period,
             symbol_id,
             count,
-        }
+        })
     }
 
     pub fn make_tick_data_req(
@@ -171,19 +193,17 @@ impl Session {
         r#type: i32,
         from_timestamp: i64,
         to_timestamp: i64,
-    ) -> ProtoOaGetTickDataReq {
+    ) -> Result<ProtoOaGetTickDataReq, Error> {
         // The Unix time in milliseconds of starting the search. Must be bigger or equal to zero (1st Jan 1970). Validation: toTimestamp - fromTimestamp <= 604800000 (1 week).
         // The Unix time in milliseconds of finishing the search. <= 2147483646000 (19th Jan 2038).
-        assert!(from_timestamp >= 0);
-        assert!(to_timestamp < 2147483646000);
-        assert!(to_timestamp - from_timestamp <= 604800000); // 1 week
-        ProtoOaGetTickDataReq {
+        check_time_range(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        Ok(ProtoOaGetTickDataReq {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
             symbol_id,
             r#type,
             from_timestamp,
             to_timestamp,
-        }
+        })
     }
 }
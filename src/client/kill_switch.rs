@@ -0,0 +1,225 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::Ordering;
+
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{error, warn};
+
+use super::Session;
+use crate::{protos::spotware_message::*, util::units::Volume, Error};
+
+// pause between two reconcile rounds, gives the server time to apply the previous round
+const FLATTEN_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Which orders and positions the kill switch acts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlattenFilter {
+    All,
+    Symbol(i64),
+    Label(String),
+}
+
+impl FlattenFilter {
+    fn matches(&self, trade_data: &ProtoOaTradeData) -> bool {
+        match self {
+            FlattenFilter::All => true,
+            FlattenFilter::Symbol(symbol_id) => trade_data.symbol_id == *symbol_id,
+            FlattenFilter::Label(label) => trade_data.label.as_deref() == Some(label.as_str()),
+        }
+    }
+}
+
+/// Outcome of a flatten run.
+#[derive(Debug, Clone, Default)]
+pub struct FlattenReport {
+    /// Pending orders that are gone from the account
+    pub cancelled_orders: Vec<i64>,
+    /// Open positions that are gone from the account
+    pub closed_positions: Vec<i64>,
+    /// Orders still pending when the deadline expired, with the last error seen
+    pub failed_orders: Vec<(i64, String)>,
+    /// Positions still open when the deadline expired, with the last error seen
+    pub failed_positions: Vec<(i64, String)>,
+    /// Number of reconcile rounds
+    pub rounds: u32,
+    /// True if the last reconcile reported nothing left
+    pub completed: bool,
+}
+
+impl Session {
+    //+------------------------------------------------------------------+
+    //|                           Kill switch                            |
+    //+------------------------------------------------------------------+
+
+    // Engage the kill switch lock and flatten the whole account:
+    // cancel every pending order and close every open position.
+    // new_order and modify_order are rejected until unlock_trading is called.
+    pub async fn flatten_all(&self, timeout: Duration) -> Result<FlattenReport, Error> {
+        self.lock_trading();
+        self.flatten(FlattenFilter::All, timeout).await
+    }
+
+    // Cancel pending orders and close open positions of one symbol.
+    pub async fn flatten_symbol(
+        &self,
+        symbol_id: i64,
+        timeout: Duration,
+    ) -> Result<FlattenReport, Error> {
+        self.flatten(FlattenFilter::Symbol(symbol_id), timeout)
+            .await
+    }
+
+    // Cancel pending orders and close open positions carrying the label.
+    pub async fn flatten_label(
+        &self,
+        label: &str,
+        timeout: Duration,
+    ) -> Result<FlattenReport, Error> {
+        self.flatten(FlattenFilter::Label(label.to_string()), timeout)
+            .await
+    }
+
+    // Reconcile, cancel and close matching orders/positions, and repeat until
+    // reconcile reports nothing left or the timeout expires.
+    // Only fails if no reconcile round succeeded at all.
+    pub async fn flatten(
+        &self,
+        filter: FlattenFilter,
+        timeout: Duration,
+    ) -> Result<FlattenReport, Error> {
+        let deadline = Instant::now() + timeout;
+        let mut report = FlattenReport::default();
+
+        let mut seen_orders = BTreeSet::new();
+        let mut seen_positions = BTreeSet::new();
+        let mut order_errors: HashMap<i64, String> = HashMap::new();
+        let mut position_errors: HashMap<i64, String> = HashMap::new();
+        // state of the last successful reconcile, None until one succeeded
+        let mut remaining: Option<(Vec<i64>, Vec<i64>)> = None;
+        let mut last_reconcile_error: Option<Error>;
+
+        loop {
+            report.rounds += 1;
+            match self.get_open_position_and_pending_orders().await {
+                Err(e) => {
+                    warn!("flatten: reconcile failed: {}", e);
+                    last_reconcile_error = Some(e);
+                }
+                Ok(res) => {
+                    last_reconcile_error = None;
+                    let orders = res
+                        .order
+                        .iter()
+                        .filter(|o| filter.matches(&o.trade_data))
+                        .map(|o| o.order_id)
+                        .collect::<Vec<_>>();
+                    let positions = res
+                        .position
+                        .iter()
+                        .filter(|p| filter.matches(&p.trade_data))
+                        .map(|p| (p.position_id, p.trade_data.volume))
+                        .collect::<Vec<_>>();
+
+                    remaining = Some((
+                        orders.clone(),
+                        positions.iter().map(|(id, _)| *id).collect(),
+                    ));
+
+                    if orders.is_empty() && positions.is_empty() {
+                        report.completed = true;
+                        break;
+                    }
+
+                    if report.rounds > 1 && Instant::now() >= deadline {
+                        break;
+                    }
+
+                    // cancel orders first, so no pending order re-opens a closed position
+                    for order_id in orders {
+                        seen_orders.insert(order_id);
+                        if let Err(e) = self.cancel_order(order_id).await {
+                            warn!("flatten: cancel order {} failed: {}", order_id, e);
+                            order_errors.insert(order_id, e.to_string());
+                        }
+                    }
+
+                    for (position_id, volume) in positions {
+                        seen_positions.insert(position_id);
+                        if let Err(e) = self
+                            .close_position(position_id, Volume::from_cents(volume))
+                            .await
+                        {
+                            warn!("flatten: close position {} failed: {}", position_id, e);
+                            position_errors.insert(position_id, e.to_string());
+                        }
+                    }
+                }
+            }
+
+            // a successful round checks the deadline right after reconcile,
+            // so only a failed reconcile can end up here past the deadline
+            if last_reconcile_error.is_some() && Instant::now() >= deadline {
+                break;
+            }
+            sleep(FLATTEN_RETRY_INTERVAL).await;
+        }
+
+        let (remaining_orders, remaining_positions) = match remaining {
+            Some(r) => r,
+            None => return Err(last_reconcile_error.unwrap_or(Error::Disconnected)),
+        };
+        let fallback = last_reconcile_error
+            .map(|e| format!("reconcile failed: {}", e))
+            .unwrap_or_else(|| "still present after deadline".to_string());
+
+        for order_id in seen_orders {
+            if remaining_orders.contains(&order_id) {
+                let reason = order_errors.remove(&order_id).unwrap_or(fallback.clone());
+                report.failed_orders.push((order_id, reason));
+            } else {
+                report.cancelled_orders.push(order_id);
+            }
+        }
+
+        for position_id in seen_positions {
+            if remaining_positions.contains(&position_id) {
+                let reason = position_errors
+                    .remove(&position_id)
+                    .unwrap_or(fallback.clone());
+                report.failed_positions.push((position_id, reason));
+            } else {
+                report.closed_positions.push(position_id);
+            }
+        }
+
+        if !report.completed {
+            error!(
+                "flatten {:?} incomplete: {} orders, {} positions left",
+                filter,
+                report.failed_orders.len(),
+                report.failed_positions.len()
+            );
+        }
+
+        Ok(report)
+    }
+
+    // Reject new_order and modify_order until unlock_trading is called.
+    pub fn lock_trading(&self) {
+        self.trading_locked.store(true, Ordering::SeqCst);
+    }
+
+    pub fn unlock_trading(&self) {
+        self.trading_locked.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_trading_locked(&self) -> bool {
+        self.trading_locked.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn check_trading_unlocked(&self) -> Result<(), Error> {
+        if self.is_trading_locked() {
+            return Err(Error::TradingLocked);
+        }
+        Ok(())
+    }
+}
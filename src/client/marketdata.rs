@@ -194,6 +194,10 @@ impl Session {
         Ok(())
     }
 
+    pub fn subscribed_spots(&self) -> &[i64] {
+        &self.subscribed_spots
+    }
+
     // subscribe
     pub async fn resume_subscribe(&mut self) -> Result<(), Error> {
         if !self.subscribed_spots.is_empty() {
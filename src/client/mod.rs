@@ -1,10 +1,13 @@
+use std::sync::atomic::AtomicBool;
+
 use crate::builder::ClientBuilder;
 use crate::credentials::AccountCredentials;
 use crate::credentials::ApplicationCredentials;
 use crate::io::Event;
 use crate::io::IoOptions;
+use crate::io::Requester;
 use crate::protos::spotware_message::ProtoMessage;
-use crate::util::get_symbol_infos;
+use crate::util::SymbolLoading;
 use crate::util::SymbolStore;
 use crate::{error::Error, io::Connection};
 
@@ -21,7 +24,15 @@ pub struct Session {
     subscribed_spots: Vec<i64>,
     subscribed_bars: Vec<(i32, i64)>,
     subscribed_depths: Vec<i64>,
+    // set by the kill switch, rejects new orders and order amendments
+    trading_locked: AtomicBool,
     pub store: SymbolStore,
+    symbol_loading: SymbolLoading,
+    include_archived_symbols: bool,
+    // symbols fetched in the background, applied by poll_symbol_refresh
+    symbol_refresh: Option<tokio::sync::oneshot::Receiver<Result<SymbolStore, Error>>>,
+    // held back by dispatch_event to report a symbol refresh first, returned by listen
+    deferred_message: Option<ProtoMessage>,
 }
 
 impl Session {
@@ -34,6 +45,8 @@ impl Session {
         application: ApplicationCredentials,
         account: AccountCredentials,
         opts: IoOptions,
+        symbol_loading: SymbolLoading,
+        include_archived_symbols: bool,
     ) -> Self {
         let (tx, _) = tokio::sync::broadcast::channel(100);
         Self {
@@ -45,7 +58,12 @@ impl Session {
             subscribed_spots: Vec::new(),
             subscribed_bars: Vec::new(),
             subscribed_depths: Vec::new(),
+            trading_locked: AtomicBool::new(false),
             store: SymbolStore::new(),
+            symbol_loading,
+            include_archived_symbols,
+            symbol_refresh: None,
+            deferred_message: None,
         }
     }
 
@@ -54,9 +72,7 @@ impl Session {
         self.version = self.get_server_version().await?;
         self.auth_application().await?;
         self.auth_account().await?;
-        let infos = get_symbol_infos(self).await?;
-        self.store.from_symbol_infos(&infos);
-        Ok(())
+        self.load_symbols_on_connect().await
     }
 
     pub async fn shutdown(&mut self) -> Result<(), Error> {
@@ -74,6 +90,9 @@ impl Session {
     }
 
     pub async fn listen(&mut self) -> Option<Event> {
+        if let Some(message) = self.deferred_message.take() {
+            return Some(Event::Message(message));
+        }
         self.connection.listen().await
     }
 
@@ -81,6 +100,10 @@ impl Session {
         self.connection.post_message(message).await
     }
 
+    pub(crate) fn requester(&self) -> Result<Requester, Error> {
+        self.connection.requester()
+    }
+
     pub async fn post_historical_message(&self, message: ProtoMessage) -> Result<(), Error> {
         self.connection.post_historical_message(message).await
     }
@@ -90,11 +113,17 @@ pub mod account;
 pub mod auth;
 pub mod event;
 pub mod historical;
+pub mod kill_switch;
 pub mod margin;
 pub mod marketdata;
 pub mod misc;
 pub mod order;
+pub mod pagination;
 pub mod position;
 pub mod symbol;
+pub mod typed;
 
 pub use event::NotifyEvent;
+pub use kill_switch::{FlattenFilter, FlattenReport};
+pub use order::{ModifyOrderParams, NewOrderParams, SubmitOutcome};
+pub use pagination::HistoryRecord;
@@ -1,5 +1,21 @@
+use chrono::Utc;
+use tokio::time::{sleep, Duration};
+use tracing::warn;
+use uuid::Uuid;
+
 use super::Session;
-use crate::{protos::spotware_message::*, Error};
+use crate::{
+    protos::spotware_message::*,
+    util::units::{Price, Volume},
+    Error,
+};
+
+// wait before looking up an order whose submission timed out, the request may still be in flight.
+// Doubled on each of the SUBMIT_LOOKUP_ATTEMPTS lookups before the order is declared absent.
+const SUBMIT_SETTLE_DELAY: Duration = Duration::from_secs(1);
+const SUBMIT_LOOKUP_ATTEMPTS: u32 = 3;
+// margin around the submission time when searching the order history
+const SUBMIT_LOOKUP_MARGIN_MS: i64 = 60_000;
 
 #[derive(Debug, Clone)]
 pub struct NewOrderParams {
@@ -28,7 +44,7 @@ pub struct NewOrderParams {
     stop_trigger_method: Option<ProtoOaOrderTriggerMethod>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ModifyOrderParams {
     volume: Option<i64>,
     limit_price: Option<f64>,
@@ -43,6 +59,273 @@ pub struct ModifyOrderParams {
     trailing_stop_loss: Option<bool>,
     stop_trigger_method: Option<i32>,
 }
+
+impl NewOrderParams {
+    pub fn new(
+        symbol_id: i64,
+        order_type: ProtoOaOrderType,
+        trade_side: ProtoOaTradeSide,
+        volume: impl Into<Volume>,
+    ) -> Self {
+        Self {
+            symbol_id,
+            order_type,
+            trade_side,
+            volume: volume.into().cents(),
+            limit_price: None,
+            stop_price: None,
+            time_in_force: None,
+            expiration_timestamp: None,
+            stop_loss: None,
+            take_profit: None,
+            comment: None,
+            base_slippage_price: None,
+            slippage_in_points: None,
+            label: None,
+            position_id: None,
+            client_order_id: None,
+            relative_stop_loss: None,
+            relative_take_profit: None,
+            guaranteed_stop_loss: None,
+            trailing_stop_loss: None,
+            stop_trigger_method: None,
+        }
+    }
+
+    pub fn market(symbol_id: i64, trade_side: ProtoOaTradeSide, volume: impl Into<Volume>) -> Self {
+        Self::new(symbol_id, ProtoOaOrderType::Market, trade_side, volume)
+    }
+
+    pub fn limit(
+        symbol_id: i64,
+        trade_side: ProtoOaTradeSide,
+        volume: impl Into<Volume>,
+        price: impl Into<Price>,
+    ) -> Self {
+        let mut params = Self::new(symbol_id, ProtoOaOrderType::Limit, trade_side, volume);
+        params.limit_price = Some(price.into().to_f64());
+        params
+    }
+
+    pub fn stop(
+        symbol_id: i64,
+        trade_side: ProtoOaTradeSide,
+        volume: impl Into<Volume>,
+        price: impl Into<Price>,
+    ) -> Self {
+        let mut params = Self::new(symbol_id, ProtoOaOrderType::Stop, trade_side, volume);
+        params.stop_price = Some(price.into().to_f64());
+        params
+    }
+
+    pub fn symbol_id(&self) -> i64 {
+        self.symbol_id
+    }
+
+    pub fn trade_side(&self) -> ProtoOaTradeSide {
+        self.trade_side
+    }
+
+    pub fn volume(&self) -> i64 {
+        self.volume
+    }
+
+    pub fn client_order_id(&self) -> Option<&str> {
+        self.client_order_id.as_deref()
+    }
+
+    pub fn set_volume(&mut self, volume: impl Into<Volume>) -> &mut Self {
+        self.volume = volume.into().cents();
+        self
+    }
+
+    pub fn set_limit_price(&mut self, limit_price: impl Into<Price>) -> &mut Self {
+        self.limit_price = Some(limit_price.into().to_f64());
+        self
+    }
+
+    pub fn set_stop_price(&mut self, stop_price: impl Into<Price>) -> &mut Self {
+        self.stop_price = Some(stop_price.into().to_f64());
+        self
+    }
+
+    pub fn set_time_in_force(&mut self, time_in_force: ProtoOaTimeInForce) -> &mut Self {
+        self.time_in_force = Some(time_in_force);
+        self
+    }
+
+    /// The Unix time in milliseconds of expiration if the order has time in force GTD.
+    pub fn set_expiration_timestamp(&mut self, expiration_timestamp: i64) -> &mut Self {
+        self.expiration_timestamp = Some(expiration_timestamp);
+        self
+    }
+
+    pub fn set_stop_loss(&mut self, stop_loss: impl Into<Price>) -> &mut Self {
+        self.stop_loss = Some(stop_loss.into().to_f64());
+        self
+    }
+
+    pub fn set_take_profit(&mut self, take_profit: impl Into<Price>) -> &mut Self {
+        self.take_profit = Some(take_profit.into().to_f64());
+        self
+    }
+
+    pub fn set_comment(&mut self, comment: &str) -> &mut Self {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    pub fn set_base_slippage_price(&mut self, base_slippage_price: impl Into<Price>) -> &mut Self {
+        self.base_slippage_price = Some(base_slippage_price.into().to_f64());
+        self
+    }
+
+    pub fn set_slippage_in_points(&mut self, slippage_in_points: i32) -> &mut Self {
+        self.slippage_in_points = Some(slippage_in_points);
+        self
+    }
+
+    pub fn set_label(&mut self, label: &str) -> &mut Self {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    pub fn set_position_id(&mut self, position_id: i64) -> &mut Self {
+        self.position_id = Some(position_id);
+        self
+    }
+
+    pub fn set_client_order_id(&mut self, client_order_id: &str) -> &mut Self {
+        self.client_order_id = Some(client_order_id.to_string());
+        self
+    }
+
+    pub fn set_relative_stop_loss(&mut self, relative_stop_loss: impl Into<Price>) -> &mut Self {
+        self.relative_stop_loss = Some(relative_stop_loss.into().raw());
+        self
+    }
+
+    pub fn set_relative_take_profit(
+        &mut self,
+        relative_take_profit: impl Into<Price>,
+    ) -> &mut Self {
+        self.relative_take_profit = Some(relative_take_profit.into().raw());
+        self
+    }
+
+    pub fn set_guaranteed_stop_loss(&mut self, guaranteed_stop_loss: bool) -> &mut Self {
+        self.guaranteed_stop_loss = Some(guaranteed_stop_loss);
+        self
+    }
+
+    pub fn set_trailing_stop_loss(&mut self, trailing_stop_loss: bool) -> &mut Self {
+        self.trailing_stop_loss = Some(trailing_stop_loss);
+        self
+    }
+
+    pub fn set_stop_trigger_method(&mut self, method: ProtoOaOrderTriggerMethod) -> &mut Self {
+        self.stop_trigger_method = Some(method);
+        self
+    }
+}
+
+impl ModifyOrderParams {
+    pub fn set_volume(&mut self, volume: impl Into<Volume>) -> &mut Self {
+        self.volume = Some(volume.into().cents());
+        self
+    }
+
+    pub fn set_limit_price(&mut self, limit_price: impl Into<Price>) -> &mut Self {
+        self.limit_price = Some(limit_price.into().to_f64());
+        self
+    }
+
+    pub fn set_stop_price(&mut self, stop_price: impl Into<Price>) -> &mut Self {
+        self.stop_price = Some(stop_price.into().to_f64());
+        self
+    }
+
+    pub fn set_expiration_timestamp(&mut self, expiration_timestamp: i64) -> &mut Self {
+        self.expiration_timestamp = Some(expiration_timestamp);
+        self
+    }
+
+    pub fn set_stop_loss(&mut self, stop_loss: impl Into<Price>) -> &mut Self {
+        self.stop_loss = Some(stop_loss.into().to_f64());
+        self
+    }
+
+    pub fn set_take_profit(&mut self, take_profit: impl Into<Price>) -> &mut Self {
+        self.take_profit = Some(take_profit.into().to_f64());
+        self
+    }
+
+    pub fn set_slippage_in_points(&mut self, slippage_in_points: i32) -> &mut Self {
+        self.slippage_in_points = Some(slippage_in_points);
+        self
+    }
+
+    pub fn set_relative_stop_loss(&mut self, relative_stop_loss: impl Into<Price>) -> &mut Self {
+        self.relative_stop_loss = Some(relative_stop_loss.into().raw());
+        self
+    }
+
+    pub fn set_relative_take_profit(
+        &mut self,
+        relative_take_profit: impl Into<Price>,
+    ) -> &mut Self {
+        self.relative_take_profit = Some(relative_take_profit.into().raw());
+        self
+    }
+
+    pub fn set_guaranteed_stop_loss(&mut self, guaranteed_stop_loss: bool) -> &mut Self {
+        self.guaranteed_stop_loss = Some(guaranteed_stop_loss);
+        self
+    }
+
+    pub fn set_trailing_stop_loss(&mut self, trailing_stop_loss: bool) -> &mut Self {
+        self.trailing_stop_loss = Some(trailing_stop_loss);
+        self
+    }
+
+    pub fn set_stop_trigger_method(&mut self, method: ProtoOaOrderTriggerMethod) -> &mut Self {
+        self.stop_trigger_method = Some(method as i32);
+        self
+    }
+}
+
+/// Result of an idempotent order submission, see `Session::submit_order`.
+#[derive(Debug)]
+pub enum SubmitOutcome {
+    /// The server accepted the order sent by this call
+    Placed(Box<ProtoOaExecutionEvent>),
+    /// An order with the same client_order_id already reached the server
+    AlreadyExists(Box<ProtoOaOrderDetailsRes>),
+    /// Every attempt failed and the lookups after the last one found no order with the
+    /// client_order_id, it is safe to submit again
+    NotPlaced {
+        client_order_id: String,
+        error: Error,
+    },
+    /// Could not prove whether the order reached the server, do not resubmit blindly
+    Unknown {
+        client_order_id: String,
+        error: Error,
+    },
+}
+
+pub fn new_client_order_id() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+// errors after which the order may or may not have reached the server
+fn is_ambiguous_submit_error(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::TimeoutError(_) | Error::Disconnected | Error::Disconnect | Error::Io(_)
+    )
+}
+
 impl Session {
     //+------------------------------------------------------------------+
     //|                             Order                                |
@@ -50,7 +333,9 @@ impl Session {
 
     // Request for sending a new trading order.
     // Allowed only if the accessToken has the "trade" permissions for the trading account.
+    // A client_order_id is generated when the params carry none.
     pub async fn new_order(&self, params: NewOrderParams) -> Result<ProtoOaExecutionEvent, Error> {
+        self.check_trading_unlocked()?;
         let req = ProtoOaNewOrderReq {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
@@ -69,7 +354,9 @@ impl Session {
             slippage_in_points: params.slippage_in_points,
             label: params.label,
             position_id: params.position_id,
-            client_order_id: params.client_order_id,
+            client_order_id: params
+                .client_order_id
+                .or_else(|| Some(new_client_order_id())),
             relative_stop_loss: params.relative_stop_loss,
             relative_take_profit: params.relative_take_profit,
             guaranteed_stop_loss: params.guaranteed_stop_loss,
@@ -83,6 +370,107 @@ impl Session {
             .map(ProtoOaExecutionEvent::from)
     }
 
+    // Send a new order and resolve ambiguous failures (timeout, disconnect) without risking a double fill.
+    // After such a failure the order is searched by its client_order_id in the pending orders (reconcile)
+    // and in the recent order history; it is only resubmitted when both lookups prove it does not exist.
+    // NotPlaced is returned once max_attempts are used up and the last lookups found nothing.
+    pub async fn submit_order(
+        &self,
+        mut params: NewOrderParams,
+        max_attempts: u32,
+    ) -> Result<SubmitOutcome, Error> {
+        let client_order_id = params
+            .client_order_id
+            .get_or_insert_with(new_client_order_id)
+            .clone();
+        let submitted_at = Utc::now().timestamp_millis();
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let error = match self.new_order(params.clone()).await {
+                Ok(event) => return Ok(SubmitOutcome::Placed(Box::new(event))),
+                Err(e) if is_ambiguous_submit_error(&e) => e,
+                Err(e) => return Err(e),
+            };
+            warn!(
+                "submit_order {} attempt {} failed: {}, looking up order",
+                client_order_id, attempt, error
+            );
+
+            let found = self
+                .lookup_submitted_order(&client_order_id, submitted_at)
+                .await;
+            match found {
+                Ok(Some(order_id)) => {
+                    return match self.order_details(order_id).await {
+                        Ok(details) => Ok(SubmitOutcome::AlreadyExists(Box::new(details))),
+                        Err(error) => Ok(SubmitOutcome::Unknown {
+                            client_order_id,
+                            error,
+                        }),
+                    };
+                }
+                Ok(None) if attempt < max_attempts => continue,
+                Ok(None) => {
+                    return Ok(SubmitOutcome::NotPlaced {
+                        client_order_id,
+                        error,
+                    })
+                }
+                Err(error) => {
+                    return Ok(SubmitOutcome::Unknown {
+                        client_order_id,
+                        error,
+                    })
+                }
+            }
+        }
+    }
+
+    // Looks the order up SUBMIT_LOOKUP_ATTEMPTS times with a growing delay, an order still
+    // in flight can show up late. None only when every lookup came back empty.
+    async fn lookup_submitted_order(
+        &self,
+        client_order_id: &str,
+        submitted_at: i64,
+    ) -> Result<Option<i64>, Error> {
+        let mut delay = SUBMIT_SETTLE_DELAY;
+        for _ in 0..SUBMIT_LOOKUP_ATTEMPTS {
+            sleep(delay).await;
+            if let Some(order_id) = self
+                .find_order_by_client_order_id(client_order_id, submitted_at)
+                .await?
+            {
+                return Ok(Some(order_id));
+            }
+            delay *= 2;
+        }
+        Ok(None)
+    }
+
+    // Search the pending orders, then the order history since `since` (Unix time in milliseconds),
+    // for an order with the given client_order_id.
+    pub async fn find_order_by_client_order_id(
+        &self,
+        client_order_id: &str,
+        since: i64,
+    ) -> Result<Option<i64>, Error> {
+        let matches = |o: &&ProtoOaOrder| o.client_order_id.as_deref() == Some(client_order_id);
+
+        let res = self.get_open_position_and_pending_orders().await?;
+        if let Some(order) = res.order.iter().find(matches) {
+            return Ok(Some(order.order_id));
+        }
+
+        let from_timestamp = (since - SUBMIT_LOOKUP_MARGIN_MS).max(0);
+        let to_timestamp = Utc::now().timestamp_millis() + SUBMIT_LOOKUP_MARGIN_MS;
+        let orders = self
+            .get_historical_order_list_all(from_timestamp, to_timestamp)
+            .await?;
+        Ok(orders.iter().find(matches).map(|o| o.order_id))
+    }
+
     // Request for cancelling existing pending order.
     // Allowed only if the accessToken has "trade" permissions for the trading account.
     pub async fn cancel_order(&self, order_id: i64) -> Result<ProtoOaExecutionEvent, Error> {
@@ -105,6 +493,7 @@ impl Session {
         order_id: i64,
         params: ModifyOrderParams,
     ) -> Result<ProtoOaExecutionEvent, Error> {
+        self.check_trading_unlocked()?;
         let req = ProtoOaAmendOrderReq {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
@@ -134,13 +523,13 @@ impl Session {
     pub async fn expected_margin(
         &self,
         symbol_id: i64,
-        volume: Vec<i64>,
+        volume: Vec<impl Into<Volume>>,
     ) -> Result<ProtoOaExpectedMarginRes, Error> {
         let req = ProtoOaExpectedMarginReq {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
             symbol_id,
-            volume,
+            volume: volume.into_iter().map(|v| v.into().cents()).collect(),
         };
 
         self.connection
@@ -0,0 +1,294 @@
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+
+use futures::stream::{self, Stream};
+use tracing::warn;
+
+use super::historical::{check_time_range, MAX_HISTORY_RANGE_MS, MAX_HISTORY_TIMESTAMP};
+use super::Session;
+use crate::{protos::spotware_message::*, Error};
+
+/// A history record with a unique id and the timestamp the server filters on.
+pub trait HistoryRecord {
+    fn record_id(&self) -> i64;
+    fn record_timestamp(&self) -> i64;
+}
+
+impl HistoryRecord for ProtoOaOrder {
+    fn record_id(&self) -> i64 {
+        self.order_id
+    }
+
+    fn record_timestamp(&self) -> i64 {
+        self.utc_last_update_timestamp
+            .or(self.trade_data.open_timestamp)
+            .unwrap_or_default()
+    }
+}
+
+impl HistoryRecord for ProtoOaDeal {
+    fn record_id(&self) -> i64 {
+        self.deal_id
+    }
+
+    fn record_timestamp(&self) -> i64 {
+        self.execution_timestamp
+    }
+}
+
+impl HistoryRecord for ProtoOaDepositWithdraw {
+    fn record_id(&self) -> i64 {
+        self.balance_history_id
+    }
+
+    fn record_timestamp(&self) -> i64 {
+        self.change_balance_timestamp
+    }
+}
+
+// Splits a range into windows accepted by the server and moves the cursor
+// inside a window while the server reports has_more.
+#[derive(Debug)]
+pub(crate) struct Pager {
+    windows: VecDeque<(i64, i64)>,
+    seen: HashSet<i64>,
+}
+
+impl Pager {
+    pub(crate) fn new(
+        from_timestamp: i64,
+        to_timestamp: i64,
+        max_range: i64,
+    ) -> Result<Self, Error> {
+        check_time_range(from_timestamp, to_timestamp, MAX_HISTORY_TIMESTAMP)?;
+        Ok(Self {
+            windows: split_range(from_timestamp, to_timestamp, max_range),
+            seen: HashSet::new(),
+        })
+    }
+
+    // range of the next request, None when done
+    pub(crate) fn next_range(&self) -> Option<(i64, i64)> {
+        self.windows.front().copied()
+    }
+
+    // Takes the records of the last requested range, returns the ones not seen before.
+    pub(crate) fn on_page<T: HistoryRecord>(&mut self, records: Vec<T>, has_more: bool) -> Vec<T> {
+        let (from, to) = match self.windows.front_mut() {
+            Some(window) => window,
+            None => return Vec::new(),
+        };
+
+        if has_more {
+            // continue after the latest record, records on that millisecond are deduped
+            let latest = records.iter().map(|r| r.record_timestamp()).max();
+            match latest {
+                Some(latest) if latest > *from && latest <= *to => *from = latest,
+                _ => {
+                    warn!("history page without progress at {}, skipping 1ms", from);
+                    *from += 1;
+                }
+            }
+            if *from > *to {
+                self.windows.pop_front();
+            }
+        } else {
+            self.windows.pop_front();
+        }
+
+        records
+            .into_iter()
+            .filter(|r| self.seen.insert(r.record_id()))
+            .collect()
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.windows.clear();
+    }
+}
+
+// windows of at most max_range, neighbours share the edge millisecond
+pub(crate) fn split_range(
+    from_timestamp: i64,
+    to_timestamp: i64,
+    max_range: i64,
+) -> VecDeque<(i64, i64)> {
+    let mut windows = VecDeque::new();
+    let mut from = from_timestamp;
+    loop {
+        let to = (from + max_range).min(to_timestamp);
+        windows.push_back((from, to));
+        if to >= to_timestamp {
+            break;
+        }
+        from = to;
+    }
+    windows
+}
+
+async fn paginate<T, F, Fut>(mut pager: Pager, mut fetch: F) -> Result<Vec<T>, Error>
+where
+    T: HistoryRecord,
+    F: FnMut(i64, i64) -> Fut,
+    Fut: Future<Output = Result<(Vec<T>, bool), Error>>,
+{
+    let mut result = Vec::new();
+    while let Some((from, to)) = pager.next_range() {
+        let (records, has_more) = fetch(from, to).await?;
+        result.extend(pager.on_page(records, has_more));
+    }
+    result.sort_by_key(|r| (r.record_timestamp(), r.record_id()));
+    Ok(result)
+}
+
+fn paginate_stream<'a, T, F, Fut>(
+    pager: Pager,
+    fetch: F,
+) -> impl Stream<Item = Result<T, Error>> + 'a
+where
+    T: HistoryRecord + 'a,
+    F: FnMut(i64, i64) -> Fut + 'a,
+    Fut: Future<Output = Result<(Vec<T>, bool), Error>> + 'a,
+{
+    stream::unfold(
+        (pager, fetch, VecDeque::new()),
+        |(mut pager, mut fetch, mut buffer)| async move {
+            loop {
+                if let Some(record) = buffer.pop_front() {
+                    return Some((Ok(record), (pager, fetch, buffer)));
+                }
+                let (from, to) = pager.next_range()?;
+                match fetch(from, to).await {
+                    Ok((records, has_more)) => buffer.extend(pager.on_page(records, has_more)),
+                    Err(e) => {
+                        pager.finish();
+                        return Some((Err(e), (pager, fetch, buffer)));
+                    }
+                }
+            }
+        },
+    )
+}
+
+impl Session {
+    //+------------------------------------------------------------------+
+    //|                           Pagination                             |
+    //+------------------------------------------------------------------+
+
+    // The *_all variants accept any range: it is split into one week windows, has_more is
+    // followed inside each window, and records repeated across pages are dropped.
+    // Every request goes through the historical lane, so paging is throttled to 5/s.
+
+    // Closed orders of any range, sorted by last update time.
+    pub async fn get_historical_order_list_all(
+        &self,
+        from_timestamp: i64,
+        to_timestamp: i64,
+    ) -> Result<Vec<ProtoOaOrder>, Error> {
+        let pager = Pager::new(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        paginate(pager, |from, to| async move {
+            let res = self.get_historical_order_list(from, to).await?;
+            Ok((res.order, res.has_more))
+        })
+        .await
+    }
+
+    // Deals of any range, sorted by execution time.
+    pub async fn get_historical_deal_list_all(
+        &self,
+        from_timestamp: i64,
+        to_timestamp: i64,
+    ) -> Result<Vec<ProtoOaDeal>, Error> {
+        let pager = Pager::new(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        paginate(pager, |from, to| async move {
+            let res = self.get_historical_deal_list(from, to, None).await?;
+            Ok((res.deal, res.has_more))
+        })
+        .await
+    }
+
+    // Deposits and withdrawals of any range, sorted by time.
+    pub async fn get_historical_cash_flow_list_all(
+        &self,
+        from_timestamp: i64,
+        to_timestamp: i64,
+    ) -> Result<Vec<ProtoOaDepositWithdraw>, Error> {
+        let pager = Pager::new(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        paginate(pager, |from, to| async move {
+            let res = self.get_historical_cash_flow_list(from, to).await?;
+            Ok((res.deposit_withdraw, false))
+        })
+        .await
+    }
+
+    // Deals of a position over any range, sorted by execution time.
+    pub async fn deal_list_position_id_all(
+        &self,
+        position_id: i64,
+        from_timestamp: i64,
+        to_timestamp: i64,
+    ) -> Result<Vec<ProtoOaDeal>, Error> {
+        let pager = Pager::new(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        paginate(pager, |from, to| async move {
+            let res = self.deal_list_position_id(position_id, from, to).await?;
+            Ok((res.deal, res.has_more))
+        })
+        .await
+    }
+
+    // Orders of a position over any range, sorted by last update time.
+    pub async fn order_list_by_position_id_all(
+        &self,
+        position_id: i64,
+        from_timestamp: i64,
+        to_timestamp: i64,
+    ) -> Result<Vec<ProtoOaOrder>, Error> {
+        let pager = Pager::new(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        paginate(pager, |from, to| async move {
+            let res = self
+                .order_list_by_position_id(position_id, from, to)
+                .await?;
+            Ok((res.order, res.has_more))
+        })
+        .await
+    }
+
+    // The *_stream variants yield records page by page as they arrive, in server order.
+    // The stream ends after the first error.
+
+    pub fn historical_order_stream(
+        &self,
+        from_timestamp: i64,
+        to_timestamp: i64,
+    ) -> Result<impl Stream<Item = Result<ProtoOaOrder, Error>> + '_, Error> {
+        let pager = Pager::new(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        Ok(paginate_stream(pager, move |from, to| async move {
+            let res = self.get_historical_order_list(from, to).await?;
+            Ok((res.order, res.has_more))
+        }))
+    }
+
+    pub fn historical_deal_stream(
+        &self,
+        from_timestamp: i64,
+        to_timestamp: i64,
+    ) -> Result<impl Stream<Item = Result<ProtoOaDeal, Error>> + '_, Error> {
+        let pager = Pager::new(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        Ok(paginate_stream(pager, move |from, to| async move {
+            let res = self.get_historical_deal_list(from, to, None).await?;
+            Ok((res.deal, res.has_more))
+        }))
+    }
+
+    pub fn historical_cash_flow_stream(
+        &self,
+        from_timestamp: i64,
+        to_timestamp: i64,
+    ) -> Result<impl Stream<Item = Result<ProtoOaDepositWithdraw, Error>> + '_, Error> {
+        let pager = Pager::new(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
+        Ok(paginate_stream(pager, move |from, to| async move {
+            let res = self.get_historical_cash_flow_list(from, to).await?;
+            Ok((res.deposit_withdraw, false))
+        }))
+    }
+}
@@ -1,5 +1,10 @@
+use super::historical::{check_time_range, MAX_HISTORY_RANGE_MS};
 use super::Session;
-use crate::{protos::spotware_message::*, Error};
+use crate::{
+    protos::spotware_message::*,
+    util::units::{Price, Volume},
+    Error,
+};
 
 impl Session {
     // Request for amending StopLoss and TakeProfit of existing position.
@@ -7,8 +12,8 @@ impl Session {
     pub async fn modify_position_sltp(
         &self,
         position_id: i64,
-        stop_loss: Option<f64>,
-        take_profit: Option<f64>,
+        stop_loss: Option<Price>,
+        take_profit: Option<Price>,
         guaranteed_stop_loss: Option<bool>,
         trailing_stop_loss: Option<bool>,
         stop_loss_trigger_method: Option<i32>,
@@ -17,8 +22,8 @@ impl Session {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
             position_id,
-            stop_loss,
-            take_profit,
+            stop_loss: stop_loss.map(Price::to_f64),
+            take_profit: take_profit.map(Price::to_f64),
             guaranteed_stop_loss,
             trailing_stop_loss,
             stop_loss_trigger_method,
@@ -34,13 +39,13 @@ impl Session {
     pub async fn close_position(
         &self,
         position_id: i64,
-        volume: i64,
+        volume: impl Into<Volume>,
     ) -> Result<ProtoOaExecutionEvent, Error> {
         let req = ProtoOaClosePositionReq {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
             position_id,
-            volume,
+            volume: volume.into().cents(),
         };
         self.connection
             .send_request(req.into())
@@ -62,7 +67,7 @@ impl Session {
             to_timestamp,
         };
         self.connection
-            .send_request(req.into())
+            .send_historical_request(req.into())
             .await
             .map(ProtoOaOrderListByPositionIdRes::from)
     }
@@ -74,9 +79,7 @@ impl Session {
         from_timestamp: i64,
         to_timestamp: i64,
     ) -> Result<ProtoOaDealListByPositionIdRes, Error> {
-        assert!(from_timestamp >= 0);
-        assert!(to_timestamp < 2147483646000);
-        assert!(to_timestamp - from_timestamp <= 604800000); // 1 week
+        check_time_range(from_timestamp, to_timestamp, MAX_HISTORY_RANGE_MS)?;
         let req = ProtoOaDealListByPositionIdReq {
             payload_type: None,
             ctid_trader_account_id: self.account.account_id,
@@ -86,7 +89,7 @@ impl Session {
         };
 
         self.connection
-            .send_request(req.into())
+            .send_historical_request(req.into())
             .await
             .map(ProtoOaDealListByPositionIdRes::from)
     }
@@ -1,4 +1,11 @@
+use chrono::Utc;
+use tokio::sync::oneshot::{self, error::TryRecvError};
+use tracing::{info, warn};
+
 use super::Session;
+use crate::io::Requester;
+use crate::util::symbol_info::fetch_symbol_store;
+use crate::util::{SymbolLoading, SymbolStore, SymbolUpdate};
 use crate::{protos::spotware_message::*, Error};
 
 impl Session {
@@ -9,67 +16,232 @@ impl Session {
     // Request for a list of symbols available for a trading account.
     // Symbol entries are returned with the limited set of fields.
     pub async fn symbol_list(&self) -> Result<ProtoOaSymbolsListRes, Error> {
+        self.requester()?
+            .symbol_list(self.account.account_id, None)
+            .await
+    }
+
+    // Request for the list of assets available for a trader's account.
+    pub async fn asset_list(&self) -> Result<ProtoOaAssetListRes, Error> {
+        self.requester()?.asset_list(self.account.account_id).await
+    }
+
+    // Request for getting a full symbol entity.
+    pub async fn symbol_by_id(&self, symbol_ids: Vec<i64>) -> Result<ProtoOaSymbolByIdRes, Error> {
+        self.requester()?
+            .symbol_by_id(self.account.account_id, symbol_ids)
+            .await
+    }
+
+    // Request for a list of asset classes available for the trader's account.
+    pub async fn asset_class_list(&self) -> Result<ProtoOaAssetClassListRes, Error> {
+        self.requester()?
+            .asset_class_list(self.account.account_id)
+            .await
+    }
+
+    // Request for a list of symbol categories available for a trading account.
+    pub async fn symbol_category_list(&self) -> Result<ProtoOaSymbolCategoryListRes, Error> {
+        self.requester()?
+            .symbol_category_list(self.account.account_id)
+            .await
+    }
+
+    // Err(MarketClosed) when the symbol's trading schedule or a holiday closes it now.
+    // Call before sending market orders, the server rejects them while closed.
+    pub fn check_market_open(&self, symbol_id: i64) -> Result<(), Error> {
+        let calendar = self.store.get_calendar_by_id(symbol_id)?;
+        if !calendar.is_open(Utc::now()) {
+            return Err(Error::MarketClosed(symbol_id));
+        }
+        Ok(())
+    }
+
+    //+------------------------------------------------------------------+
+    //|                          Symbol Store                            |
+    //+------------------------------------------------------------------+
+
+    pub(crate) async fn load_symbols_on_connect(&mut self) -> Result<(), Error> {
+        match self.symbol_loading.clone() {
+            SymbolLoading::Eager => self.load_symbols().await,
+            SymbolLoading::Cached { path, max_age } => match SymbolStore::load(&path) {
+                // max_age only decides whether connect waits for the fetch
+                Ok(cached) if cached.server_version == self.version && cached.age() <= max_age => {
+                    self.store = cached.store;
+                    self.spawn_symbol_refresh()
+                }
+                Ok(cached) if cached.server_version == self.version => {
+                    info!(
+                        "symbol cache {} is older than {:?}",
+                        path.display(),
+                        max_age
+                    );
+                    self.load_symbols().await
+                }
+                Ok(cached) => {
+                    info!(
+                        "symbol cache is for server version {}, server is {}",
+                        cached.server_version, self.version
+                    );
+                    self.load_symbols().await
+                }
+                Err(e) => {
+                    info!("symbol cache {} not used: {}", path.display(), e);
+                    self.load_symbols().await
+                }
+            },
+            SymbolLoading::Deferred => self.spawn_symbol_refresh(),
+            SymbolLoading::Skip => Ok(()),
+        }
+    }
+
+    // Fetches every symbol and replaces the store, and the cache file when one is used.
+    pub async fn load_symbols(&mut self) -> Result<(), Error> {
+        self.store = fetch_symbol_store(
+            &self.requester()?,
+            self.account.account_id,
+            self.include_archived_symbols,
+        )
+        .await?;
+        self.save_symbol_cache();
+        Ok(())
+    }
+
+    // Fetches every symbol in a background task, the store keeps serving the old ones
+    // until poll_symbol_refresh applies the result.
+    pub fn spawn_symbol_refresh(&mut self) -> Result<(), Error> {
+        let requester = self.requester()?;
+        let account_id = self.account.account_id;
+        let include_archived = self.include_archived_symbols;
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = tx.send(fetch_symbol_store(&requester, account_id, include_archived).await);
+        });
+        self.symbol_refresh = Some(rx);
+        Ok(())
+    }
+
+    // Swaps in the result of a finished background refresh, returns the changes of the
+    // symbols that were already known. dispatch_event calls it on every event, the changes
+    // come out of it as NotifyEvent::SymbolUpdated.
+    pub fn poll_symbol_refresh(&mut self) -> Option<Vec<SymbolUpdate>> {
+        let result = match self.symbol_refresh.as_mut()?.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Closed) => {
+                self.symbol_refresh = None;
+                return None;
+            }
+        };
+        self.symbol_refresh = None;
+        self.apply_symbol_refresh(result)
+    }
+
+    // Waits for the background refresh and applies it, e.g. after connecting with
+    // SymbolLoading::Deferred without subscriptions. None when no refresh runs or it failed.
+    pub async fn wait_symbol_refresh(&mut self) -> Option<Vec<SymbolUpdate>> {
+        let result = self.symbol_refresh.take()?.await.ok()?;
+        self.apply_symbol_refresh(result)
+    }
+
+    fn apply_symbol_refresh(
+        &mut self,
+        result: Result<SymbolStore, Error>,
+    ) -> Option<Vec<SymbolUpdate>> {
+        match result {
+            Ok(store) => {
+                let symbols = store.infos().into_iter().map(|i| i.info.clone()).collect();
+                let updates = self.store.clone().apply_symbols(symbols);
+                self.store = store;
+                self.save_symbol_cache();
+                Some(updates)
+            }
+            Err(e) => {
+                warn!("symbol refresh failed: {}", e);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn save_symbol_cache(&self) {
+        if let SymbolLoading::Cached { path, .. } = &self.symbol_loading {
+            if let Err(e) = self.store.save(path, self.version) {
+                warn!("save symbol cache {} failed: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+// The symbol requests without the session, for background refreshes. The Session methods
+// above go through them too.
+impl Requester {
+    pub(crate) async fn symbol_list(
+        &self,
+        account_id: i64,
+        include_archived_symbols: Option<bool>,
+    ) -> Result<ProtoOaSymbolsListRes, Error> {
         let req = ProtoOaSymbolsListReq {
             payload_type: None,
-            ctid_trader_account_id: self.account.account_id,
-            include_archived_symbols: None,
+            ctid_trader_account_id: account_id,
+            include_archived_symbols,
         };
 
-        self.connection
-            .send_request(req.into())
+        self.send_request(req.into())
             .await
             .map(ProtoOaSymbolsListRes::from)
     }
 
-    // Request for the list of assets available for a trader's account.
-    pub async fn asset_list(&self) -> Result<ProtoOaAssetListRes, Error> {
+    pub(crate) async fn asset_list(&self, account_id: i64) -> Result<ProtoOaAssetListRes, Error> {
         let req = ProtoOaAssetListReq {
             payload_type: None,
-            ctid_trader_account_id: self.account.account_id,
+            ctid_trader_account_id: account_id,
         };
 
-        self.connection
-            .send_request(req.into())
+        self.send_request(req.into())
             .await
             .map(ProtoOaAssetListRes::from)
     }
 
-    // Request for getting a full symbol entity.
-    pub async fn symbol_by_id(&self, symbol_ids: Vec<i64>) -> Result<ProtoOaSymbolByIdRes, Error> {
+    pub(crate) async fn symbol_by_id(
+        &self,
+        account_id: i64,
+        symbol_ids: Vec<i64>,
+    ) -> Result<ProtoOaSymbolByIdRes, Error> {
         let req = ProtoOaSymbolByIdReq {
             payload_type: None,
-            ctid_trader_account_id: self.account.account_id,
+            ctid_trader_account_id: account_id,
             symbol_id: symbol_ids,
         };
 
-        self.connection
-            .send_request(req.into())
+        self.send_request(req.into())
             .await
             .map(ProtoOaSymbolByIdRes::from)
     }
 
-    // Request for a list of asset classes available for the trader's account.
-    pub async fn asset_class_list(&self) -> Result<ProtoOaAssetClassListRes, Error> {
+    pub(crate) async fn asset_class_list(
+        &self,
+        account_id: i64,
+    ) -> Result<ProtoOaAssetClassListRes, Error> {
         let req = ProtoOaAssetClassListReq {
             payload_type: None,
-            ctid_trader_account_id: self.account.account_id,
+            ctid_trader_account_id: account_id,
         };
 
-        self.connection
-            .send_request(req.into())
+        self.send_request(req.into())
             .await
             .map(ProtoOaAssetClassListRes::from)
     }
 
-    // Request for a list of symbol categories available for a trading account.
-    pub async fn symbol_category_list(&self) -> Result<ProtoOaSymbolCategoryListRes, Error> {
+    pub(crate) async fn symbol_category_list(
+        &self,
+        account_id: i64,
+    ) -> Result<ProtoOaSymbolCategoryListRes, Error> {
         let req = ProtoOaSymbolCategoryListReq {
             payload_type: None,
-            ctid_trader_account_id: self.account.account_id,
+            ctid_trader_account_id: account_id,
         };
 
-        self.connection
-            .send_request(req.into())
+        self.send_request(req.into())
             .await
             .map(ProtoOaSymbolCategoryListRes::from)
     }
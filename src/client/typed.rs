@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+
+use super::Session;
+use crate::domain::{Account, Bar, Deal, Order, Position, Symbol};
+use crate::Error;
+
+impl Session {
+    //+------------------------------------------------------------------+
+    //|                              Typed                               |
+    //+------------------------------------------------------------------+
+
+    // Variants of the account, history and market data requests returning domain types.
+
+    // Trader's account details.
+    pub async fn account_info(&self) -> Result<Account, Error> {
+        let res = self.get_account_data().await?;
+        Ok(Account::from(&res.trader))
+    }
+
+    // Currently open positions.
+    pub async fn open_positions(&self) -> Result<Vec<Position>, Error> {
+        let res = self.get_open_position_and_pending_orders().await?;
+        Ok(res.position.iter().map(Position::from).collect())
+    }
+
+    // Currently pending orders.
+    pub async fn pending_orders(&self) -> Result<Vec<Order>, Error> {
+        let res = self.get_open_position_and_pending_orders().await?;
+        Ok(res.order.iter().map(Order::from).collect())
+    }
+
+    // Closed orders of any range, sorted by last update time.
+    pub async fn order_history(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Order>, Error> {
+        let orders = self
+            .get_historical_order_list_all(from.timestamp_millis(), to.timestamp_millis())
+            .await?;
+        Ok(orders.iter().map(Order::from).collect())
+    }
+
+    // Deals of any range, sorted by execution time.
+    pub async fn deal_history(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Deal>, Error> {
+        let deals = self
+            .get_historical_deal_list_all(from.timestamp_millis(), to.timestamp_millis())
+            .await?;
+        Ok(deals.iter().map(Deal::from).collect())
+    }
+
+    // Trend bars of a single request window, sorted by open time.
+    pub async fn bars(
+        &self,
+        symbol_id: i64,
+        period: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Bar>, Error> {
+        let res = self
+            .get_trend_bars(
+                from.timestamp_millis(),
+                to.timestamp_millis(),
+                period,
+                symbol_id,
+                None,
+            )
+            .await?;
+        let mut bars: Vec<Bar> = res.trendbar.iter().map(Bar::from).collect();
+        bars.sort_by_key(|bar| bar.time);
+        Ok(bars)
+    }
+
+    // Symbols loaded on connect, sorted by id.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = self
+            .store
+            .get_ids()
+            .into_iter()
+            .filter_map(|id| self.store.get_info_by_id(id))
+            .map(Symbol::from)
+            .collect();
+        symbols.sort_by_key(|symbol| symbol.symbol_id);
+        symbols
+    }
+
+    pub fn symbol(&self, symbol_name: &str) -> Option<Symbol> {
+        self.store.get_info_by_name(symbol_name).map(Symbol::from)
+    }
+}
@@ -2,6 +2,7 @@ use tracing::error;
 
 use crate::protos::spotware_message::ProtoOaSpotEvent;
 use crate::util::time_util;
+use crate::util::units::Price;
 
 #[derive(Debug, Clone)]
 /// Struct used for aggregating trades by time in an online (streaming) manner
@@ -77,11 +78,11 @@ impl ContinueBar {
         // update ask/bid
         let ask = event
             .ask
-            .map(|x| (x as f64) / 100_000.0)
+            .map(|x| Price::from_raw(x as i64).to_f64())
             .unwrap_or(self.last_ask);
         let bid = event
             .bid
-            .map(|x| (x as f64) / 100_000.0)
+            .map(|x| Price::from_raw(x as i64).to_f64())
             .unwrap_or(self.last_bid);
 
         if event.ask.is_some() {
@@ -114,9 +115,9 @@ impl ContinueBar {
             let _low = event.trendbar[0].low.unwrap();
             let _open = event.trendbar[0].delta_open.unwrap_or(0) as i64 + _low;
             let _high = event.trendbar[0].delta_high.unwrap_or(0) as i64 + _low;
-            let low = _low as f64 / 100_000.0;
-            let open = _open as f64 / 100_000.0;
-            let high = _high as f64 / 100_000.0;
+            let low = Price::from_raw(_low).to_f64();
+            let open = Price::from_raw(_open).to_f64();
+            let high = Price::from_raw(_high).to_f64();
             let vol = event.trendbar[0].volume as u64;
 
             self.open = open;
@@ -0,0 +1,99 @@
+use chrono::{DateTime, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::DEFAULT_MONEY_DIGITS;
+use crate::protos::spotware_message::{
+    ProtoOaAccountType, ProtoOaTotalMarginCalculationType, ProtoOaTrader,
+};
+use crate::util::time_util::from_opt_mill_seconds;
+use crate::util::units::Money;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AccountType {
+    Hedged,
+    Netted,
+    SpreadBetting,
+}
+
+impl From<ProtoOaAccountType> for AccountType {
+    fn from(value: ProtoOaAccountType) -> Self {
+        match value {
+            ProtoOaAccountType::Hedged => AccountType::Hedged,
+            ProtoOaAccountType::Netted => AccountType::Netted,
+            ProtoOaAccountType::SpreadBetting => AccountType::SpreadBetting,
+        }
+    }
+}
+
+/// How the margin of opposite positions on one symbol adds up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MarginMode {
+    /// The larger of the buy and the sell side
+    #[default]
+    Max,
+    /// Both sides
+    Sum,
+    /// The net volume
+    Net,
+}
+
+impl From<ProtoOaTotalMarginCalculationType> for MarginMode {
+    fn from(value: ProtoOaTotalMarginCalculationType) -> Self {
+        match value {
+            ProtoOaTotalMarginCalculationType::Max => MarginMode::Max,
+            ProtoOaTotalMarginCalculationType::Sum => MarginMode::Sum,
+            ProtoOaTotalMarginCalculationType::Net => MarginMode::Net,
+        }
+    }
+}
+
+/// A trading account, from `ProtoOaTrader`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Account {
+    pub account_id: i64,
+    pub login: Option<i64>,
+    pub account_type: AccountType,
+    pub broker_name: Option<String>,
+    pub deposit_asset_id: i64,
+    pub balance: Money,
+    pub manager_bonus: Option<Money>,
+    pub ib_bonus: Option<Money>,
+    pub non_withdrawable_bonus: Option<Money>,
+    /// e.g. 100.0 for 1:100
+    pub leverage: Option<f64>,
+    pub max_leverage: Option<f64>,
+    pub margin_mode: MarginMode,
+    pub swap_free: bool,
+    pub is_limited_risk: bool,
+    pub registration_time: Option<DateTime<Utc>>,
+    pub money_digits: u32,
+}
+
+impl From<&ProtoOaTrader> for Account {
+    fn from(trader: &ProtoOaTrader) -> Self {
+        let digits = trader.money_digits.unwrap_or(DEFAULT_MONEY_DIGITS);
+        let money = |v: i64| Money::new(v, digits);
+        Self {
+            account_id: trader.ctid_trader_account_id,
+            login: trader.trader_login,
+            account_type: trader.account_type().into(),
+            broker_name: trader.broker_name.clone(),
+            deposit_asset_id: trader.deposit_asset_id,
+            balance: money(trader.balance),
+            manager_bonus: trader.manager_bonus.map(money),
+            ib_bonus: trader.ib_bonus.map(money),
+            non_withdrawable_bonus: trader.non_withdrawable_bonus.map(money),
+            leverage: trader.leverage_in_cents.map(|l| l as f64 / 100.0),
+            max_leverage: trader.max_leverage.map(|l| l as f64 / 100.0),
+            margin_mode: trader.total_margin_calculation_type().into(),
+            swap_free: trader.swap_free.unwrap_or(false),
+            is_limited_risk: trader.is_limited_risk.unwrap_or(false),
+            registration_time: from_opt_mill_seconds(trader.registration_timestamp),
+            money_digits: digits,
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::protos::spotware_message::{ProtoOaSpotEvent, ProtoOaTrendbar};
+use crate::util::{
+    bar_gen::{Candle, Quote},
+    download::Kline,
+    symbol_info::SpotwareSymbolInfo,
+    time_util::{from_mill_seconds, from_opt_mill_seconds},
+    units::{Price, SymbolUnits, Volume},
+};
+
+/// An OHLC bar. Converts from trend bars, `Kline` and `Candle` alike.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Bar {
+    /// Open time
+    pub time: DateTime<Utc>,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    pub volume: u64,
+}
+
+impl From<&ProtoOaTrendbar> for Bar {
+    fn from(bar: &ProtoOaTrendbar) -> Self {
+        let low = bar.low.unwrap_or_default();
+        Self {
+            time: from_mill_seconds(
+                bar.utc_timestamp_in_minutes.unwrap_or_default() as i64 * 60_000,
+            ),
+            open: Price::from_raw(low + bar.delta_open.unwrap_or(0) as i64),
+            high: Price::from_raw(low + bar.delta_high.unwrap_or(0) as i64),
+            low: Price::from_raw(low),
+            close: Price::from_raw(low + bar.delta_close.unwrap_or(0) as i64),
+            volume: bar.volume as u64,
+        }
+    }
+}
+
+impl From<&Kline> for Bar {
+    fn from(kline: &Kline) -> Self {
+        Self {
+            time: kline.timestamp,
+            open: Price::from_f64(kline.open),
+            high: Price::from_f64(kline.high),
+            low: Price::from_f64(kline.low),
+            close: Price::from_f64(kline.close),
+            volume: kline.vol,
+        }
+    }
+}
+
+impl From<&Candle> for Bar {
+    fn from(candle: &Candle) -> Self {
+        Self {
+            time: from_mill_seconds(candle.timestamp),
+            open: Price::from_f64(candle.open),
+            high: Price::from_f64(candle.high),
+            low: Price::from_f64(candle.low),
+            close: Price::from_f64(candle.close),
+            volume: candle.vol,
+        }
+    }
+}
+
+impl From<&Bar> for Kline {
+    fn from(bar: &Bar) -> Self {
+        Self {
+            timestamp: bar.time,
+            open: bar.open.to_f64(),
+            high: bar.high.to_f64(),
+            low: bar.low.to_f64(),
+            close: bar.close.to_f64(),
+            vol: bar.volume,
+        }
+    }
+}
+
+/// A bid/ask update, either side may be missing on a spot event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Tick {
+    pub time: DateTime<Utc>,
+    pub bid: Option<Price>,
+    pub ask: Option<Price>,
+}
+
+impl Tick {
+    pub fn spread(&self) -> Option<Price> {
+        Some(self.ask? - self.bid?)
+    }
+
+    pub fn mid(&self) -> Option<Price> {
+        Some(Price::from_raw((self.ask?.raw() + self.bid?.raw()) / 2))
+    }
+}
+
+impl From<&ProtoOaSpotEvent> for Tick {
+    fn from(spot: &ProtoOaSpotEvent) -> Self {
+        Self {
+            time: from_opt_mill_seconds(spot.timestamp).unwrap_or_else(Utc::now),
+            bid: spot.bid.map(|p| Price::from_raw(p as i64)),
+            ask: spot.ask.map(|p| Price::from_raw(p as i64)),
+        }
+    }
+}
+
+impl From<&Quote> for Tick {
+    fn from(quote: &Quote) -> Self {
+        Self {
+            time: from_mill_seconds(quote.timestamp),
+            bid: Some(Price::from_f64(quote.bid)),
+            ask: Some(Price::from_f64(quote.ask)),
+        }
+    }
+}
+
+/// Trading details of a symbol, from the symbol store.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Symbol {
+    pub symbol_id: i64,
+    pub name: String,
+    pub asset_class: String,
+    pub category: String,
+    pub base_asset_id: i64,
+    pub quote_asset_id: i64,
+    pub digits: i32,
+    pub pip_position: i32,
+    pub lot_size: Volume,
+    pub min_volume: Option<Volume>,
+    pub max_volume: Option<Volume>,
+    pub step_volume: Option<Volume>,
+    pub base_decimals: u8,
+    pub quote_decimals: u8,
+    pub swap_long: Option<f64>,
+    pub swap_short: Option<f64>,
+    pub enable_short_selling: bool,
+    pub guaranteed_stop_loss: bool,
+}
+
+impl Symbol {
+    pub fn units(&self) -> SymbolUnits {
+        SymbolUnits {
+            digits: self.digits,
+            pip_position: self.pip_position,
+            lot_size: self.lot_size.cents(),
+            step_volume: self.step_volume.map(Volume::cents).unwrap_or(1),
+        }
+    }
+}
+
+impl From<&SpotwareSymbolInfo> for Symbol {
+    fn from(info: &SpotwareSymbolInfo) -> Self {
+        let symbol = &info.info;
+        let units = SymbolUnits::from(symbol);
+        Self {
+            symbol_id: symbol.symbol_id,
+            name: info.name.clone(),
+            asset_class: info.asset_class.clone(),
+            category: info.category.clone(),
+            base_asset_id: info.base_asset_id,
+            quote_asset_id: info.quote_asset_id,
+            digits: symbol.digits,
+            pip_position: symbol.pip_position,
+            lot_size: Volume::from_cents(units.lot_size),
+            min_volume: symbol.min_volume.map(Volume::from_cents),
+            max_volume: symbol.max_volume.map(Volume::from_cents),
+            step_volume: symbol.step_volume.map(Volume::from_cents),
+            base_decimals: info.base_decimals,
+            quote_decimals: info.quote_decimals,
+            swap_long: symbol.swap_long,
+            swap_short: symbol.swap_short,
+            enable_short_selling: symbol.enable_short_selling.unwrap_or(true),
+            guaranteed_stop_loss: symbol.guaranteed_stop_loss.unwrap_or(false),
+        }
+    }
+}
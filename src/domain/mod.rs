@@ -0,0 +1,18 @@
+//! Typed view of the account, trading and market data protos.
+//!
+//! Timestamps are `chrono::DateTime<Utc>`, prices are `Price`, volumes `Volume` and
+//! money amounts `Money` scaled by the money digits of the message. Every type converts
+//! from its proto with `From`, serde support is enabled with the `serde` feature.
+mod account;
+mod market;
+mod trading;
+
+pub use account::{Account, AccountType, MarginMode};
+pub use market::{Bar, Symbol, Tick};
+pub use trading::{
+    ClosedDeal, Deal, DealStatus, Order, OrderStatus, OrderType, Position, PositionStatus, Side,
+    TimeInForce,
+};
+
+// money digits when a message does not carry them
+pub(crate) const DEFAULT_MONEY_DIGITS: u32 = 2;
@@ -0,0 +1,330 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::DEFAULT_MONEY_DIGITS;
+use crate::protos::spotware_message::*;
+use crate::util::time_util::{from_mill_seconds, from_opt_mill_seconds};
+use crate::util::units::{Money, Price, Volume};
+
+// always serializable, order groups persist it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+impl From<ProtoOaTradeSide> for Side {
+    fn from(value: ProtoOaTradeSide) -> Self {
+        match value {
+            ProtoOaTradeSide::Buy => Side::Buy,
+            ProtoOaTradeSide::Sell => Side::Sell,
+        }
+    }
+}
+
+impl From<Side> for ProtoOaTradeSide {
+    fn from(value: Side) -> Self {
+        match value {
+            Side::Buy => ProtoOaTradeSide::Buy,
+            Side::Sell => ProtoOaTradeSide::Sell,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PositionStatus {
+    Open,
+    Closed,
+    Created,
+    Error,
+}
+
+impl From<ProtoOaPositionStatus> for PositionStatus {
+    fn from(value: ProtoOaPositionStatus) -> Self {
+        match value {
+            ProtoOaPositionStatus::PositionStatusOpen => PositionStatus::Open,
+            ProtoOaPositionStatus::PositionStatusClosed => PositionStatus::Closed,
+            ProtoOaPositionStatus::PositionStatusCreated => PositionStatus::Created,
+            ProtoOaPositionStatus::PositionStatusError => PositionStatus::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OrderType {
+    Market,
+    Limit,
+    Stop,
+    StopLossTakeProfit,
+    MarketRange,
+    StopLimit,
+}
+
+impl From<ProtoOaOrderType> for OrderType {
+    fn from(value: ProtoOaOrderType) -> Self {
+        match value {
+            ProtoOaOrderType::Market => OrderType::Market,
+            ProtoOaOrderType::Limit => OrderType::Limit,
+            ProtoOaOrderType::Stop => OrderType::Stop,
+            ProtoOaOrderType::StopLossTakeProfit => OrderType::StopLossTakeProfit,
+            ProtoOaOrderType::MarketRange => OrderType::MarketRange,
+            ProtoOaOrderType::StopLimit => OrderType::StopLimit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OrderStatus {
+    Accepted,
+    Filled,
+    Rejected,
+    Expired,
+    Cancelled,
+}
+
+impl From<ProtoOaOrderStatus> for OrderStatus {
+    fn from(value: ProtoOaOrderStatus) -> Self {
+        match value {
+            ProtoOaOrderStatus::OrderStatusAccepted => OrderStatus::Accepted,
+            ProtoOaOrderStatus::OrderStatusFilled => OrderStatus::Filled,
+            ProtoOaOrderStatus::OrderStatusRejected => OrderStatus::Rejected,
+            ProtoOaOrderStatus::OrderStatusExpired => OrderStatus::Expired,
+            ProtoOaOrderStatus::OrderStatusCancelled => OrderStatus::Cancelled,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TimeInForce {
+    GoodTillDate,
+    GoodTillCancel,
+    ImmediateOrCancel,
+    FillOrKill,
+    MarketOnOpen,
+}
+
+impl From<ProtoOaTimeInForce> for TimeInForce {
+    fn from(value: ProtoOaTimeInForce) -> Self {
+        match value {
+            ProtoOaTimeInForce::GoodTillDate => TimeInForce::GoodTillDate,
+            ProtoOaTimeInForce::GoodTillCancel => TimeInForce::GoodTillCancel,
+            ProtoOaTimeInForce::ImmediateOrCancel => TimeInForce::ImmediateOrCancel,
+            ProtoOaTimeInForce::FillOrKill => TimeInForce::FillOrKill,
+            ProtoOaTimeInForce::MarketOnOpen => TimeInForce::MarketOnOpen,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DealStatus {
+    Filled,
+    PartiallyFilled,
+    Rejected,
+    InternallyRejected,
+    Error,
+    Missed,
+}
+
+impl From<ProtoOaDealStatus> for DealStatus {
+    fn from(value: ProtoOaDealStatus) -> Self {
+        match value {
+            ProtoOaDealStatus::Filled => DealStatus::Filled,
+            ProtoOaDealStatus::PartiallyFilled => DealStatus::PartiallyFilled,
+            ProtoOaDealStatus::Rejected => DealStatus::Rejected,
+            ProtoOaDealStatus::InternallyRejected => DealStatus::InternallyRejected,
+            ProtoOaDealStatus::Error => DealStatus::Error,
+            ProtoOaDealStatus::Missed => DealStatus::Missed,
+        }
+    }
+}
+
+/// An open (or just closed) position, from `ProtoOaPosition`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Position {
+    pub position_id: i64,
+    pub symbol_id: i64,
+    pub side: Side,
+    pub volume: Volume,
+    pub status: PositionStatus,
+    /// VWAP of the entry deals
+    pub entry_price: Option<Price>,
+    pub stop_loss: Option<Price>,
+    pub take_profit: Option<Price>,
+    pub guaranteed_stop_loss: bool,
+    pub trailing_stop_loss: bool,
+    pub swap: Money,
+    pub commission: Money,
+    pub used_margin: Option<Money>,
+    pub label: Option<String>,
+    pub comment: Option<String>,
+    pub open_time: Option<DateTime<Utc>>,
+    pub last_update_time: Option<DateTime<Utc>>,
+}
+
+impl From<&ProtoOaPosition> for Position {
+    fn from(position: &ProtoOaPosition) -> Self {
+        let digits = position.money_digits.unwrap_or(DEFAULT_MONEY_DIGITS);
+        let trade = &position.trade_data;
+        Self {
+            position_id: position.position_id,
+            symbol_id: trade.symbol_id,
+            side: trade.trade_side().into(),
+            volume: Volume::from_cents(trade.volume),
+            status: position.position_status().into(),
+            entry_price: position.price.map(Price::from_f64),
+            stop_loss: position.stop_loss.map(Price::from_f64),
+            take_profit: position.take_profit.map(Price::from_f64),
+            guaranteed_stop_loss: position.guaranteed_stop_loss.unwrap_or(false),
+            trailing_stop_loss: position.trailing_stop_loss.unwrap_or(false),
+            swap: Money::new(position.swap, digits),
+            commission: Money::new(position.commission.unwrap_or_default(), digits),
+            used_margin: position.used_margin.map(|m| Money::new(m as i64, digits)),
+            label: trade.label.clone(),
+            comment: trade.comment.clone(),
+            open_time: from_opt_mill_seconds(trade.open_timestamp),
+            last_update_time: from_opt_mill_seconds(position.utc_last_update_timestamp),
+        }
+    }
+}
+
+/// A pending or historical order, from `ProtoOaOrder`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Order {
+    pub order_id: i64,
+    pub symbol_id: i64,
+    pub side: Side,
+    pub order_type: OrderType,
+    pub status: OrderStatus,
+    pub volume: Volume,
+    pub executed_volume: Option<Volume>,
+    pub limit_price: Option<Price>,
+    pub stop_price: Option<Price>,
+    pub execution_price: Option<Price>,
+    pub stop_loss: Option<Price>,
+    pub take_profit: Option<Price>,
+    /// Stop loss distance for market orders
+    pub relative_stop_loss: Option<Price>,
+    /// Take profit distance for market orders
+    pub relative_take_profit: Option<Price>,
+    pub time_in_force: Option<TimeInForce>,
+    pub position_id: Option<i64>,
+    pub closing_order: bool,
+    pub client_order_id: Option<String>,
+    pub label: Option<String>,
+    pub comment: Option<String>,
+    pub open_time: Option<DateTime<Utc>>,
+    pub last_update_time: Option<DateTime<Utc>>,
+    pub expiration_time: Option<DateTime<Utc>>,
+}
+
+impl From<&ProtoOaOrder> for Order {
+    fn from(order: &ProtoOaOrder) -> Self {
+        let trade = &order.trade_data;
+        Self {
+            order_id: order.order_id,
+            symbol_id: trade.symbol_id,
+            side: trade.trade_side().into(),
+            order_type: order.order_type().into(),
+            status: order.order_status().into(),
+            volume: Volume::from_cents(trade.volume),
+            executed_volume: order.executed_volume.map(Volume::from_cents),
+            limit_price: order.limit_price.map(Price::from_f64),
+            stop_price: order.stop_price.map(Price::from_f64),
+            execution_price: order.execution_price.map(Price::from_f64),
+            stop_loss: order.stop_loss.map(Price::from_f64),
+            take_profit: order.take_profit.map(Price::from_f64),
+            relative_stop_loss: order.relative_stop_loss.map(Price::from_raw),
+            relative_take_profit: order.relative_take_profit.map(Price::from_raw),
+            time_in_force: order.time_in_force.map(|_| order.time_in_force().into()),
+            position_id: order.position_id,
+            closing_order: order.closing_order.unwrap_or(false),
+            client_order_id: order.client_order_id.clone(),
+            label: trade.label.clone(),
+            comment: trade.comment.clone(),
+            open_time: from_opt_mill_seconds(trade.open_timestamp),
+            last_update_time: from_opt_mill_seconds(order.utc_last_update_timestamp),
+            expiration_time: from_opt_mill_seconds(order.expiration_timestamp),
+        }
+    }
+}
+
+/// Realized result of a deal that closed (part of) a position.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClosedDeal {
+    pub entry_price: Price,
+    pub closed_volume: Option<Volume>,
+    pub gross_profit: Money,
+    pub swap: Money,
+    pub commission: Money,
+    /// Account balance after the deal
+    pub balance: Money,
+}
+
+/// An execution, from `ProtoOaDeal`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Deal {
+    pub deal_id: i64,
+    pub order_id: i64,
+    pub position_id: i64,
+    pub symbol_id: i64,
+    pub side: Side,
+    pub status: DealStatus,
+    pub volume: Volume,
+    pub filled_volume: Volume,
+    pub execution_price: Option<Price>,
+    pub commission: Option<Money>,
+    pub create_time: DateTime<Utc>,
+    pub execution_time: DateTime<Utc>,
+    pub close: Option<ClosedDeal>,
+}
+
+impl From<&ProtoOaDeal> for Deal {
+    fn from(deal: &ProtoOaDeal) -> Self {
+        let detail = deal.close_position_detail.as_ref();
+        let digits = deal
+            .money_digits
+            .or(detail.and_then(|d| d.money_digits))
+            .unwrap_or(DEFAULT_MONEY_DIGITS);
+        let money = |v: i64| Money::new(v, digits);
+        Self {
+            deal_id: deal.deal_id,
+            order_id: deal.order_id,
+            position_id: deal.position_id,
+            symbol_id: deal.symbol_id,
+            side: deal.trade_side().into(),
+            status: deal.deal_status().into(),
+            volume: Volume::from_cents(deal.volume),
+            filled_volume: Volume::from_cents(deal.filled_volume),
+            execution_price: deal.execution_price.map(Price::from_f64),
+            commission: deal.commission.map(money),
+            create_time: from_mill_seconds(deal.create_timestamp),
+            execution_time: from_mill_seconds(deal.execution_timestamp),
+            close: detail.map(|d| ClosedDeal {
+                entry_price: Price::from_f64(d.entry_price),
+                closed_volume: d.closed_volume.map(Volume::from_cents),
+                gross_profit: money(d.gross_profit),
+                swap: money(d.swap),
+                commission: money(d.commission),
+                balance: money(d.balance),
+            }),
+        }
+    }
+}
@@ -71,4 +71,19 @@ pub enum Error {
     //
     #[error("Wrong period: {0}")]
     PeriodParamError(i32),
+
+    #[error("Wrong time range: {0} - {1}")]
+    TimeRangeError(i64, i64),
+
+    #[error("Trading is locked by kill switch")]
+    TradingLocked,
+
+    #[error("Market is closed for symbol {0}")]
+    MarketClosed(i64),
+
+    #[error("No conversion from asset {0} to asset {1}")]
+    NoConversion(i64, i64),
+
+    #[error("No price for symbol {0}")]
+    NoPrice(i64),
 }
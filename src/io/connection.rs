@@ -186,9 +186,42 @@ impl Connection {
         message: ProtoMessage,
         historical: bool,
     ) -> Result<ProtoMessage, Error> {
-        let timeout = self.options.io_timeout;
+        self.requester()?.request(message, historical).await
+    }
+
+    // A handle sending requests through the IO task, for tasks running beside the session.
+    pub fn requester(&self) -> Result<Requester, Error> {
+        let c = self.check_io_task()?;
+        Ok(Requester {
+            requests_tx: c.requests_tx.clone(),
+            historical_tx: c.historical_tx.clone(),
+            io_timeout: self.options.io_timeout,
+        })
+    }
+}
+
+/// Sends requests through the IO task of a connection, with the same rate limits.
+/// It stops working once the connection is shut down.
+#[derive(Debug, Clone)]
+pub struct Requester {
+    requests_tx: mpsc::UnboundedSender<Request>,
+    historical_tx: mpsc::UnboundedSender<Request>,
+    io_timeout: Duration,
+}
+
+impl Requester {
+    #[inline]
+    pub async fn send_request(&self, message: ProtoMessage) -> Result<ProtoMessage, Error> {
+        self.request(message, false).await
+    }
+
+    async fn request(
+        &self,
+        message: ProtoMessage,
+        historical: bool,
+    ) -> Result<ProtoMessage, Error> {
         match self
-            .timed_request(message, timeout.as_secs(), historical)
+            .timed_request(message, self.io_timeout.as_secs(), historical)
             .await
         {
             Ok(msg) => {
@@ -216,7 +249,6 @@ impl Connection {
         timeout_ms: u64,
         historical: bool,
     ) -> Result<ProtoMessage, Error> {
-        let c = self.check_io_task()?;
         let (tx, rx) = oneshot::channel::<Response>();
         let req = Request {
             message,
@@ -224,9 +256,9 @@ impl Connection {
         };
 
         let request_tx = if historical {
-            &c.historical_tx
+            &self.historical_tx
         } else {
-            &c.requests_tx
+            &self.requests_tx
         };
 
         request_tx
@@ -1,9 +1,8 @@
 /// 核心IO任务，长期运行
-use std::{mem, pin::Pin};
+use std::{mem, num::NonZeroU32, pin::Pin};
 
 use futures::{SinkExt, StreamExt};
 use governor::Quota;
-use nonzero_ext::nonzero;
 use tokio::{
     io::AsyncWriteExt,
     net::TcpStream,
@@ -27,6 +26,10 @@ use super::{
 
 pub type MessageStream = Framed<TlsStream<TcpStream>, MsgCodec>;
 
+// see opan api document, 50/s for request, 5/s for historical
+pub const REQUESTS_PER_SECOND: u32 = 50;
+pub const HISTORICAL_REQUESTS_PER_SECOND: u32 = 5;
+
 /// The state held by the IO task, a long-running tokio future. The IO
 /// task manages the underlying Tls/TCP connection, sends periodic
 /// keep-alive heartbeat packets, and sends response packets to tasks that
@@ -75,9 +78,10 @@ impl IoTask {
         cancel_rx: oneshot::Receiver<()>,
         first_connect_tx: oneshot::Sender<()>,
     ) -> Self {
-        // see opan api document, 50/s for request, 5/s for historical
-        let request_quota = Quota::per_second(nonzero!(50u32)).allow_burst(nonzero!(50u32));
-        let historical_quota = Quota::per_second(nonzero!(5u32)).allow_burst(nonzero!(5u32));
+        let request_rate = NonZeroU32::new(REQUESTS_PER_SECOND).unwrap();
+        let historical_rate = NonZeroU32::new(HISTORICAL_REQUESTS_PER_SECOND).unwrap();
+        let request_quota = Quota::per_second(request_rate).allow_burst(request_rate);
+        let historical_quota = Quota::per_second(historical_rate).allow_burst(historical_rate);
 
         Self {
             options,
@@ -8,6 +8,7 @@ mod ratelimit;
 mod types;
 
 pub use cm::ConnectionMode;
-pub use connection::Connection;
+pub use connection::{Connection, Requester};
+pub use io_task::{HISTORICAL_REQUESTS_PER_SECOND, REQUESTS_PER_SECOND};
 pub use options::IoOptions;
 pub use types::{ConnectionState, Event};
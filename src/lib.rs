@@ -2,15 +2,21 @@
 mod builder;
 mod client;
 pub mod credentials;
+pub mod domain;
 mod error;
 mod io;
 pub mod protos;
 pub mod util;
 
 pub use builder::ClientBuilder;
+pub use client::HistoryRecord;
 pub use client::NotifyEvent;
 pub use client::Session;
+pub use client::{FlattenFilter, FlattenReport};
+pub use client::{ModifyOrderParams, NewOrderParams, SubmitOutcome};
 pub use error::Error;
 pub use io::ConnectionState;
 pub use io::Event;
 pub use util::session_config::SessionConfig;
+pub use util::symbol_cache::SymbolLoading;
+pub use util::units::{Money, Price, Volume};
@@ -0,0 +1,736 @@
+//! Execution algorithms working one parent order through several child orders:
+//! TWAP, iceberg and limit-chase.
+//!
+//! Every algo is a plain state machine driven by the caller's event loop:
+//! pass it each `NotifyEvent` with `on_event` and call `on_timer` about once a second.
+//! Child requests are paced by a `RequestPacer`; give all running algos the same
+//! pacer so that together they leave room in the IoTask request quota.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use governor::{
+    clock::DefaultClock,
+    middleware::NoOpMiddleware,
+    state::{InMemoryState, NotKeyed},
+    Quota, RateLimiter,
+};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::client::order::new_client_order_id;
+use crate::io::REQUESTS_PER_SECOND;
+use crate::util::units::{Price, Volume};
+use crate::{
+    protos::spotware_message::*, Error, ModifyOrderParams, NewOrderParams, NotifyEvent, Session,
+    SubmitOutcome,
+};
+
+// default share of the request quota left to algos, the rest stays free for the strategy
+const DEFAULT_ALGO_REQUESTS_PER_SECOND: u32 = REQUESTS_PER_SECOND / 5;
+// submissions per child order, see Session::submit_order
+const SUBMIT_ATTEMPTS: u32 = 2;
+// minimal pause between two amendments of the chased order
+const DEFAULT_REPRICE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Token bucket shared by algos, cloning shares the bucket.
+#[derive(Clone)]
+pub struct RequestPacer {
+    lim: Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock, NoOpMiddleware>>,
+}
+
+impl RequestPacer {
+    // per_second is clamped to 1..=REQUESTS_PER_SECOND
+    pub fn new(per_second: u32) -> Self {
+        let rate = NonZeroU32::new(per_second.clamp(1, REQUESTS_PER_SECOND)).unwrap();
+        let lim = RateLimiter::direct(Quota::per_second(rate));
+        Self { lim: Arc::new(lim) }
+    }
+
+    pub(crate) async fn wait(&self) {
+        self.lim.until_ready().await;
+    }
+}
+
+impl Default for RequestPacer {
+    fn default() -> Self {
+        Self::new(DEFAULT_ALGO_REQUESTS_PER_SECOND)
+    }
+}
+
+impl fmt::Debug for RequestPacer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestPacer").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlgoState {
+    /// Created, start not called yet
+    #[default]
+    Idle,
+    Running,
+    /// Target volume filled, or the schedule ran out with all of it sent
+    Completed,
+    /// The TWAP schedule ran out with volume held back by the price limit, see
+    /// `AlgoProgress::unsent_volume`
+    Expired,
+    /// Cancelled by the caller, or a child order was cancelled/rejected by the server
+    Stopped,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlgoProgress {
+    pub state: AlgoState,
+    pub target_volume: Volume,
+    /// Volume sent in child orders
+    pub sent_volume: Volume,
+    pub filled_volume: Volume,
+    /// Volume weighted average fill price, None before the first fill
+    pub avg_price: Option<Price>,
+    pub child_orders: u32,
+    pub amendments: u32,
+}
+
+impl AlgoProgress {
+    pub fn remaining_volume(&self) -> Volume {
+        (self.target_volume - self.filled_volume).max(Volume::ZERO)
+    }
+
+    // Volume never sent in a child order.
+    pub fn unsent_volume(&self) -> Volume {
+        (self.target_volume - self.sent_volume).max(Volume::ZERO)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.state,
+            AlgoState::Completed | AlgoState::Expired | AlgoState::Stopped
+        )
+    }
+}
+
+// what happened to a child order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChildUpdate {
+    Working(i64),
+    Filled(i64),
+    Closed(i64),
+}
+
+// child order bookkeeping shared by the algos
+#[derive(Debug)]
+struct ChildOrders {
+    symbol_id: i64,
+    trade_side: ProtoOaTradeSide,
+    label: Option<String>,
+    pacer: RequestPacer,
+    progress: AlgoProgress,
+    notional: f64,
+    client_order_ids: HashSet<String>,
+    // order id => limit price of working child orders
+    working: HashMap<i64, Option<f64>>,
+    deals: HashSet<i64>,
+}
+
+impl ChildOrders {
+    fn new(symbol_id: i64, trade_side: ProtoOaTradeSide, volume: Volume) -> Self {
+        Self {
+            symbol_id,
+            trade_side,
+            label: None,
+            pacer: RequestPacer::default(),
+            progress: AlgoProgress {
+                target_volume: volume,
+                ..Default::default()
+            },
+            notional: 0.0,
+            client_order_ids: HashSet::new(),
+            working: HashMap::new(),
+            deals: HashSet::new(),
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.progress.state == AlgoState::Running
+    }
+
+    async fn send(
+        &mut self,
+        session: &Session,
+        mut params: NewOrderParams,
+    ) -> Result<Option<ChildUpdate>, Error> {
+        let client_order_id = new_client_order_id();
+        params.set_client_order_id(&client_order_id);
+        if let Some(label) = &self.label {
+            params.set_label(label);
+        }
+        self.client_order_ids.insert(client_order_id);
+        self.progress.child_orders += 1;
+        let volume = Volume::from_cents(params.volume());
+        self.progress.sent_volume += volume;
+
+        self.pacer.wait().await;
+        match session.submit_order(params, SUBMIT_ATTEMPTS).await {
+            Ok(SubmitOutcome::Placed(event)) => Ok(self.apply_execution(&event)),
+            Ok(SubmitOutcome::AlreadyExists(details)) => {
+                for deal in details.deal.iter() {
+                    self.apply_deal(deal);
+                }
+                Ok(self.apply_order(&details.order))
+            }
+            // the child may be working, sending more could overfill the parent
+            Ok(SubmitOutcome::Unknown { error, .. }) => {
+                self.progress.state = AlgoState::Stopped;
+                Err(error)
+            }
+            // provably not placed
+            Ok(SubmitOutcome::NotPlaced { error, .. }) | Err(error) => {
+                self.progress.sent_volume -= volume;
+                Err(error)
+            }
+        }
+    }
+
+    async fn amend_price(
+        &mut self,
+        session: &Session,
+        order_id: i64,
+        limit_price: f64,
+    ) -> Result<(), Error> {
+        let mut params = ModifyOrderParams::default();
+        params.set_limit_price(limit_price);
+
+        self.pacer.wait().await;
+        let event = session.modify_order(order_id, params).await?;
+        self.progress.amendments += 1;
+        self.apply_execution(&event);
+        Ok(())
+    }
+
+    async fn cancel_all(&mut self, session: &Session) -> Result<(), Error> {
+        self.progress.state = AlgoState::Stopped;
+        let order_ids = self.working.keys().copied().collect::<Vec<_>>();
+        for order_id in order_ids {
+            self.pacer.wait().await;
+            let event = session.cancel_order(order_id).await?;
+            self.apply_execution(&event);
+        }
+        Ok(())
+    }
+
+    fn is_ours(&self, order: &ProtoOaOrder) -> bool {
+        self.working.contains_key(&order.order_id)
+            || order
+                .client_order_id
+                .as_ref()
+                .is_some_and(|id| self.client_order_ids.contains(id))
+    }
+
+    fn apply_execution(&mut self, event: &ProtoOaExecutionEvent) -> Option<ChildUpdate> {
+        let order = event.order.as_ref()?;
+        if !self.is_ours(order) {
+            return None;
+        }
+        if let Some(deal) = &event.deal {
+            self.apply_deal(deal);
+        }
+
+        let order_id = order.order_id;
+        match event.execution_type() {
+            ProtoOaExecutionType::OrderAccepted
+            | ProtoOaExecutionType::OrderReplaced
+            | ProtoOaExecutionType::OrderPartialFill => {
+                self.working.insert(order_id, order.limit_price);
+                Some(ChildUpdate::Working(order_id))
+            }
+            ProtoOaExecutionType::OrderFilled => {
+                self.working.remove(&order_id);
+                Some(ChildUpdate::Filled(order_id))
+            }
+            ProtoOaExecutionType::OrderCancelled
+            | ProtoOaExecutionType::OrderExpired
+            | ProtoOaExecutionType::OrderRejected => {
+                self.working.remove(&order_id);
+                Some(ChildUpdate::Closed(order_id))
+            }
+            _ => None,
+        }
+    }
+
+    // same as apply_execution, for an order looked up after an ambiguous submit
+    fn apply_order(&mut self, order: &ProtoOaOrder) -> Option<ChildUpdate> {
+        let order_id = order.order_id;
+        match order.order_status() {
+            ProtoOaOrderStatus::OrderStatusAccepted => {
+                self.working.insert(order_id, order.limit_price);
+                Some(ChildUpdate::Working(order_id))
+            }
+            ProtoOaOrderStatus::OrderStatusFilled => {
+                self.working.remove(&order_id);
+                Some(ChildUpdate::Filled(order_id))
+            }
+            _ => {
+                self.working.remove(&order_id);
+                Some(ChildUpdate::Closed(order_id))
+            }
+        }
+    }
+
+    fn apply_deal(&mut self, deal: &ProtoOaDeal) {
+        match deal.deal_status() {
+            ProtoOaDealStatus::Filled | ProtoOaDealStatus::PartiallyFilled => {}
+            _ => return,
+        }
+        let price = match deal.execution_price {
+            Some(price) => price,
+            None => return,
+        };
+        if !self.deals.insert(deal.deal_id) {
+            return;
+        }
+        self.notional += price * deal.filled_volume as f64;
+        self.progress.filled_volume += Volume::from_cents(deal.filled_volume);
+        let filled = self.progress.filled_volume.cents();
+        if filled > 0 {
+            self.progress.avg_price = Some(Price::from_f64(self.notional / filled as f64));
+        }
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                               TWAP                               |
+//+------------------------------------------------------------------+
+
+/// Time weighted average price: market orders of equal size spread evenly over a duration.
+#[derive(Debug)]
+pub struct Twap {
+    orders: ChildOrders,
+    duration: Duration,
+    slices: u32,
+    volume_step: i64,
+    price_limit: Option<f64>,
+    started_at: Option<Instant>,
+    next_slice: u32,
+    bid: Option<f64>,
+    ask: Option<f64>,
+}
+
+impl Twap {
+    pub fn new(
+        symbol_id: i64,
+        trade_side: ProtoOaTradeSide,
+        volume: impl Into<Volume>,
+        duration: Duration,
+        slices: u32,
+    ) -> Self {
+        Self {
+            orders: ChildOrders::new(symbol_id, trade_side, volume.into()),
+            duration,
+            slices: slices.max(1),
+            volume_step: 1,
+            price_limit: None,
+            started_at: None,
+            next_slice: 0,
+            bid: None,
+            ask: None,
+        }
+    }
+
+    pub fn set_label(&mut self, label: &str) -> &mut Self {
+        self.orders.label = Some(label.to_string());
+        self
+    }
+
+    pub fn set_pacer(&mut self, pacer: RequestPacer) -> &mut Self {
+        self.orders.pacer = pacer;
+        self
+    }
+
+    // slices are rounded down to the symbol step volume, the last slice takes the rest
+    pub fn set_volume_step(&mut self, volume_step: impl Into<Volume>) -> &mut Self {
+        self.volume_step = volume_step.into().cents().max(1);
+        self
+    }
+
+    // hold a slice back while the touch is worse than the limit (above for buy, below for sell),
+    // its volume is added to the next slice; needs spot subscription of the symbol. When the
+    // last slice is held back the algo ends Expired with the volume left unsent
+    pub fn set_price_limit(&mut self, price_limit: impl Into<Price>) -> &mut Self {
+        self.price_limit = Some(price_limit.into().to_f64());
+        self
+    }
+
+    pub fn progress(&self) -> &AlgoProgress {
+        &self.orders.progress
+    }
+
+    pub async fn start(&mut self, session: &Session) -> Result<(), Error> {
+        if self.orders.progress.state != AlgoState::Idle {
+            return Ok(());
+        }
+        self.orders.progress.state = AlgoState::Running;
+        self.started_at = Some(Instant::now());
+        self.on_timer(session).await
+    }
+
+    // Slices are only sent from on_timer.
+    pub fn on_event(&mut self, event: &NotifyEvent) {
+        match event {
+            NotifyEvent::SpotEvent(spot) if spot.symbol_id == self.orders.symbol_id => {
+                update_touch(spot, &mut self.bid, &mut self.ask);
+            }
+            NotifyEvent::ExecutionEvent(event) if self.orders.apply_execution(event).is_some() => {
+                self.check_completed();
+            }
+            _ => {}
+        }
+    }
+
+    pub async fn on_timer(&mut self, session: &Session) -> Result<(), Error> {
+        let started_at = match self.started_at {
+            Some(t) if self.orders.is_running() => t,
+            _ => return Ok(()),
+        };
+        let interval = self.duration / self.slices;
+        let elapsed = started_at.elapsed();
+
+        while self.next_slice < self.slices && elapsed >= interval * self.next_slice {
+            let slice = self.next_slice;
+            self.next_slice += 1;
+            if !self.price_ok() {
+                debug!("twap slice {} held back by price limit", slice);
+                continue;
+            }
+            let volume = self.slice_target(slice) - self.orders.progress.sent_volume;
+            if volume > Volume::ZERO {
+                let params =
+                    NewOrderParams::market(self.orders.symbol_id, self.orders.trade_side, volume);
+                self.orders.send(session, params).await?;
+            }
+        }
+        self.check_completed();
+        Ok(())
+    }
+
+    pub async fn cancel(&mut self, session: &Session) -> Result<(), Error> {
+        self.orders.cancel_all(session).await
+    }
+
+    // cumulative volume to send up to and including the slice
+    fn slice_target(&self, slice: u32) -> Volume {
+        let target = self.orders.progress.target_volume;
+        if slice + 1 >= self.slices {
+            return target;
+        }
+        let share = target.cents() as i128 * (slice as i128 + 1) / self.slices as i128;
+        Volume::from_cents(share as i64).round_to_step(self.volume_step)
+    }
+
+    fn price_ok(&self) -> bool {
+        let limit = match self.price_limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+        match self.orders.trade_side {
+            ProtoOaTradeSide::Buy => self.ask.is_some_and(|ask| ask <= limit),
+            ProtoOaTradeSide::Sell => self.bid.is_some_and(|bid| bid >= limit),
+        }
+    }
+
+    fn check_completed(&mut self) {
+        if self.orders.is_running()
+            && self.next_slice >= self.slices
+            && self.orders.working.is_empty()
+        {
+            let progress = &mut self.orders.progress;
+            progress.state = if progress.unsent_volume() > Volume::ZERO {
+                AlgoState::Expired
+            } else {
+                AlgoState::Completed
+            };
+        }
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                              Iceberg                             |
+//+------------------------------------------------------------------+
+
+/// Limit order showing only a clip of the volume, the next clip is placed when one fills.
+#[derive(Debug)]
+pub struct Iceberg {
+    orders: ChildOrders,
+    clip_volume: i64,
+    limit_price: f64,
+}
+
+impl Iceberg {
+    pub fn new(
+        symbol_id: i64,
+        trade_side: ProtoOaTradeSide,
+        volume: impl Into<Volume>,
+        clip_volume: impl Into<Volume>,
+        limit_price: impl Into<Price>,
+    ) -> Self {
+        Self {
+            orders: ChildOrders::new(symbol_id, trade_side, volume.into()),
+            clip_volume: clip_volume.into().cents().max(1),
+            limit_price: limit_price.into().to_f64(),
+        }
+    }
+
+    pub fn set_label(&mut self, label: &str) -> &mut Self {
+        self.orders.label = Some(label.to_string());
+        self
+    }
+
+    pub fn set_pacer(&mut self, pacer: RequestPacer) -> &mut Self {
+        self.orders.pacer = pacer;
+        self
+    }
+
+    pub fn progress(&self) -> &AlgoProgress {
+        &self.orders.progress
+    }
+
+    pub async fn start(&mut self, session: &Session) -> Result<(), Error> {
+        if self.orders.progress.state != AlgoState::Idle {
+            return Ok(());
+        }
+        self.orders.progress.state = AlgoState::Running;
+        self.refill(session).await
+    }
+
+    // Move the working clip and all later clips to a new price.
+    pub async fn set_limit_price(
+        &mut self,
+        session: &Session,
+        limit_price: impl Into<Price>,
+    ) -> Result<(), Error> {
+        let limit_price = limit_price.into().to_f64();
+        self.limit_price = limit_price;
+        let order_ids = self.orders.working.keys().copied().collect::<Vec<_>>();
+        for order_id in order_ids {
+            self.orders
+                .amend_price(session, order_id, limit_price)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn on_event(&mut self, session: &Session, event: &NotifyEvent) -> Result<(), Error> {
+        if let NotifyEvent::ExecutionEvent(event) = event {
+            let update = self.orders.apply_execution(event);
+            self.on_update(session, update).await?;
+        }
+        Ok(())
+    }
+
+    // Retries a clip whose submission was refused.
+    pub async fn on_timer(&mut self, session: &Session) -> Result<(), Error> {
+        self.refill(session).await
+    }
+
+    pub async fn cancel(&mut self, session: &Session) -> Result<(), Error> {
+        self.orders.cancel_all(session).await
+    }
+
+    async fn on_update(
+        &mut self,
+        session: &Session,
+        update: Option<ChildUpdate>,
+    ) -> Result<(), Error> {
+        match update {
+            Some(ChildUpdate::Filled(_)) => self.refill(session).await,
+            Some(ChildUpdate::Closed(order_id)) if self.orders.is_running() => {
+                warn!("iceberg clip {} closed by server, stopping", order_id);
+                self.orders.cancel_all(session).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn refill(&mut self, session: &Session) -> Result<(), Error> {
+        // a marketable clip may come back filled already, then the next one goes out at once
+        while self.orders.is_running() && self.orders.working.is_empty() {
+            let remaining = self.orders.progress.remaining_volume();
+            if remaining == Volume::ZERO {
+                self.orders.progress.state = AlgoState::Completed;
+                break;
+            }
+            let volume = remaining.min(Volume::from_cents(self.clip_volume));
+            let params = NewOrderParams::limit(
+                self.orders.symbol_id,
+                self.orders.trade_side,
+                volume,
+                self.limit_price,
+            );
+            match self.orders.send(session, params).await? {
+                Some(ChildUpdate::Filled(_)) => continue,
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                            Limit chase                           |
+//+------------------------------------------------------------------+
+
+/// Limit order joining the touch and re-priced when the touch moves, never beyond a bound.
+#[derive(Debug)]
+pub struct LimitChase {
+    orders: ChildOrders,
+    price_bound: f64,
+    digits: i32,
+    reprice_interval: Duration,
+    last_reprice: Option<Instant>,
+    bid: Option<f64>,
+    ask: Option<f64>,
+}
+
+impl LimitChase {
+    // price_bound is the highest price to buy at, or the lowest to sell at
+    pub fn new(
+        symbol_id: i64,
+        trade_side: ProtoOaTradeSide,
+        volume: impl Into<Volume>,
+        price_bound: impl Into<Price>,
+        digits: i32,
+    ) -> Self {
+        Self {
+            orders: ChildOrders::new(symbol_id, trade_side, volume.into()),
+            price_bound: price_bound.into().to_f64(),
+            digits,
+            reprice_interval: DEFAULT_REPRICE_INTERVAL,
+            last_reprice: None,
+            bid: None,
+            ask: None,
+        }
+    }
+
+    pub fn set_label(&mut self, label: &str) -> &mut Self {
+        self.orders.label = Some(label.to_string());
+        self
+    }
+
+    pub fn set_pacer(&mut self, pacer: RequestPacer) -> &mut Self {
+        self.orders.pacer = pacer;
+        self
+    }
+
+    pub fn set_reprice_interval(&mut self, reprice_interval: Duration) -> &mut Self {
+        self.reprice_interval = reprice_interval;
+        self
+    }
+
+    pub fn progress(&self) -> &AlgoProgress {
+        &self.orders.progress
+    }
+
+    // The order is placed on the first spot of the symbol, which must be subscribed.
+    pub async fn start(&mut self, session: &Session) -> Result<(), Error> {
+        if self.orders.progress.state != AlgoState::Idle {
+            return Ok(());
+        }
+        self.orders.progress.state = AlgoState::Running;
+        self.chase(session).await
+    }
+
+    pub async fn on_event(&mut self, session: &Session, event: &NotifyEvent) -> Result<(), Error> {
+        match event {
+            NotifyEvent::SpotEvent(spot) if spot.symbol_id == self.orders.symbol_id => {
+                update_touch(spot, &mut self.bid, &mut self.ask);
+                self.chase(session).await
+            }
+            NotifyEvent::ExecutionEvent(event) => {
+                match self.orders.apply_execution(event) {
+                    Some(ChildUpdate::Filled(_)) => {
+                        self.orders.progress.state = AlgoState::Completed;
+                    }
+                    Some(ChildUpdate::Closed(order_id)) if self.orders.is_running() => {
+                        warn!("chased order {} closed by server, stopping", order_id);
+                        self.orders.progress.state = AlgoState::Stopped;
+                    }
+                    _ => {}
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Applies a re-price held back by the reprice interval.
+    pub async fn on_timer(&mut self, session: &Session) -> Result<(), Error> {
+        self.chase(session).await
+    }
+
+    pub async fn cancel(&mut self, session: &Session) -> Result<(), Error> {
+        self.orders.cancel_all(session).await
+    }
+
+    // touch price clamped to the bound
+    fn target_price(&self) -> Option<f64> {
+        let price = match self.orders.trade_side {
+            ProtoOaTradeSide::Buy => self.bid?.min(self.price_bound),
+            ProtoOaTradeSide::Sell => self.ask?.max(self.price_bound),
+        };
+        Some(Price::from_f64(price).round_to_digits(self.digits).to_f64())
+    }
+
+    async fn chase(&mut self, session: &Session) -> Result<(), Error> {
+        if !self.orders.is_running() {
+            return Ok(());
+        }
+        let target = match self.target_price() {
+            Some(price) => price,
+            None => return Ok(()),
+        };
+
+        let (order_id, price) = match self.orders.working.iter().next() {
+            Some((order_id, price)) => (*order_id, *price),
+            None => {
+                if self.orders.progress.sent_volume > Volume::ZERO {
+                    // placed already, waiting for the fill or a lost submission
+                    return Ok(());
+                }
+                let params = NewOrderParams::limit(
+                    self.orders.symbol_id,
+                    self.orders.trade_side,
+                    self.orders.progress.target_volume,
+                    target,
+                );
+                if let Some(ChildUpdate::Filled(_)) = self.orders.send(session, params).await? {
+                    self.orders.progress.state = AlgoState::Completed;
+                }
+                self.last_reprice = Some(Instant::now());
+                return Ok(());
+            }
+        };
+
+        let half_point = Price::from_points(1, self.digits).to_f64() / 2.0;
+        if price.is_some_and(|p| (p - target).abs() < half_point) {
+            return Ok(());
+        }
+        if self
+            .last_reprice
+            .is_some_and(|t| t.elapsed() < self.reprice_interval)
+        {
+            return Ok(());
+        }
+        self.last_reprice = Some(Instant::now());
+        self.orders.amend_price(session, order_id, target).await
+    }
+}
+
+fn update_touch(spot: &ProtoOaSpotEvent, bid: &mut Option<f64>, ask: &mut Option<f64>) {
+    if let Some(price) = spot.bid {
+        *bid = Some(Price::from_raw(price as i64).to_f64());
+    }
+    if let Some(price) = spot.ask {
+        *ask = Some(Price::from_raw(price as i64).to_f64());
+    }
+}
@@ -0,0 +1,347 @@
+//! Trade performance statistics from the deal history.
+//!
+//! Every deal closing (part of) a position is one `ClosedTrade`. Its net profit is the
+//! gross profit plus the swap and commission of its `close_position_detail`, in the deposit
+//! currency. The `PerformanceReport` groups the trades by symbol and by label (taken from
+//! the orders of the position, unlabeled trades only count in the total and per symbol)
+//! and builds a daily balance curve from the trades and cash flows for the Sharpe and
+//! Sortino ratios. Deposits and withdrawals do not count as returns.
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{Deal, Order, DEFAULT_MONEY_DIGITS};
+use crate::protos::spotware_message::ProtoOaDepositWithdraw;
+use crate::util::time_util::from_mill_seconds;
+use crate::util::units::Money;
+use crate::{Error, Session};
+
+// daily returns are annualized over calendar days, the market data is 24/7 for some symbols
+const DAYS_PER_YEAR: f64 = 365.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClosedTrade {
+    pub deal_id: i64,
+    pub position_id: i64,
+    pub symbol_id: i64,
+    pub label: Option<String>,
+    /// Closed volume in cents
+    pub volume: i64,
+    /// Execution time of the first deal of the position, when it is in the history
+    pub open_time: Option<DateTime<Utc>>,
+    pub close_time: DateTime<Utc>,
+    pub gross_profit: f64,
+    pub swap: f64,
+    pub commission: f64,
+    pub net_profit: f64,
+    /// Account balance after the deal
+    pub balance: f64,
+}
+
+impl ClosedTrade {
+    pub fn holding_time(&self) -> Option<Duration> {
+        Some(self.close_time - self.open_time?)
+    }
+}
+
+/// A deposit or withdrawal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CashFlow {
+    pub time: DateTime<Utc>,
+    /// Negative for withdrawals
+    pub delta: f64,
+    /// Account balance after the operation
+    pub balance: f64,
+}
+
+impl From<&ProtoOaDepositWithdraw> for CashFlow {
+    fn from(flow: &ProtoOaDepositWithdraw) -> Self {
+        let digits = flow.money_digits.unwrap_or(DEFAULT_MONEY_DIGITS);
+        Self {
+            time: from_mill_seconds(flow.change_balance_timestamp),
+            delta: Money::new(flow.delta, digits).to_f64(),
+            balance: Money::new(flow.balance, digits).to_f64(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TradeStats {
+    pub trades: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub net_profit: f64,
+    /// Sum of the winning trades
+    pub gross_profit: f64,
+    /// Sum of the losing trades, negative
+    pub gross_loss: f64,
+    /// Share of winning trades, 0.0 to 1.0
+    pub win_rate: f64,
+    pub average_win: f64,
+    /// Negative
+    pub average_loss: f64,
+    /// None without losing trades
+    pub profit_factor: Option<f64>,
+    /// Average net profit per trade
+    pub expectancy: f64,
+    /// Largest fall of the cumulative net profit from a previous high
+    pub max_drawdown: f64,
+    /// In seconds, over the trades whose opening deal is known
+    pub average_holding_time: Option<f64>,
+    pub commission: f64,
+    pub swap: f64,
+}
+
+impl TradeStats {
+    // Trades in close time order.
+    pub fn from_trades<'a>(trades: impl IntoIterator<Item = &'a ClosedTrade>) -> Self {
+        let mut stats = TradeStats::default();
+        let (mut cumulative, mut peak) = (0.0_f64, 0.0_f64);
+        let (mut holding, mut held) = (0.0, 0);
+        for trade in trades {
+            stats.trades += 1;
+            if trade.net_profit > 0.0 {
+                stats.wins += 1;
+                stats.gross_profit += trade.net_profit;
+            } else if trade.net_profit < 0.0 {
+                stats.losses += 1;
+                stats.gross_loss += trade.net_profit;
+            }
+            stats.net_profit += trade.net_profit;
+            stats.commission += trade.commission;
+            stats.swap += trade.swap;
+            cumulative += trade.net_profit;
+            peak = peak.max(cumulative);
+            stats.max_drawdown = stats.max_drawdown.max(peak - cumulative);
+            if let Some(time) = trade.holding_time() {
+                holding += time.num_milliseconds() as f64 / 1000.0;
+                held += 1;
+            }
+        }
+        if stats.trades > 0 {
+            stats.win_rate = stats.wins as f64 / stats.trades as f64;
+            stats.expectancy = stats.net_profit / stats.trades as f64;
+        }
+        if stats.wins > 0 {
+            stats.average_win = stats.gross_profit / stats.wins as f64;
+        }
+        if stats.losses > 0 {
+            stats.average_loss = stats.gross_loss / stats.losses as f64;
+            stats.profit_factor = Some(stats.gross_profit / -stats.gross_loss);
+        }
+        stats.average_holding_time = (held > 0).then(|| holding / held as f64);
+        stats
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyEquity {
+    pub date: NaiveDate,
+    /// Balance at the end of the day
+    pub balance: f64,
+    pub net_profit: f64,
+    pub cash_flow: f64,
+    /// Net profit over the balance at the start of the day
+    pub return_rate: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PerformanceReport {
+    pub total: TradeStats,
+    pub by_symbol: BTreeMap<i64, TradeStats>,
+    pub by_label: BTreeMap<String, TradeStats>,
+    pub deposits: f64,
+    /// Negative
+    pub withdrawals: f64,
+    pub daily: Vec<DailyEquity>,
+    /// Annualized, None with less than two days or no variation
+    pub sharpe_ratio: Option<f64>,
+    pub sortino_ratio: Option<f64>,
+    /// Largest fall of the balance without cash flows from a previous high, 0.0 to 1.0
+    pub max_drawdown_rate: Option<f64>,
+}
+
+impl PerformanceReport {
+    pub fn new(trades: &[ClosedTrade], cash_flows: &[CashFlow]) -> Self {
+        let mut trades: Vec<&ClosedTrade> = trades.iter().collect();
+        trades.sort_by_key(|trade| (trade.close_time, trade.deal_id));
+
+        let mut by_symbol: BTreeMap<i64, Vec<&ClosedTrade>> = BTreeMap::new();
+        let mut by_label: BTreeMap<String, Vec<&ClosedTrade>> = BTreeMap::new();
+        for trade in &trades {
+            by_symbol.entry(trade.symbol_id).or_default().push(trade);
+            if let Some(label) = &trade.label {
+                by_label.entry(label.clone()).or_default().push(trade);
+            }
+        }
+
+        let daily = daily_equity(&trades, cash_flows);
+        let returns: Vec<f64> = daily.iter().map(|day| day.return_rate).collect();
+        Self {
+            total: TradeStats::from_trades(trades.iter().copied()),
+            by_symbol: by_symbol
+                .into_iter()
+                .map(|(id, trades)| (id, TradeStats::from_trades(trades)))
+                .collect(),
+            by_label: by_label
+                .into_iter()
+                .map(|(label, trades)| (label, TradeStats::from_trades(trades)))
+                .collect(),
+            deposits: cash_flows.iter().map(|f| f.delta.max(0.0)).sum(),
+            withdrawals: cash_flows.iter().map(|f| f.delta.min(0.0)).sum(),
+            sharpe_ratio: sharpe_ratio(&returns),
+            sortino_ratio: sortino_ratio(&returns),
+            max_drawdown_rate: max_drawdown_rate(&daily),
+            daily,
+        }
+    }
+
+    // Report of the deals, with the labels of their orders.
+    pub fn from_history(
+        deals: &[Deal],
+        orders: &[Order],
+        cash_flows: &[ProtoOaDepositWithdraw],
+    ) -> Self {
+        let cash_flows: Vec<CashFlow> = cash_flows.iter().map(CashFlow::from).collect();
+        Self::new(&closed_trades(deals, orders), &cash_flows)
+    }
+
+    // Fetches the deals, orders and cash flows of the range, paginated, and reports them.
+    pub async fn fetch(
+        session: &Session,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Self, Error> {
+        let deals = session.deal_history(from, to).await?;
+        let orders = session.order_history(from, to).await?;
+        let cash_flows = session
+            .get_historical_cash_flow_list_all(from.timestamp_millis(), to.timestamp_millis())
+            .await?;
+        Ok(Self::from_history(&deals, &orders, &cash_flows))
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::String(e.to_string()))
+    }
+}
+
+// The deals that closed a position, labelled from the orders of the position.
+pub fn closed_trades(deals: &[Deal], orders: &[Order]) -> Vec<ClosedTrade> {
+    let mut labels: HashMap<i64, &str> = HashMap::new();
+    for order in orders {
+        if let (Some(position_id), Some(label)) = (order.position_id, order.label.as_deref()) {
+            labels.entry(position_id).or_insert(label);
+        }
+    }
+    let mut opened: HashMap<i64, DateTime<Utc>> = HashMap::new();
+    for deal in deals.iter().filter(|deal| deal.close.is_none()) {
+        let time = opened
+            .entry(deal.position_id)
+            .or_insert(deal.execution_time);
+        *time = (*time).min(deal.execution_time);
+    }
+
+    deals
+        .iter()
+        .filter_map(|deal| {
+            let close = deal.close.as_ref()?;
+            let (gross, swap, commission) = (
+                close.gross_profit.to_f64(),
+                close.swap.to_f64(),
+                close.commission.to_f64(),
+            );
+            Some(ClosedTrade {
+                deal_id: deal.deal_id,
+                position_id: deal.position_id,
+                symbol_id: deal.symbol_id,
+                label: labels.get(&deal.position_id).map(|l| l.to_string()),
+                volume: close.closed_volume.unwrap_or(deal.filled_volume).cents(),
+                open_time: opened.get(&deal.position_id).copied(),
+                close_time: deal.execution_time,
+                gross_profit: gross,
+                swap,
+                commission,
+                net_profit: gross + swap + commission,
+                balance: close.balance.to_f64(),
+            })
+        })
+        .collect()
+}
+
+// One entry per calendar day from the first to the last trade or cash flow.
+fn daily_equity(trades: &[&ClosedTrade], cash_flows: &[CashFlow]) -> Vec<DailyEquity> {
+    // (time, net profit, cash flow, balance after)
+    let mut events: Vec<(DateTime<Utc>, f64, f64, f64)> = trades
+        .iter()
+        .map(|t| (t.close_time, t.net_profit, 0.0, t.balance))
+        .chain(cash_flows.iter().map(|f| (f.time, 0.0, f.delta, f.balance)))
+        .collect();
+    events.sort_by_key(|event| event.0);
+    let (Some(first), Some(last)) = (events.first(), events.last()) else {
+        return Vec::new();
+    };
+
+    let mut balance = first.3 - first.1 - first.2;
+    let mut daily = Vec::new();
+    let mut events = events.iter().peekable();
+    let mut date = first.0.date_naive();
+    while date <= last.0.date_naive() {
+        let start = balance;
+        let (mut profit, mut cash) = (0.0, 0.0);
+        while let Some(event) = events.next_if(|e| e.0.date_naive() == date) {
+            profit += event.1;
+            cash += event.2;
+            balance = event.3;
+        }
+        daily.push(DailyEquity {
+            date,
+            balance,
+            net_profit: profit,
+            cash_flow: cash,
+            return_rate: if start > 0.0 { profit / start } else { 0.0 },
+        });
+        date += Duration::days(1);
+    }
+    daily
+}
+
+fn sharpe_ratio(returns: &[f64]) -> Option<f64> {
+    if returns.len() < 2 {
+        return None;
+    }
+    let mean = mean(returns);
+    let variance =
+        returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+    let deviation = variance.sqrt();
+    (deviation > 0.0).then(|| mean / deviation * DAYS_PER_YEAR.sqrt())
+}
+
+fn sortino_ratio(returns: &[f64]) -> Option<f64> {
+    if returns.len() < 2 {
+        return None;
+    }
+    let downside = returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / returns.len() as f64;
+    let deviation = downside.sqrt();
+    (deviation > 0.0).then(|| mean(returns) / deviation * DAYS_PER_YEAR.sqrt())
+}
+
+// On the balance curve with the cash flows taken out.
+fn max_drawdown_rate(daily: &[DailyEquity]) -> Option<f64> {
+    let first = daily.first()?;
+    let mut equity = first.balance - first.net_profit - first.cash_flow;
+    let mut peak = equity;
+    let mut drawdown: f64 = 0.0;
+    for day in daily {
+        equity += day.net_profit;
+        peak = peak.max(equity);
+        if peak > 0.0 {
+            drawdown = drawdown.max((peak - equity) / peak);
+        }
+    }
+    Some(drawdown)
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
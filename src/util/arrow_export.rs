@@ -0,0 +1,210 @@
+//! Arrow IPC and Parquet files, enabled by the `arrow` feature.
+//! Timestamps are stored as `Timestamp(Millisecond, "UTC")`, the export metadata goes
+//! into the schema metadata.
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, Float64Array, Int64Array, StringArray, TimestampMillisecondArray, UInt64Array,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+
+use crate::util::export::{ExportMetadata, ExportRecord};
+use crate::Error;
+
+// rows buffered before a record batch is written
+const BATCH_ROWS: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrowFormat {
+    Ipc,
+    Parquet,
+}
+
+enum Sink {
+    Ipc(FileWriter<File>),
+    Parquet(ArrowWriter<File>),
+}
+
+/// Writes records to an Arrow IPC or Parquet file, one record batch every 8192 rows.
+pub struct ArrowFileWriter<T: ExportRecord> {
+    sink: Sink,
+    schema: SchemaRef,
+    buffer: Vec<T>,
+}
+
+impl<T: ExportRecord> ArrowFileWriter<T> {
+    pub fn create(
+        path: impl AsRef<Path>,
+        format: ArrowFormat,
+        metadata: &ExportMetadata,
+    ) -> Result<Self, Error> {
+        let schema = Arc::new(Schema::new_with_metadata(
+            T::arrow_fields(),
+            metadata.to_pairs().into_iter().collect::<HashMap<_, _>>(),
+        ));
+        let file = File::create(path)?;
+        let sink = match format {
+            ArrowFormat::Ipc => Sink::Ipc(FileWriter::try_new(file, &schema).map_err(arrow_error)?),
+            ArrowFormat::Parquet => Sink::Parquet(
+                ArrowWriter::try_new(file, schema.clone(), None).map_err(parquet_error)?,
+            ),
+        };
+        Ok(Self {
+            sink,
+            schema,
+            buffer: Vec::with_capacity(BATCH_ROWS),
+        })
+    }
+
+    pub fn write(&mut self, record: &T) -> Result<(), Error> {
+        self.buffer.push(record.clone());
+        if self.buffer.len() >= BATCH_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let batch = RecordBatch::try_new(self.schema.clone(), T::to_arrow_columns(&self.buffer))
+            .map_err(arrow_error)?;
+        self.buffer.clear();
+        match &mut self.sink {
+            Sink::Ipc(writer) => writer.write(&batch).map_err(arrow_error),
+            Sink::Parquet(writer) => writer.write(&batch).map_err(parquet_error),
+        }
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.flush()?;
+        match self.sink {
+            Sink::Ipc(mut writer) => writer.finish().map_err(arrow_error),
+            Sink::Parquet(writer) => writer.close().map(|_| ()).map_err(parquet_error),
+        }
+    }
+}
+
+// Read all records and the metadata of a file written by ArrowFileWriter.
+pub fn read_arrow_file<T: ExportRecord>(
+    path: impl AsRef<Path>,
+    format: ArrowFormat,
+) -> Result<(ExportMetadata, Vec<T>), Error> {
+    let file = File::open(path)?;
+    let mut records = Vec::new();
+    let schema = match format {
+        ArrowFormat::Ipc => {
+            let reader = FileReader::try_new(file, None).map_err(arrow_error)?;
+            let schema = reader.schema();
+            for batch in reader {
+                records.extend(T::from_arrow_batch(&batch.map_err(arrow_error)?)?);
+            }
+            schema
+        }
+        ArrowFormat::Parquet => {
+            let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+                .map_err(parquet_error)?
+                .build()
+                .map_err(parquet_error)?;
+            let schema = arrow::record_batch::RecordBatchReader::schema(&reader);
+            for batch in reader {
+                records.extend(T::from_arrow_batch(&batch.map_err(arrow_error)?)?);
+            }
+            schema
+        }
+    };
+    let metadata = ExportMetadata::from_pairs(schema.metadata().clone());
+    Ok((metadata, records))
+}
+
+//+------------------------------------------------------------------+
+//|                           Columns                                |
+//+------------------------------------------------------------------+
+
+pub(crate) fn field(name: &str, data_type: DataType, nullable: bool) -> Field {
+    Field::new(name, data_type, nullable)
+}
+
+pub(crate) fn timestamp_field(name: &str, nullable: bool) -> Field {
+    Field::new(
+        name,
+        DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        nullable,
+    )
+}
+
+pub(crate) fn timestamp_array(values: impl Iterator<Item = Option<i64>>) -> ArrayRef {
+    Arc::new(TimestampMillisecondArray::from_iter(values).with_timezone_utc())
+}
+
+pub(crate) fn f64_array(values: impl Iterator<Item = Option<f64>>) -> ArrayRef {
+    Arc::new(Float64Array::from_iter(values))
+}
+
+pub(crate) fn i64_array(values: impl Iterator<Item = Option<i64>>) -> ArrayRef {
+    Arc::new(Int64Array::from_iter(values))
+}
+
+pub(crate) fn u64_array(values: impl Iterator<Item = Option<u64>>) -> ArrayRef {
+    Arc::new(UInt64Array::from_iter(values))
+}
+
+pub(crate) fn string_array<'a>(values: impl Iterator<Item = &'a str>) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(values))
+}
+
+fn column<'a, A: Array + 'static>(batch: &'a RecordBatch, name: &str) -> Result<&'a A, Error> {
+    batch
+        .column_by_name(name)
+        .and_then(|c| c.as_any().downcast_ref::<A>())
+        .ok_or(Error::String(format!(
+            "missing or mistyped column {}",
+            name
+        )))
+}
+
+pub(crate) fn timestamp_column<'a>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a TimestampMillisecondArray, Error> {
+    column(batch, name)
+}
+
+pub(crate) fn f64_column<'a>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a Float64Array, Error> {
+    column(batch, name)
+}
+
+pub(crate) fn i64_column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a Int64Array, Error> {
+    column(batch, name)
+}
+
+pub(crate) fn u64_column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a UInt64Array, Error> {
+    column(batch, name)
+}
+
+pub(crate) fn string_column<'a>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a StringArray, Error> {
+    column(batch, name)
+}
+
+fn arrow_error(e: arrow::error::ArrowError) -> Error {
+    Error::String(format!("arrow error: {}", e))
+}
+
+fn parquet_error(e: parquet::errors::ParquetError) -> Error {
+    Error::String(format!("parquet error: {}", e))
+}
@@ -0,0 +1,458 @@
+//! Local cache of historical trend bars, keyed by (symbol, period).
+//!
+//! Layout under the cache root, one directory per symbol:
+//!
+//! ```text
+//! <root>/<symbol>/<period>.bars    bar lines, appended as they are downloaded
+//! <root>/<symbol>/<period>.ranges  time ranges already downloaded
+//! ```
+//!
+//! `.bars` is plain text, one bar per line:
+//! `timestamp_ms,open,high,low,close,volume\n`, lines starting with `#` are comments.
+//! Bars are appended in download order, so a file may be out of order and, after an
+//! interrupted update, hold the same bar twice; readers sort and dedupe, `compact`
+//! rewrites the file in order. Every append is one write of whole lines, and readers
+//! ignore a trailing line without `\n`, so reading while another process appends is safe.
+//!
+//! `.ranges` holds one `from_ms,to_ms\n` line per downloaded range `[from, to)`. It is
+//! replaced atomically (write to a temporary file, then rename) after the bars are
+//! appended, so a range is only recorded once all of its bars are on disk.
+//!
+//! There must be one writer per (symbol, period) at a time, readers are not limited.
+//!
+//! A bar that has not closed yet (its period ends in the future) is returned as
+//! provisional and never stored; the range covering it is fetched again next time.
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use tracing::{info, warn};
+
+use crate::{
+    protos::spotware_message::ProtoOaTrendbarPeriod,
+    util::download::{download_asset, Kline},
+    util::time_util::from_mill_seconds,
+    Error, Session,
+};
+
+const BARS_HEADER: &str = "# timestamp_ms,open,high,low,close,volume\n";
+
+/// Result of a cache query.
+#[derive(Debug, Clone, Default)]
+pub struct BarQuery {
+    /// Bars of the range in time order
+    pub bars: Vec<Kline>,
+    /// True if the last bar has not closed yet
+    pub provisional: bool,
+}
+
+/// Consistency report of one (symbol, period) file.
+#[derive(Debug, Clone, Default)]
+pub struct CacheCheck {
+    /// Timestamps (ms) stored more than once
+    pub duplicates: Vec<i64>,
+    /// Consecutive bars further apart than one period, as `(last bar, next bar)` timestamps (ms).
+    /// Weekends and holidays show up here as well.
+    pub gaps: Vec<(i64, i64)>,
+    /// Downloaded ranges `[from, to)`, merged
+    pub coverage: Vec<(i64, i64)>,
+    /// Lines that could not be parsed
+    pub bad_lines: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BarCache {
+    root: PathBuf,
+}
+
+impl BarCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    // Bars of [start, end): read from disk, missing ranges are downloaded and stored first.
+    pub async fn get_bars(
+        &self,
+        client: &Session,
+        symbol: &str,
+        period: i32,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+    ) -> Result<BarQuery, Error> {
+        // unknown periods fail before anything is read
+        period_length_ms(period)?;
+        let from = start.timestamp_millis();
+        let to = end.timestamp_millis();
+
+        let mut fresh = Vec::new();
+        for (missing_from, missing_to) in self.missing_ranges(symbol, period, from, to)? {
+            fresh.extend(
+                self.fetch_range(client, symbol, period, missing_from, missing_to)
+                    .await?,
+            );
+        }
+
+        let mut bars = self.read_range(symbol, period, from, to)?;
+        for bar in fresh {
+            bars.insert(bar.timestamp.timestamp_millis(), bar);
+        }
+
+        let bars = bars.into_values().collect::<Vec<_>>();
+        let now = Utc::now();
+        let provisional = bars
+            .last()
+            .is_some_and(|k| bar_end(k.timestamp, period) > now);
+        Ok(BarQuery { bars, provisional })
+    }
+
+    // Download [from, to) and store the closed bars, the range stops before the first open one.
+    // Returns all downloaded bars.
+    pub(crate) async fn fetch_range(
+        &self,
+        client: &Session,
+        symbol: &str,
+        period: i32,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Kline>, Error> {
+        let period_ms = period_length_ms(period)?;
+        info!(
+            "bar cache {} {}: fetching {} -> {}",
+            symbol, period, from, to
+        );
+        let bars = download_asset(
+            client,
+            symbol,
+            period,
+            &from_mill_seconds(from),
+            &from_mill_seconds(to),
+        )
+        .await?;
+
+        let closed_before = Utc::now().timestamp_millis() - period_ms;
+        let stored_to = to.min(closed_before);
+        if stored_to > from {
+            let closed = bars
+                .iter()
+                .filter(|k| k.timestamp.timestamp_millis() < stored_to)
+                .cloned()
+                .collect::<Vec<_>>();
+            self.store(symbol, period, from, stored_to, &closed)?;
+        }
+        Ok(bars)
+    }
+
+    // Append bars downloaded for [from, to) and record the range as covered.
+    pub fn store(
+        &self,
+        symbol: &str,
+        period: i32,
+        from: i64,
+        to: i64,
+        bars: &[Kline],
+    ) -> Result<(), Error> {
+        let dir = self.symbol_dir(symbol);
+        fs::create_dir_all(&dir)?;
+
+        let bars_path = self.bars_path(symbol, period);
+        let mut buf = String::new();
+        if !bars_path.exists() {
+            buf.push_str(BARS_HEADER);
+        }
+        for k in bars {
+            buf.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                k.timestamp.timestamp_millis(),
+                k.open,
+                k.high,
+                k.low,
+                k.close,
+                k.vol
+            ));
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&bars_path)?;
+        file.write_all(buf.as_bytes())?;
+        file.sync_data()?;
+
+        let mut coverage = self.coverage(symbol, period)?;
+        coverage.push((from, to));
+        self.write_coverage(symbol, period, &merge_ranges(coverage))
+    }
+
+    // Downloaded ranges [from, to), merged and sorted.
+    pub fn coverage(&self, symbol: &str, period: i32) -> Result<Vec<(i64, i64)>, Error> {
+        let path = self.ranges_path(symbol, period);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let data = fs::read_to_string(&path)?;
+        let ranges = complete_lines(&data)
+            .filter_map(|line| {
+                let (from, to) = line.split_once(',')?;
+                Some((from.trim().parse().ok()?, to.trim().parse().ok()?))
+            })
+            .collect();
+        Ok(merge_ranges(ranges))
+    }
+
+    // Parts of [from, to) not downloaded yet.
+    pub fn missing_ranges(
+        &self,
+        symbol: &str,
+        period: i32,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<(i64, i64)>, Error> {
+        let mut missing = Vec::new();
+        let mut cursor = from;
+        for (covered_from, covered_to) in self.coverage(symbol, period)? {
+            if covered_to <= cursor {
+                continue;
+            }
+            if covered_from >= to {
+                break;
+            }
+            if covered_from > cursor {
+                missing.push((cursor, covered_from));
+            }
+            cursor = cursor.max(covered_to);
+        }
+        if cursor < to {
+            missing.push((cursor, to));
+        }
+        Ok(missing)
+    }
+
+    // All stored bars in time order, without duplicates.
+    pub fn read(&self, symbol: &str, period: i32) -> Result<Vec<Kline>, Error> {
+        Ok(self.load(symbol, period)?.0.into_values().collect())
+    }
+
+    pub fn check(&self, symbol: &str, period: i32) -> Result<CacheCheck, Error> {
+        let (bars, duplicates, bad_lines) = self.load(symbol, period)?;
+        let period_ms = period_length_ms(period)?;
+
+        let timestamps = bars.keys().copied().collect::<Vec<_>>();
+        let gaps = timestamps
+            .windows(2)
+            .filter(|w| {
+                // months differ in length, compare with the calendar instead
+                if period == ProtoOaTrendbarPeriod::Mn1 as i32 {
+                    bar_end(from_mill_seconds(w[0]), period).timestamp_millis() < w[1]
+                } else {
+                    w[1] - w[0] > period_ms
+                }
+            })
+            .map(|w| (w[0], w[1]))
+            .collect();
+
+        Ok(CacheCheck {
+            duplicates,
+            gaps,
+            coverage: self.coverage(symbol, period)?,
+            bad_lines,
+        })
+    }
+
+    // Rewrite the bar file in time order without duplicates and bad lines.
+    pub fn compact(&self, symbol: &str, period: i32) -> Result<(), Error> {
+        let bars = self.read(symbol, period)?;
+        let mut buf = String::from(BARS_HEADER);
+        for k in bars {
+            buf.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                k.timestamp.timestamp_millis(),
+                k.open,
+                k.high,
+                k.low,
+                k.close,
+                k.vol
+            ));
+        }
+        replace_file(&self.bars_path(symbol, period), buf.as_bytes())
+    }
+
+    fn read_range(
+        &self,
+        symbol: &str,
+        period: i32,
+        from: i64,
+        to: i64,
+    ) -> Result<BTreeMap<i64, Kline>, Error> {
+        let (mut bars, _, _) = self.load(symbol, period)?;
+        let mut bars = bars.split_off(&from);
+        bars.split_off(&to);
+        Ok(bars)
+    }
+
+    // bars by timestamp, duplicated timestamps and number of bad lines
+    #[allow(clippy::type_complexity)]
+    fn load(
+        &self,
+        symbol: &str,
+        period: i32,
+    ) -> Result<(BTreeMap<i64, Kline>, Vec<i64>, usize), Error> {
+        let path = self.bars_path(symbol, period);
+        let mut bars = BTreeMap::new();
+        let mut duplicates = Vec::new();
+        let mut bad_lines = 0;
+        if !path.exists() {
+            return Ok((bars, duplicates, bad_lines));
+        }
+
+        let data = fs::read_to_string(&path)?;
+        for line in complete_lines(&data) {
+            match parse_bar(line) {
+                Some(bar) => {
+                    let ts = bar.timestamp.timestamp_millis();
+                    if bars.insert(ts, bar).is_some() {
+                        duplicates.push(ts);
+                    }
+                }
+                None => {
+                    warn!("bar cache {}: bad line {:?}", path.display(), line);
+                    bad_lines += 1;
+                }
+            }
+        }
+        Ok((bars, duplicates, bad_lines))
+    }
+
+    fn write_coverage(
+        &self,
+        symbol: &str,
+        period: i32,
+        coverage: &[(i64, i64)],
+    ) -> Result<(), Error> {
+        let mut buf = String::new();
+        for (from, to) in coverage {
+            buf.push_str(&format!("{},{}\n", from, to));
+        }
+        replace_file(&self.ranges_path(symbol, period), buf.as_bytes())
+    }
+
+    fn symbol_dir(&self, symbol: &str) -> PathBuf {
+        // symbol names may contain '/' or spaces
+        let name = symbol
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        self.root.join(name)
+    }
+
+    fn bars_path(&self, symbol: &str, period: i32) -> PathBuf {
+        self.symbol_dir(symbol)
+            .join(format!("{}.bars", period_name(period)))
+    }
+
+    fn ranges_path(&self, symbol: &str, period: i32) -> PathBuf {
+        self.symbol_dir(symbol)
+            .join(format!("{}.ranges", period_name(period)))
+    }
+}
+
+pub(crate) fn period_name(period: i32) -> String {
+    ProtoOaTrendbarPeriod::try_from(period)
+        .map(|p| p.as_str_name().to_string())
+        .unwrap_or_else(|_| period.to_string())
+}
+
+// nominal length of a bar, one month counts as 31 days
+pub(crate) fn period_length_ms(period: i32) -> Result<i64, Error> {
+    let minutes = match ProtoOaTrendbarPeriod::try_from(period) {
+        Ok(ProtoOaTrendbarPeriod::M1) => 1,
+        Ok(ProtoOaTrendbarPeriod::M2) => 2,
+        Ok(ProtoOaTrendbarPeriod::M3) => 3,
+        Ok(ProtoOaTrendbarPeriod::M4) => 4,
+        Ok(ProtoOaTrendbarPeriod::M5) => 5,
+        Ok(ProtoOaTrendbarPeriod::M10) => 10,
+        Ok(ProtoOaTrendbarPeriod::M15) => 15,
+        Ok(ProtoOaTrendbarPeriod::M30) => 30,
+        Ok(ProtoOaTrendbarPeriod::H1) => 60,
+        Ok(ProtoOaTrendbarPeriod::H4) => 240,
+        Ok(ProtoOaTrendbarPeriod::H12) => 720,
+        Ok(ProtoOaTrendbarPeriod::D1) => 1440,
+        Ok(ProtoOaTrendbarPeriod::W1) => 10080,
+        Ok(ProtoOaTrendbarPeriod::Mn1) => 44640,
+        Err(_) => return Err(Error::PeriodParamError(period)),
+    };
+    Ok(minutes * 60_000)
+}
+
+// close time of the bar opened at `start`
+fn bar_end(start: DateTime<Utc>, period: i32) -> DateTime<Utc> {
+    if period == ProtoOaTrendbarPeriod::Mn1 as i32 {
+        let (year, month) = if start.month() == 12 {
+            (start.year() + 1, 1)
+        } else {
+            (start.year(), start.month() + 1)
+        };
+        return Utc
+            .with_ymd_and_hms(year, month, 1, 0, 0, 0)
+            .single()
+            .unwrap_or(start + Duration::days(31));
+    }
+    start + Duration::milliseconds(period_length_ms(period).unwrap_or_default())
+}
+
+// lines ending with '\n', skipping comments; a partially written last line is ignored
+fn complete_lines(data: &str) -> impl Iterator<Item = &str> {
+    let complete = match data.rfind('\n') {
+        Some(pos) => &data[..pos],
+        None => "",
+    };
+    complete
+        .split('\n')
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+fn parse_bar(line: &str) -> Option<Kline> {
+    let mut fields = line.split(',');
+    let timestamp = from_mill_seconds(fields.next()?.parse().ok()?);
+    let open = fields.next()?.parse().ok()?;
+    let high = fields.next()?.parse().ok()?;
+    let low = fields.next()?.parse().ok()?;
+    let close = fields.next()?.parse().ok()?;
+    let vol = fields.next()?.parse().ok()?;
+    Some(Kline {
+        timestamp,
+        open,
+        high,
+        low,
+        close,
+        vol,
+    })
+}
+
+fn merge_ranges(mut ranges: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    ranges.retain(|(from, to)| from < to);
+    ranges.sort();
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(ranges.len());
+    for (from, to) in ranges {
+        match merged.last_mut() {
+            Some(last) if from <= last.1 => last.1 = last.1.max(to),
+            _ => merged.push((from, to)),
+        }
+    }
+    merged
+}
+
+pub(crate) fn replace_file(path: &Path, data: &[u8]) -> Result<(), Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -1,7 +1,10 @@
+use chrono::{DateTime, Utc};
 use tracing::error;
 
 use crate::protos::spotware_message::ProtoOaSpotEvent;
+use crate::util::calendar::TradingCalendar;
 use crate::util::time_util;
+use crate::util::units::Price;
 
 #[derive(Default, Debug, Clone, Copy)]
 /// Defines a Candle
@@ -74,6 +77,7 @@ pub struct BarGenerator {
     num_asks: i32,
     last_ask: f64,
     last_bid: f64,
+    calendar: Option<TradingCalendar>,
 }
 
 impl BarGenerator {
@@ -89,9 +93,28 @@ impl BarGenerator {
             num_bids: 0,
             last_ask: 0.0,
             last_bid: 0.0,
+            calendar: None,
         }
     }
 
+    // Closes the open bar when the market closes, see `poll`.
+    pub fn set_calendar(&mut self, calendar: TradingCalendar) -> &mut Self {
+        self.calendar = Some(calendar);
+        self
+    }
+
+    // With a calendar set, returns the open bar once the market is closed at `now`
+    // instead of holding it until the first spot of the next session.
+    pub fn poll(&mut self, now: DateTime<Utc>) -> Option<Candle> {
+        let closed = self.calendar.as_ref().is_some_and(|c| !c.is_open(now));
+        if !closed || self.timestamp == 0 {
+            return None;
+        }
+        let candle = self.frozen_candle();
+        self.timestamp = 0;
+        candle
+    }
+
     fn frozen_candle(&mut self) -> Option<Candle> {
         let candle = if self.timestamp == 0 {
             None
@@ -135,11 +158,11 @@ impl BarGenerator {
         // update ask/bid
         let ask = event
             .ask
-            .map(|x| (x as f64) / 100_000.0)
+            .map(|x| Price::from_raw(x as i64).to_f64())
             .unwrap_or(self.last_ask);
         let bid = event
             .bid
-            .map(|x| (x as f64) / 100_000.0)
+            .map(|x| Price::from_raw(x as i64).to_f64())
             .unwrap_or(self.last_bid);
 
         if event.ask.is_some() {
@@ -175,9 +198,9 @@ impl BarGenerator {
             let _low = event.trendbar[0].low.unwrap();
             let _open = event.trendbar[0].delta_open.unwrap_or(0) as i64 + _low;
             let _high = event.trendbar[0].delta_high.unwrap_or(0) as i64 + _low;
-            let low = _low as f64 / 100_000.0;
-            let open = _open as f64 / 100_000.0;
-            let high = _high as f64 / 100_000.0;
+            let low = Price::from_raw(_low).to_f64();
+            let open = Price::from_raw(_open).to_f64();
+            let high = Price::from_raw(_high).to_f64();
             let vol = event.trendbar[0].volume as u64;
 
             self.open = open;
@@ -0,0 +1,299 @@
+//! Downloads many (symbol, period) series into a `BarCache`.
+//!
+//! Every series is split into request sized windows. Windows are issued round robin across
+//! the series, so all of them advance together within the historical request quota.
+//! A window is recorded in the cache coverage as soon as its bars are stored, so running the
+//! same download again after an interruption only fetches what is still missing.
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::stream::{FuturesUnordered, StreamExt};
+use tracing::{info, warn};
+
+use crate::{
+    io::HISTORICAL_REQUESTS_PER_SECOND,
+    util::{bar_cache::BarCache, download::request_window_ms},
+    Error, Session,
+};
+
+/// One series to download.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BulkJob {
+    pub symbol: String,
+    pub period: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Completed,
+    /// Gave up after the retries, with the last error
+    Failed(String),
+}
+
+/// Reported after every window of a series.
+#[derive(Debug, Clone)]
+pub struct BulkProgress {
+    pub job: BulkJob,
+    pub state: JobState,
+    pub windows_done: usize,
+    /// Windows missing from the cache when the run started
+    pub windows_total: usize,
+    /// Bars downloaded so far
+    pub bars: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BulkReport {
+    pub completed: Vec<BulkJob>,
+    pub failed: Vec<(BulkJob, String)>,
+    pub bars: usize,
+    pub requests: usize,
+    pub retries: usize,
+}
+
+#[derive(Debug)]
+pub struct BulkDownloader {
+    cache: BarCache,
+    jobs: Vec<BulkJob>,
+    concurrency: usize,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+// per job bookkeeping of a run
+struct JobRun {
+    windows: VecDeque<(i64, i64)>,
+    progress: BulkProgress,
+}
+
+impl BulkDownloader {
+    pub fn new(cache: BarCache) -> Self {
+        Self {
+            cache,
+            jobs: Vec::new(),
+            concurrency: HISTORICAL_REQUESTS_PER_SECOND as usize,
+            max_retries: 3,
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+
+    pub fn add(&mut self, symbol: &str, period: i32) -> &mut Self {
+        let job = BulkJob {
+            symbol: symbol.to_string(),
+            period,
+        };
+        if !self.jobs.contains(&job) {
+            self.jobs.push(job);
+        }
+        self
+    }
+
+    // Every period of every symbol.
+    pub fn add_all(&mut self, symbols: &[&str], periods: &[i32]) -> &mut Self {
+        for symbol in symbols {
+            for period in periods {
+                self.add(symbol, *period);
+            }
+        }
+        self
+    }
+
+    // Requests in flight at once, default 5. The historical lane still throttles to 5/s.
+    pub fn set_concurrency(&mut self, concurrency: usize) -> &mut Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    // Retries after a timeout or a dropped connection before the series is given up, default 3.
+    pub fn set_max_retries(&mut self, max_retries: u32) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    // Wait before the first retry, doubled on every further one, default 1s.
+    pub fn set_retry_delay(&mut self, retry_delay: Duration) -> &mut Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    pub fn jobs(&self) -> &[BulkJob] {
+        &self.jobs
+    }
+
+    pub fn cache(&self) -> &BarCache {
+        &self.cache
+    }
+
+    // Download [start, end) of all series into the cache. A series that keeps failing
+    // is reported as failed and the others go on; the run stops if the client disconnects.
+    pub async fn run<F>(
+        &self,
+        client: &Session,
+        start: &DateTime<Utc>,
+        end: &DateTime<Utc>,
+        mut on_progress: F,
+    ) -> Result<BulkReport, Error>
+    where
+        F: FnMut(&BulkProgress),
+    {
+        let from = start.timestamp_millis();
+        let to = end.timestamp_millis();
+        let mut report = BulkReport::default();
+        let mut runs = Vec::with_capacity(self.jobs.len());
+        let mut rotation = VecDeque::new();
+
+        for (idx, job) in self.jobs.iter().enumerate() {
+            let windows = self.plan(job, from, to);
+            let mut progress = BulkProgress {
+                job: job.clone(),
+                state: JobState::Running,
+                windows_done: 0,
+                windows_total: 0,
+                bars: 0,
+            };
+            let windows = match windows {
+                Ok(windows) => windows,
+                Err(e) => {
+                    progress.state = JobState::Failed(e.to_string());
+                    VecDeque::new()
+                }
+            };
+            progress.windows_total = windows.len();
+            if windows.is_empty() {
+                if progress.state == JobState::Running {
+                    progress.state = JobState::Completed;
+                }
+                Self::finish_job(&mut report, &progress);
+                on_progress(&progress);
+            } else {
+                rotation.push_back(idx);
+            }
+            runs.push(JobRun { windows, progress });
+        }
+        info!(
+            "bulk download: {} series, {} windows",
+            self.jobs.len(),
+            runs.iter().map(|r| r.windows.len()).sum::<usize>()
+        );
+
+        let mut in_flight = FuturesUnordered::new();
+        loop {
+            while in_flight.len() < self.concurrency {
+                let Some(idx) = rotation.pop_front() else {
+                    break;
+                };
+                let run = &mut runs[idx];
+                if let Some((window_from, window_to)) = run.windows.pop_front() {
+                    let job = &self.jobs[idx];
+                    in_flight.push(async move {
+                        let (result, retries) =
+                            self.fetch_window(client, job, window_from, window_to).await;
+                        (idx, result, retries)
+                    });
+                }
+                if !run.windows.is_empty() {
+                    rotation.push_back(idx);
+                }
+            }
+
+            let Some((idx, result, retries)) = in_flight.next().await else {
+                break;
+            };
+            report.requests += 1 + retries as usize;
+            report.retries += retries as usize;
+            let run = &mut runs[idx];
+            if run.progress.state != JobState::Running {
+                continue;
+            }
+
+            match result {
+                Ok(bars) => {
+                    run.progress.windows_done += 1;
+                    run.progress.bars += bars;
+                    report.bars += bars;
+                    if run.progress.windows_done == run.progress.windows_total {
+                        run.progress.state = JobState::Completed;
+                    }
+                }
+                Err(Error::Disconnected) => return Err(Error::Disconnected),
+                Err(e) => {
+                    warn!(
+                        "bulk download {} {} failed: {}",
+                        run.progress.job.symbol, run.progress.job.period, e
+                    );
+                    run.windows.clear();
+                    rotation.retain(|i| *i != idx);
+                    run.progress.state = JobState::Failed(e.to_string());
+                }
+            }
+            if run.progress.state != JobState::Running {
+                Self::finish_job(&mut report, &run.progress);
+            }
+            on_progress(&run.progress);
+        }
+
+        Ok(report)
+    }
+
+    // missing parts of [from, to), split into request windows
+    fn plan(&self, job: &BulkJob, from: i64, to: i64) -> Result<VecDeque<(i64, i64)>, Error> {
+        let window = request_window_ms(job.period)?;
+        let mut windows = VecDeque::new();
+        for (mut missing_from, missing_to) in
+            self.cache
+                .missing_ranges(&job.symbol, job.period, from, to)?
+        {
+            while missing_from < missing_to {
+                let window_to = (missing_from + window).min(missing_to);
+                windows.push_back((missing_from, window_to));
+                missing_from = window_to;
+            }
+        }
+        Ok(windows)
+    }
+
+    // number of bars downloaded and the number of retries
+    async fn fetch_window(
+        &self,
+        client: &Session,
+        job: &BulkJob,
+        from: i64,
+        to: i64,
+    ) -> (Result<usize, Error>, u32) {
+        let mut retries = 0;
+        loop {
+            match self
+                .cache
+                .fetch_range(client, &job.symbol, job.period, from, to)
+                .await
+            {
+                Ok(bars) => return (Ok(bars.len()), retries),
+                Err(e) if is_transient_error(&e) && retries < self.max_retries => {
+                    let delay = self.retry_delay * 2u32.pow(retries);
+                    retries += 1;
+                    warn!(
+                        "bulk download {} {} [{}, {}): {}, retry {} in {:?}",
+                        job.symbol, job.period, from, to, e, retries, delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return (Err(e), retries),
+            }
+        }
+    }
+
+    fn finish_job(report: &mut BulkReport, progress: &BulkProgress) {
+        match &progress.state {
+            JobState::Completed => report.completed.push(progress.job.clone()),
+            JobState::Failed(e) => report.failed.push((progress.job.clone(), e.clone())),
+            JobState::Running => {}
+        }
+    }
+}
+
+// errors worth retrying the same request for
+fn is_transient_error(e: &Error) -> bool {
+    matches!(e, Error::TimeoutError(_) | Error::Disconnect | Error::Io(_))
+}
@@ -0,0 +1,247 @@
+//! Trading sessions and holidays of a symbol.
+//!
+//! `ProtoOaSymbol.schedule` holds weekly intervals in seconds from Sunday 00:00 in
+//! `schedule_time_zone`, `holiday` the days (or parts of days) the market is closed,
+//! optionally recurring every year. The intervals are expanded in the local time zone, so
+//! the sessions follow its DST changes.
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::protos::spotware_message::{ProtoOaHoliday, ProtoOaSymbol};
+use crate::util::symbol_info::SpotwareSymbolInfo;
+use crate::Error;
+
+const SECONDS_PER_DAY: u32 = 86_400;
+
+// how far next_open and next_close look ahead, a bit more than a year of holidays
+const SEARCH_HORIZON_WEEKS: i64 = 54;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TradingSession {
+    pub open: DateTime<Utc>,
+    pub close: DateTime<Utc>,
+}
+
+impl TradingSession {
+    pub fn contains(&self, at: DateTime<Utc>) -> bool {
+        self.open <= at && at < self.close
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Holiday {
+    pub name: String,
+    pub time_zone: Tz,
+    pub date: NaiveDate,
+    /// Repeats on the same day every year
+    pub is_recurring: bool,
+    /// Seconds from 00:00 of the day, the whole day when not set
+    pub start_second: Option<u32>,
+    pub end_second: Option<u32>,
+}
+
+impl Holiday {
+    pub fn from_proto(holiday: &ProtoOaHoliday) -> Result<Self, Error> {
+        let date = NaiveDate::from_ymd_opt(1970, 1, 1)
+            .and_then(|epoch| epoch.checked_add_signed(Duration::days(holiday.holiday_date)))
+            .ok_or_else(|| {
+                Error::String(format!("invalid holiday date {}", holiday.holiday_date))
+            })?;
+        Ok(Self {
+            name: holiday.name.clone(),
+            time_zone: parse_time_zone(&holiday.schedule_time_zone)?,
+            date,
+            is_recurring: holiday.is_recurring,
+            start_second: holiday.start_second.map(|s| s.max(0) as u32),
+            end_second: holiday.end_second.map(|s| s.max(0) as u32),
+        })
+    }
+
+    // The closed window of the holiday in `year`, if it falls in that year.
+    fn window(&self, year: i32) -> Option<TradingSession> {
+        let date = if self.is_recurring {
+            // Feb 29 only recurs on leap years
+            NaiveDate::from_ymd_opt(year, self.date.month(), self.date.day())?
+        } else if self.date.year() == year {
+            self.date
+        } else {
+            return None;
+        };
+        let day = date.and_hms_opt(0, 0, 0)?;
+        let start = self.start_second.unwrap_or(0);
+        let end = self.end_second.unwrap_or(SECONDS_PER_DAY);
+        Some(TradingSession {
+            open: to_utc(&self.time_zone, day + Duration::seconds(start as i64)),
+            close: to_utc(&self.time_zone, day + Duration::seconds(end as i64)),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TradingCalendar {
+    time_zone: Tz,
+    /// (start, end) seconds from Sunday 00:00 local time, sorted
+    schedule: Vec<(u32, u32)>,
+    holidays: Vec<Holiday>,
+}
+
+impl TradingCalendar {
+    pub fn new(time_zone: Tz, mut schedule: Vec<(u32, u32)>, holidays: Vec<Holiday>) -> Self {
+        schedule.retain(|(start, end)| start < end);
+        schedule.sort_unstable();
+        Self {
+            time_zone,
+            schedule,
+            holidays,
+        }
+    }
+
+    pub fn from_symbol(symbol: &ProtoOaSymbol) -> Result<Self, Error> {
+        let time_zone = parse_time_zone(symbol.schedule_time_zone.as_deref().unwrap_or("UTC"))?;
+        let schedule = symbol
+            .schedule
+            .iter()
+            .map(|i| (i.start_second, i.end_second))
+            .collect();
+        let holidays = symbol
+            .holiday
+            .iter()
+            .map(Holiday::from_proto)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(time_zone, schedule, holidays))
+    }
+
+    pub fn time_zone(&self) -> Tz {
+        self.time_zone
+    }
+
+    pub fn holidays(&self) -> &[Holiday] {
+        &self.holidays
+    }
+
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        !self.sessions(at, at + Duration::milliseconds(1)).is_empty()
+    }
+
+    // Start of the next session after `at`, None when the market stays closed (or open)
+    // for the search horizon.
+    pub fn next_open(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let end = at + Duration::weeks(SEARCH_HORIZON_WEEKS);
+        self.sessions(at, end)
+            .into_iter()
+            .map(|s| s.open)
+            .find(|open| *open > at && *open < end)
+    }
+
+    // End of the current session, or of the next one when the market is closed.
+    pub fn next_close(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let end = at + Duration::weeks(SEARCH_HORIZON_WEEKS);
+        self.sessions(at, end)
+            .into_iter()
+            .map(|s| s.close)
+            .find(|close| *close > at && *close < end)
+    }
+
+    // Holiday closing the market at `at`, if any.
+    pub fn holiday_at(&self, at: DateTime<Utc>) -> Option<&Holiday> {
+        self.holidays.iter().find(|h| {
+            (at.year() - 1..=at.year() + 1)
+                .filter_map(|year| h.window(year))
+                .any(|w| w.contains(at))
+        })
+    }
+
+    // Sessions overlapping [from, to), sorted. Contiguous intervals are merged and the
+    // holidays cut out, the first and last session are not clipped to the range.
+    pub fn sessions(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<TradingSession> {
+        if from >= to || self.schedule.is_empty() {
+            return Vec::new();
+        }
+        // a week on both sides, so sessions crossing the range ends are complete
+        let begin = from - Duration::weeks(1);
+        let end = to + Duration::weeks(1);
+
+        let mut sessions: Vec<TradingSession> = Vec::new();
+        let mut week = week_start(begin.with_timezone(&self.time_zone).date_naive());
+        while to_utc(&self.time_zone, week) < end {
+            for (start, stop) in &self.schedule {
+                let session = TradingSession {
+                    open: to_utc(&self.time_zone, week + Duration::seconds(*start as i64)),
+                    close: to_utc(&self.time_zone, week + Duration::seconds(*stop as i64)),
+                };
+                match sessions.last_mut() {
+                    Some(last) if session.open <= last.close => {
+                        last.close = last.close.max(session.close)
+                    }
+                    _ => sessions.push(session),
+                }
+            }
+            week += Duration::weeks(1);
+        }
+
+        for year in begin.year() - 1..=end.year() {
+            for window in self.holidays.iter().filter_map(|h| h.window(year)) {
+                sessions = subtract(sessions, &window);
+            }
+        }
+
+        sessions.retain(|s| s.close > from && s.open < to);
+        sessions
+    }
+}
+
+impl TryFrom<&SpotwareSymbolInfo> for TradingCalendar {
+    type Error = Error;
+
+    fn try_from(info: &SpotwareSymbolInfo) -> Result<Self, Self::Error> {
+        Self::from_symbol(&info.info)
+    }
+}
+
+fn parse_time_zone(name: &str) -> Result<Tz, Error> {
+    name.parse::<Tz>()
+        .map_err(|e| Error::String(format!("unknown schedule time zone {}: {}", name, e)))
+}
+
+// Sunday 00:00 of the week of `date`
+fn week_start(date: NaiveDate) -> NaiveDateTime {
+    let sunday = date - Duration::days(date.weekday().num_days_from_sunday() as i64);
+    sunday.and_hms_opt(0, 0, 0).unwrap_or_default()
+}
+
+// Local time to UTC. A time repeated at the end of DST maps to its first occurrence, a
+// time skipped at the start of DST to the hour after.
+fn to_utc(tz: &Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => t.with_timezone(&Utc),
+        LocalResult::None => tz
+            .from_local_datetime(&(local + Duration::hours(1)))
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc.from_utc_datetime(&local)),
+    }
+}
+
+// Removes the closed window from sorted, disjoint sessions.
+fn subtract(sessions: Vec<TradingSession>, closed: &TradingSession) -> Vec<TradingSession> {
+    let mut result = Vec::with_capacity(sessions.len() + 1);
+    for s in sessions {
+        if s.close <= closed.open || s.open >= closed.close {
+            result.push(s);
+            continue;
+        }
+        if s.open < closed.open {
+            result.push(TradingSession {
+                open: s.open,
+                close: closed.open,
+            });
+        }
+        if s.close > closed.close {
+            result.push(TradingSession {
+                open: closed.close,
+                close: s.close,
+            });
+        }
+    }
+    result
+}
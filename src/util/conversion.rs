@@ -0,0 +1,361 @@
+//! Currency conversion between any two assets.
+//!
+//! A symbol quotes its base asset in its quote asset, so the symbols of the `SymbolStore`
+//! form a graph of assets. The `CurrencyConverter` finds the shortest chain of symbols
+//! between two assets (JPY to USD through USDJPY, CHF to JPY through USDCHF and USDJPY),
+//! caches it, and prices it from the spots it is fed or from trend bars at a past time.
+//! Feed it every `NotifyEvent`. `live_rate` subscribes the spots of a chain on demand.
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::domain::Bar;
+use crate::protos::spotware_message::{ProtoOaLightSymbol, ProtoOaTrendbarPeriod};
+use crate::util::units::Price;
+use crate::util::SymbolStore;
+use crate::{Error, NotifyEvent, Session};
+
+// windows searched for the last bar before a historical time, M1 then H1 to get over
+// weekends and holidays
+const MINUTE_WINDOW: Duration = Duration::hours(1);
+const HOUR_WINDOW: Duration = Duration::weeks(4);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversionStep {
+    pub symbol_id: i64,
+    /// The amount is in the quote asset of the symbol, divide by its price
+    pub inverted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionChain {
+    pub from_asset_id: i64,
+    pub to_asset_id: i64,
+    /// Empty when both assets are the same
+    pub steps: Vec<ConversionStep>,
+}
+
+impl ConversionChain {
+    pub fn symbol_ids(&self) -> Vec<i64> {
+        self.steps.iter().map(|step| step.symbol_id).collect()
+    }
+
+    // Rate of the chain from the price of each symbol, None when one is missing.
+    pub fn rate_with<F>(&self, mut price: F) -> Option<f64>
+    where
+        F: FnMut(i64) -> Option<f64>,
+    {
+        self.steps.iter().try_fold(1.0, |rate, step| {
+            let price = price(step.symbol_id).filter(|p| *p > 0.0)?;
+            Some(if step.inverted {
+                rate / price
+            } else {
+                rate * price
+            })
+        })
+    }
+
+    // Chain through the symbols returned by `Session::symbol_for_conversion`, in order.
+    fn from_light_symbols(
+        from_asset_id: i64,
+        to_asset_id: i64,
+        symbols: &[ProtoOaLightSymbol],
+    ) -> Option<Self> {
+        let mut asset_id = from_asset_id;
+        let mut steps = Vec::with_capacity(symbols.len());
+        for symbol in symbols {
+            let base = symbol.base_asset_id?;
+            let quote = symbol.quote_asset_id?;
+            let inverted = if base == asset_id {
+                asset_id = quote;
+                false
+            } else if quote == asset_id {
+                asset_id = base;
+                true
+            } else {
+                return None;
+            };
+            steps.push(ConversionStep {
+                symbol_id: symbol.symbol_id,
+                inverted,
+            });
+        }
+        (asset_id == to_asset_id).then_some(Self {
+            from_asset_id,
+            to_asset_id,
+            steps,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CurrencyConverter {
+    // asset -> (neighbour asset, step to it)
+    graph: HashMap<i64, Vec<(i64, ConversionStep)>>,
+    chains: HashMap<(i64, i64), Option<ConversionChain>>,
+    // symbol -> last bid and ask
+    quotes: HashMap<i64, (Option<Price>, Option<Price>)>,
+    // symbol -> last M1 close, used until the first spot of the symbol
+    fallbacks: HashMap<i64, f64>,
+    // (symbol, unix minute) -> close of the last bar at that time
+    closes: HashMap<(i64, i64), f64>,
+}
+
+impl CurrencyConverter {
+    pub fn new(store: &SymbolStore) -> Self {
+        let mut converter = Self::default();
+        converter.rebuild(store);
+        converter
+    }
+
+    // Rebuilds the asset graph, e.g. after `NotifyEvent::SymbolUpdated`. Quotes are kept.
+    pub fn rebuild(&mut self, store: &SymbolStore) {
+        self.graph.clear();
+        self.chains.clear();
+        for info in store.infos() {
+            let (base, quote) = (info.base_asset_id, info.quote_asset_id);
+            if base == quote {
+                continue;
+            }
+            let symbol_id = info.info.symbol_id;
+            self.graph.entry(base).or_default().push((
+                quote,
+                ConversionStep {
+                    symbol_id,
+                    inverted: false,
+                },
+            ));
+            self.graph.entry(quote).or_default().push((
+                base,
+                ConversionStep {
+                    symbol_id,
+                    inverted: true,
+                },
+            ));
+        }
+    }
+
+    // Shortest chain between two assets in the store, cached.
+    pub fn chain(&mut self, from_asset_id: i64, to_asset_id: i64) -> Option<ConversionChain> {
+        self.chains
+            .entry((from_asset_id, to_asset_id))
+            .or_insert_with(|| shortest_chain(&self.graph, from_asset_id, to_asset_id))
+            .clone()
+    }
+
+    // Chain from the store, or from the server when the store has none.
+    pub async fn resolve_chain(
+        &mut self,
+        session: &Session,
+        from_asset_id: i64,
+        to_asset_id: i64,
+    ) -> Result<ConversionChain, Error> {
+        if let Some(chain) = self.chain(from_asset_id, to_asset_id) {
+            return Ok(chain);
+        }
+        let res = session
+            .symbol_for_conversion(from_asset_id, to_asset_id)
+            .await?;
+        let chain = ConversionChain::from_light_symbols(from_asset_id, to_asset_id, &res.symbol)
+            .ok_or(Error::NoConversion(from_asset_id, to_asset_id))?;
+        self.chains
+            .insert((from_asset_id, to_asset_id), Some(chain.clone()));
+        Ok(chain)
+    }
+
+    pub fn on_event(&mut self, event: &NotifyEvent) {
+        match event {
+            NotifyEvent::SpotEvent(spot) => {
+                self.fallbacks.remove(&spot.symbol_id);
+                let quote = self.quotes.entry(spot.symbol_id).or_default();
+                if let Some(bid) = spot.bid {
+                    quote.0 = Some(Price::from_raw(bid as i64));
+                }
+                if let Some(ask) = spot.ask {
+                    quote.1 = Some(Price::from_raw(ask as i64));
+                }
+            }
+            // the server sends the latest prices again after the resubscription
+            NotifyEvent::ClientDisconnectEvent(_) | NotifyEvent::AccountDisconnectEvent(_) => {
+                self.quotes.clear();
+                self.fallbacks.clear();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn bid(&self, symbol_id: i64) -> Option<Price> {
+        self.quotes.get(&symbol_id)?.0
+    }
+
+    pub fn ask(&self, symbol_id: i64) -> Option<Price> {
+        self.quotes.get(&symbol_id)?.1
+    }
+
+    // Mid price of the last spot, or the side known. Before the first spot, the bar close
+    // fetched by `live_rate`.
+    pub fn price(&self, symbol_id: i64) -> Option<f64> {
+        match self.quotes.get(&symbol_id) {
+            Some((Some(bid), Some(ask))) => Some((bid.to_f64() + ask.to_f64()) / 2.0),
+            Some((Some(price), None)) | Some((None, Some(price))) => Some(price.to_f64()),
+            _ => self.fallbacks.get(&symbol_id).copied(),
+        }
+    }
+
+    // Rate from the spots received so far, None without a chain or a price.
+    pub fn rate(&mut self, from_asset_id: i64, to_asset_id: i64) -> Option<f64> {
+        let chain = self.chain(from_asset_id, to_asset_id)?;
+        chain.rate_with(|symbol_id| self.price(symbol_id))
+    }
+
+    pub fn convert(&mut self, amount: f64, from_asset_id: i64, to_asset_id: i64) -> Option<f64> {
+        Some(amount * self.rate(from_asset_id, to_asset_id)?)
+    }
+
+    // Rate from the live spots. Subscribes the spots of the chain that are not subscribed
+    // yet, a symbol without a spot so far is priced from its last M1 bar until one arrives.
+    pub async fn live_rate(
+        &mut self,
+        session: &mut Session,
+        from_asset_id: i64,
+        to_asset_id: i64,
+    ) -> Result<f64, Error> {
+        let chain = self
+            .resolve_chain(session, from_asset_id, to_asset_id)
+            .await?;
+        let symbol_ids = chain.symbol_ids();
+        let missing: Vec<i64> = symbol_ids
+            .iter()
+            .copied()
+            .filter(|id| !session.subscribed_spots().contains(id))
+            .collect();
+        if !missing.is_empty() {
+            session.subscribe_spot(missing).await?;
+        }
+        for symbol_id in symbol_ids {
+            if self.price(symbol_id).is_none() {
+                let close = self.close_at(session, symbol_id, Utc::now()).await?;
+                self.fallbacks.insert(symbol_id, close);
+            }
+        }
+        chain
+            .rate_with(|symbol_id| self.price(symbol_id))
+            .ok_or(Error::NoConversion(from_asset_id, to_asset_id))
+    }
+
+    // Rate at a past time from the close of the last bar of each symbol at that time.
+    pub async fn historical_rate(
+        &mut self,
+        session: &Session,
+        from_asset_id: i64,
+        to_asset_id: i64,
+        at: DateTime<Utc>,
+    ) -> Result<f64, Error> {
+        let chain = self
+            .resolve_chain(session, from_asset_id, to_asset_id)
+            .await?;
+        let mut rate = 1.0;
+        for step in &chain.steps {
+            let price = self.close_at(session, step.symbol_id, at).await?;
+            if step.inverted {
+                rate /= price;
+            } else {
+                rate *= price;
+            }
+        }
+        Ok(rate)
+    }
+
+    // Values an amount, e.g. the profit of a deal, at the time it was realized.
+    pub async fn convert_at(
+        &mut self,
+        session: &Session,
+        amount: f64,
+        from_asset_id: i64,
+        to_asset_id: i64,
+        at: DateTime<Utc>,
+    ) -> Result<f64, Error> {
+        let rate = self
+            .historical_rate(session, from_asset_id, to_asset_id, at)
+            .await?;
+        Ok(amount * rate)
+    }
+
+    // Close of the last bar opened at or before `at`, cached by minute.
+    async fn close_at(
+        &mut self,
+        session: &Session,
+        symbol_id: i64,
+        at: DateTime<Utc>,
+    ) -> Result<f64, Error> {
+        let key = (symbol_id, at.timestamp().div_euclid(60));
+        if let Some(close) = self.closes.get(&key) {
+            return Ok(*close);
+        }
+        let windows = [
+            (ProtoOaTrendbarPeriod::M1, MINUTE_WINDOW),
+            (ProtoOaTrendbarPeriod::H1, HOUR_WINDOW),
+        ];
+        for (period, window) in windows {
+            let res = session
+                .get_trend_bars(
+                    (at - window).timestamp_millis(),
+                    at.timestamp_millis(),
+                    period as i32,
+                    symbol_id,
+                    None,
+                )
+                .await?;
+            let close = res
+                .trendbar
+                .iter()
+                .map(Bar::from)
+                .filter(|bar| bar.time <= at)
+                .max_by_key(|bar| bar.time)
+                .map(|bar| bar.close.to_f64())
+                .filter(|close| *close > 0.0);
+            if let Some(close) = close {
+                self.closes.insert(key, close);
+                return Ok(close);
+            }
+        }
+        Err(Error::NoPrice(symbol_id))
+    }
+}
+
+// Breadth first search, so the chain uses as few symbols as possible.
+fn shortest_chain(
+    graph: &HashMap<i64, Vec<(i64, ConversionStep)>>,
+    from_asset_id: i64,
+    to_asset_id: i64,
+) -> Option<ConversionChain> {
+    // asset -> (previous asset, step from it)
+    let mut visited: HashMap<i64, Option<(i64, ConversionStep)>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    visited.insert(from_asset_id, None);
+    queue.push_back(from_asset_id);
+    while let Some(asset_id) = queue.pop_front() {
+        if asset_id == to_asset_id {
+            break;
+        }
+        for (next, step) in graph.get(&asset_id).into_iter().flatten() {
+            if !visited.contains_key(next) {
+                visited.insert(*next, Some((asset_id, *step)));
+                queue.push_back(*next);
+            }
+        }
+    }
+
+    let mut steps = Vec::new();
+    let mut asset_id = to_asset_id;
+    while let Some((previous, step)) = visited.get(&asset_id)?.as_ref() {
+        steps.push(*step);
+        asset_id = *previous;
+    }
+    steps.reverse();
+    Some(ConversionChain {
+        from_asset_id,
+        to_asset_id,
+        steps,
+    })
+}
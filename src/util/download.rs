@@ -1,11 +1,21 @@
+use std::path::Path;
+
 use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
 use tracing::info;
 
 use crate::{
-    client::Session, protos::spotware_message::ProtoOaGetTrendbarsRes,
-    util::time_util::from_mill_seconds, Error,
+    client::{historical::max_trend_bar_range_ms, Session},
+    protos::spotware_message::ProtoOaGetTrendbarsRes,
+    util::{
+        bar_cache::period_name,
+        export::{ExportFormat, ExportMetadata, RecordWriter},
+        time_util::from_mill_seconds,
+        units::Price,
+    },
+    Error,
 };
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Kline {
     /// latest timestamp of last received trade
     pub timestamp: DateTime<Utc>,
@@ -40,6 +50,53 @@ pub async fn download_asset(
     start: &DateTime<Utc>,
     end: &DateTime<Utc>,
 ) -> Result<Vec<Kline>, Error> {
+    let mut candles = Vec::new();
+    download_windows(client, symbol, period, start, end, |window| {
+        candles.extend(window);
+        Ok(())
+    })
+    .await?;
+    Ok(candles)
+}
+
+// Same as download_asset, bars are written to `path` as each request returns instead
+// of being collected in memory. Returns the number of bars written.
+pub async fn download_asset_to_file(
+    client: &Session,
+    symbol: &str,
+    period: i32,
+    start: &DateTime<Utc>,
+    end: &DateTime<Utc>,
+    path: impl AsRef<Path>,
+    format: &ExportFormat,
+) -> Result<usize, Error> {
+    let metadata = ExportMetadata::new(symbol, Some(&period_name(period)));
+    let mut writer = RecordWriter::create(path, format, &metadata)?;
+    let mut count = 0;
+    download_windows(client, symbol, period, start, end, |window| {
+        for kline in window.iter() {
+            writer.write(kline)?;
+        }
+        count += window.len();
+        Ok(())
+    })
+    .await?;
+    writer.finish()?;
+    Ok(count)
+}
+
+// Requests the bars window by window and hands each window to `on_window`, oldest first.
+async fn download_windows<F>(
+    client: &Session,
+    symbol: &str,
+    period: i32,
+    start: &DateTime<Utc>,
+    end: &DateTime<Utc>,
+    mut on_window: F,
+) -> Result<(), Error>
+where
+    F: FnMut(Vec<Kline>) -> Result<(), Error>,
+{
     let symbol_id = client.store.get_id_by_name(symbol);
     if symbol_id.is_none() {
         return Err(Error::String(format!(
@@ -49,7 +106,7 @@ pub async fn download_asset(
     }
     let symbol_id = symbol_id.unwrap();
     // Validation: toTimestamp - fromTimestamp <= X, where X depends on series period:
-    // M1, M2, M3, M4, M5: 302_400_000 (5 weeks);
+    // M1, M2, M3, M4, M5: 3_024_000_000 (5 weeks);
     // M10, M15, M30, H1: 21168000000 (35 weeks),
     // H4, H12, D1: 31_622_400_000 (1 year);
     // W1, MN1: 158112000000 (5 years).
@@ -68,13 +125,7 @@ pub async fn download_asset(
         from_mill_seconds(from_timestamp),
         from_mill_seconds(to_timestamp)
     );
-    let incrment: i64 = match period {
-        1 | 2 | 3 | 4 | 5 => 302_400_000, // M1 M2 M3 M4 M5 5weeks
-        6 | 7 | 8 | 9 => 21_168_000_000,  // M10 M15 M30 H1 35weeks
-        10 | 11 | 12 => 31_622_400_000,   // H4 H12 D1      1years
-        13 | 14 => 158_112_000_000,       // W1 Mn1         5years
-        _ => return Err(Error::PeriodParamError(period)),
-    };
+    let incrment = request_window_ms(period)?;
     /*match period {
         ProtoOaTrendbarPeriod::M1
         | ProtoOaTrendbarPeriod::M2
@@ -91,7 +142,7 @@ pub async fn download_asset(
         ProtoOaTrendbarPeriod::W1 | ProtoOaTrendbarPeriod::Mn1 => 158112000000 as i64,
     };*/
 
-    let mut candles = Vec::new();
+    let mut last_timestamp = None;
     while from_timestamp < to_timestamp {
         let mut to = from_timestamp + incrment;
         if to > to_timestamp {
@@ -107,12 +158,26 @@ pub async fn download_asset(
                 None,
             )
             .await?;
+        let mut candles = Vec::new();
         save_bar(event, &mut candles, to);
+        // the first bar of a window may repeat the last one of the previous window
+        if last_timestamp.is_some() && candles.first().map(|k| k.timestamp) == last_timestamp {
+            candles.remove(0);
+        }
+        if let Some(last) = candles.last() {
+            last_timestamp = Some(last.timestamp);
+        }
+        on_window(candles)?;
 
         from_timestamp += incrment;
     }
 
-    Ok(candles)
+    Ok(())
+}
+
+// Range requested at once for a period, within the server limit.
+pub(crate) fn request_window_ms(period: i32) -> Result<i64, Error> {
+    max_trend_bar_range_ms(period)
 }
 
 fn save_bar(event: ProtoOaGetTrendbarsRes, candles: &mut Vec<Kline>, last_to_timestamp: i64) {
@@ -126,10 +191,10 @@ fn save_bar(event: ProtoOaGetTrendbarsRes, candles: &mut Vec<Kline>, last_to_tim
         let _open = bar.delta_open.unwrap_or(0) as i64 + _low;
         let _high = bar.delta_high.unwrap_or(0) as i64 + _low;
         let _close = bar.delta_close.unwrap_or(0) as i64 + _low;
-        let low = _low as f64 / 100_000.0;
-        let open = _open as f64 / 100_000.0;
-        let high = _high as f64 / 100_000.0;
-        let close = _close as f64 / 100_000.0;
+        let low = Price::from_raw(_low).to_f64();
+        let open = Price::from_raw(_open).to_f64();
+        let high = Price::from_raw(_high).to_f64();
+        let close = Price::from_raw(_close).to_f64();
         let vol = bar.volume as u64;
 
         let kline = Kline {
@@ -0,0 +1,756 @@
+//! CSV export and import of bars, ticks, quotes and deals.
+//! Arrow IPC and Parquet files are available with the `arrow` feature, see `arrow_export`.
+//!
+//! CSV files start with `# key: value` metadata lines (symbol, period, ...), followed by
+//! a header line and one record per line; `pandas.read_csv(path, comment="#")` reads them.
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDateTime;
+
+#[cfg(feature = "arrow")]
+use crate::util::arrow_export::{self, ArrowFileWriter, ArrowFormat};
+use crate::{
+    protos::spotware_message::ProtoOaDeal,
+    util::{
+        bar_gen::Candle, bar_gen::Quote, download::Kline, tick_download::Tick,
+        time_util::from_mill_seconds,
+    },
+    Error,
+};
+
+/// How timestamps are written to and read from CSV files.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TimestampFormat {
+    #[default]
+    UnixMillis,
+    UnixSeconds,
+    /// e.g. `2024-01-02T03:04:05.678Z`
+    Rfc3339,
+    /// chrono strftime pattern, always UTC, e.g. `%Y-%m-%d %H:%M:%S%.3f`
+    Custom(String),
+}
+
+impl TimestampFormat {
+    // Err on a Custom pattern chrono can't format or parse with.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let TimestampFormat::Custom(pattern) = self {
+            if StrftimeItems::new(pattern).any(|item| item == Item::Error) {
+                return Err(Error::String(format!("bad timestamp pattern {:?}", pattern)));
+            }
+        }
+        Ok(())
+    }
+
+    // A Custom pattern that fails validate() falls back to Unix milliseconds.
+    pub fn format(&self, timestamp: i64) -> String {
+        match self {
+            TimestampFormat::UnixMillis => timestamp.to_string(),
+            TimestampFormat::UnixSeconds => (timestamp / 1000).to_string(),
+            TimestampFormat::Rfc3339 => {
+                from_mill_seconds(timestamp).to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+            }
+            TimestampFormat::Custom(pattern) => {
+                let mut value = String::new();
+                match write!(value, "{}", from_mill_seconds(timestamp).format(pattern)) {
+                    Ok(()) => value,
+                    Err(_) => timestamp.to_string(),
+                }
+            }
+        }
+    }
+
+    pub fn parse(&self, value: &str) -> Result<i64, Error> {
+        let bad = || Error::String(format!("bad timestamp {:?} for {:?}", value, self));
+        match self {
+            TimestampFormat::UnixMillis => value.parse().map_err(|_| bad()),
+            TimestampFormat::UnixSeconds => value
+                .parse::<i64>()
+                .map(|secs| secs * 1000)
+                .map_err(|_| bad()),
+            TimestampFormat::Rfc3339 => chrono::DateTime::parse_from_rfc3339(value)
+                .map(|dt| dt.timestamp_millis())
+                .map_err(|_| bad()),
+            TimestampFormat::Custom(pattern) => NaiveDateTime::parse_from_str(value, pattern)
+                .map(|dt| dt.and_utc().timestamp_millis())
+                .map_err(|_| bad()),
+        }
+    }
+}
+
+/// Symbol, period and free form key/values stored with the data.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ExportMetadata {
+    pub symbol: Option<String>,
+    pub period: Option<String>,
+    pub extra: BTreeMap<String, String>,
+}
+
+impl ExportMetadata {
+    pub fn new(symbol: &str, period: Option<&str>) -> Self {
+        Self {
+            symbol: Some(symbol.to_string()),
+            period: period.map(|p| p.to_string()),
+            extra: BTreeMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> &mut Self {
+        self.extra.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub(crate) fn to_pairs(&self) -> Vec<(String, String)> {
+        let mut pairs = Vec::new();
+        if let Some(symbol) = &self.symbol {
+            pairs.push(("symbol".to_string(), symbol.clone()));
+        }
+        if let Some(period) = &self.period {
+            pairs.push(("period".to_string(), period.clone()));
+        }
+        for (key, value) in self.extra.iter() {
+            pairs.push((key.clone(), value.clone()));
+        }
+        pairs
+    }
+
+    pub(crate) fn from_pairs(pairs: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut metadata = Self::default();
+        for (key, value) in pairs {
+            match key.as_str() {
+                "symbol" => metadata.symbol = Some(value),
+                "period" => metadata.period = Some(value),
+                _ => {
+                    metadata.extra.insert(key, value);
+                }
+            }
+        }
+        metadata
+    }
+}
+
+/// A record type that can be exported, one CSV line or one Arrow row.
+pub trait ExportRecord: Sized + Clone {
+    fn csv_header() -> &'static [&'static str];
+    fn to_csv_row(&self, format: &TimestampFormat) -> Vec<String>;
+    fn from_csv_row(row: &csv::StringRecord, format: &TimestampFormat) -> Result<Self, Error>;
+
+    #[cfg(feature = "arrow")]
+    fn arrow_fields() -> Vec<arrow::datatypes::Field>;
+    #[cfg(feature = "arrow")]
+    fn to_arrow_columns(records: &[Self]) -> Vec<arrow::array::ArrayRef>;
+    #[cfg(feature = "arrow")]
+    fn from_arrow_batch(batch: &arrow::record_batch::RecordBatch) -> Result<Vec<Self>, Error>;
+}
+
+/// Flat view of a `ProtoOaDeal`, money values scaled by the deal money digits.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DealRecord {
+    pub deal_id: i64,
+    pub order_id: i64,
+    pub position_id: i64,
+    pub symbol_id: i64,
+    /// BUY or SELL
+    pub trade_side: String,
+    pub volume: i64,
+    pub filled_volume: i64,
+    pub execution_price: Option<f64>,
+    /// Unix time in milliseconds
+    pub create_timestamp: i64,
+    /// Unix time in milliseconds
+    pub execution_timestamp: i64,
+    pub deal_status: String,
+    pub commission: Option<f64>,
+    /// Set on deals closing a position
+    pub entry_price: Option<f64>,
+    pub gross_profit: Option<f64>,
+    pub swap: Option<f64>,
+    pub balance: Option<f64>,
+}
+
+impl From<&ProtoOaDeal> for DealRecord {
+    fn from(deal: &ProtoOaDeal) -> Self {
+        let detail = deal.close_position_detail.as_ref();
+        let digits = deal
+            .money_digits
+            .or(detail.and_then(|d| d.money_digits))
+            .unwrap_or(2);
+        let scale = |v: i64| v as f64 / 10f64.powi(digits as i32);
+        Self {
+            deal_id: deal.deal_id,
+            order_id: deal.order_id,
+            position_id: deal.position_id,
+            symbol_id: deal.symbol_id,
+            trade_side: deal.trade_side().as_str_name().to_string(),
+            volume: deal.volume,
+            filled_volume: deal.filled_volume,
+            execution_price: deal.execution_price,
+            create_timestamp: deal.create_timestamp,
+            execution_timestamp: deal.execution_timestamp,
+            deal_status: deal.deal_status().as_str_name().to_string(),
+            commission: deal.commission.map(scale),
+            entry_price: detail.map(|d| d.entry_price),
+            gross_profit: detail.map(|d| scale(d.gross_profit)),
+            swap: detail.map(|d| scale(d.swap)),
+            balance: detail.map(|d| scale(d.balance)),
+        }
+    }
+}
+
+impl From<&Candle> for Kline {
+    fn from(candle: &Candle) -> Self {
+        Self {
+            timestamp: from_mill_seconds(candle.timestamp),
+            open: candle.open,
+            high: candle.high,
+            low: candle.low,
+            close: candle.close,
+            vol: candle.vol,
+        }
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                              CSV                                 |
+//+------------------------------------------------------------------+
+
+pub struct CsvWriter<W: Write, T: ExportRecord> {
+    writer: csv::Writer<W>,
+    format: TimestampFormat,
+    _record: PhantomData<T>,
+}
+
+impl<T: ExportRecord> CsvWriter<BufWriter<File>, T> {
+    pub fn create(
+        path: impl AsRef<Path>,
+        metadata: &ExportMetadata,
+        format: TimestampFormat,
+    ) -> Result<Self, Error> {
+        format.validate()?;
+        let file = BufWriter::new(File::create(path)?);
+        Self::new(file, metadata, format)
+    }
+}
+
+impl<W: Write, T: ExportRecord> CsvWriter<W, T> {
+    // Writes the metadata and the header line.
+    pub fn new(
+        mut inner: W,
+        metadata: &ExportMetadata,
+        format: TimestampFormat,
+    ) -> Result<Self, Error> {
+        format.validate()?;
+        for (key, value) in metadata.to_pairs() {
+            writeln!(inner, "# {}: {}", key, value)?;
+        }
+        let mut writer = csv::Writer::from_writer(inner);
+        writer.write_record(T::csv_header()).map_err(csv_error)?;
+        Ok(Self {
+            writer,
+            format,
+            _record: PhantomData,
+        })
+    }
+
+    pub fn write(&mut self, record: &T) -> Result<(), Error> {
+        self.writer
+            .write_record(record.to_csv_row(&self.format))
+            .map_err(csv_error)
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// Read a CSV file written by CsvWriter, the format must match the one used to write it.
+pub fn read_csv<T: ExportRecord>(
+    path: impl AsRef<Path>,
+    format: &TimestampFormat,
+) -> Result<(ExportMetadata, Vec<T>), Error> {
+    format.validate()?;
+    let mut pairs = Vec::new();
+    for line in BufReader::new(File::open(path.as_ref())?).lines() {
+        let line = line?;
+        match line.strip_prefix('#') {
+            Some(comment) => {
+                if let Some((key, value)) = comment.split_once(':') {
+                    pairs.push((key.trim().to_string(), value.trim().to_string()));
+                }
+            }
+            None => break,
+        }
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_path(path)
+        .map_err(csv_error)?;
+    let mut records = Vec::new();
+    for row in reader.records() {
+        records.push(T::from_csv_row(&row.map_err(csv_error)?, format)?);
+    }
+    Ok((ExportMetadata::from_pairs(pairs), records))
+}
+
+//+------------------------------------------------------------------+
+//|                         File format                              |
+//+------------------------------------------------------------------+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv(TimestampFormat),
+    #[cfg(feature = "arrow")]
+    ArrowIpc,
+    #[cfg(feature = "arrow")]
+    Parquet,
+}
+
+/// Writes records one by one to a file of any supported format.
+pub enum RecordWriter<T: ExportRecord> {
+    Csv(CsvWriter<BufWriter<File>, T>),
+    #[cfg(feature = "arrow")]
+    Arrow(ArrowFileWriter<T>),
+}
+
+impl<T: ExportRecord> RecordWriter<T> {
+    pub fn create(
+        path: impl AsRef<Path>,
+        format: &ExportFormat,
+        metadata: &ExportMetadata,
+    ) -> Result<Self, Error> {
+        Ok(match format {
+            ExportFormat::Csv(ts_format) => {
+                RecordWriter::Csv(CsvWriter::create(path, metadata, ts_format.clone())?)
+            }
+            #[cfg(feature = "arrow")]
+            ExportFormat::ArrowIpc => {
+                RecordWriter::Arrow(ArrowFileWriter::create(path, ArrowFormat::Ipc, metadata)?)
+            }
+            #[cfg(feature = "arrow")]
+            ExportFormat::Parquet => RecordWriter::Arrow(ArrowFileWriter::create(
+                path,
+                ArrowFormat::Parquet,
+                metadata,
+            )?),
+        })
+    }
+
+    pub fn write(&mut self, record: &T) -> Result<(), Error> {
+        match self {
+            RecordWriter::Csv(writer) => writer.write(record),
+            #[cfg(feature = "arrow")]
+            RecordWriter::Arrow(writer) => writer.write(record),
+        }
+    }
+
+    pub fn finish(self) -> Result<(), Error> {
+        match self {
+            RecordWriter::Csv(writer) => writer.finish(),
+            #[cfg(feature = "arrow")]
+            RecordWriter::Arrow(writer) => writer.finish(),
+        }
+    }
+}
+
+// Write all records to a new file.
+pub fn write_records<T: ExportRecord>(
+    path: impl AsRef<Path>,
+    format: &ExportFormat,
+    metadata: &ExportMetadata,
+    records: &[T],
+) -> Result<(), Error> {
+    let mut writer = RecordWriter::create(path, format, metadata)?;
+    for record in records {
+        writer.write(record)?;
+    }
+    writer.finish()
+}
+
+// Read a file written by RecordWriter.
+pub fn read_records<T: ExportRecord>(
+    path: impl AsRef<Path>,
+    format: &ExportFormat,
+) -> Result<(ExportMetadata, Vec<T>), Error> {
+    match format {
+        ExportFormat::Csv(ts_format) => read_csv(path, ts_format),
+        #[cfg(feature = "arrow")]
+        ExportFormat::ArrowIpc => arrow_export::read_arrow_file(path, ArrowFormat::Ipc),
+        #[cfg(feature = "arrow")]
+        ExportFormat::Parquet => arrow_export::read_arrow_file(path, ArrowFormat::Parquet),
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                           Records                                |
+//+------------------------------------------------------------------+
+
+impl ExportRecord for Kline {
+    fn csv_header() -> &'static [&'static str] {
+        &["timestamp", "open", "high", "low", "close", "volume"]
+    }
+
+    fn to_csv_row(&self, format: &TimestampFormat) -> Vec<String> {
+        vec![
+            format.format(self.timestamp.timestamp_millis()),
+            self.open.to_string(),
+            self.high.to_string(),
+            self.low.to_string(),
+            self.close.to_string(),
+            self.vol.to_string(),
+        ]
+    }
+
+    fn from_csv_row(row: &csv::StringRecord, format: &TimestampFormat) -> Result<Self, Error> {
+        Ok(Self {
+            timestamp: from_mill_seconds(format.parse(field(row, 0)?)?),
+            open: parse_field(row, 1)?,
+            high: parse_field(row, 2)?,
+            low: parse_field(row, 3)?,
+            close: parse_field(row, 4)?,
+            vol: parse_field(row, 5)?,
+        })
+    }
+
+    #[cfg(feature = "arrow")]
+    fn arrow_fields() -> Vec<arrow::datatypes::Field> {
+        use arrow::datatypes::DataType;
+        vec![
+            arrow_export::timestamp_field("timestamp", false),
+            arrow_export::field("open", DataType::Float64, false),
+            arrow_export::field("high", DataType::Float64, false),
+            arrow_export::field("low", DataType::Float64, false),
+            arrow_export::field("close", DataType::Float64, false),
+            arrow_export::field("volume", DataType::UInt64, false),
+        ]
+    }
+
+    #[cfg(feature = "arrow")]
+    fn to_arrow_columns(records: &[Self]) -> Vec<arrow::array::ArrayRef> {
+        use arrow_export::{f64_array, timestamp_array, u64_array};
+        vec![
+            timestamp_array(records.iter().map(|k| Some(k.timestamp.timestamp_millis()))),
+            f64_array(records.iter().map(|k| Some(k.open))),
+            f64_array(records.iter().map(|k| Some(k.high))),
+            f64_array(records.iter().map(|k| Some(k.low))),
+            f64_array(records.iter().map(|k| Some(k.close))),
+            u64_array(records.iter().map(|k| Some(k.vol))),
+        ]
+    }
+
+    #[cfg(feature = "arrow")]
+    fn from_arrow_batch(batch: &arrow::record_batch::RecordBatch) -> Result<Vec<Self>, Error> {
+        use arrow_export::{f64_column, timestamp_column, u64_column};
+        let timestamp = timestamp_column(batch, "timestamp")?;
+        let open = f64_column(batch, "open")?;
+        let high = f64_column(batch, "high")?;
+        let low = f64_column(batch, "low")?;
+        let close = f64_column(batch, "close")?;
+        let volume = u64_column(batch, "volume")?;
+        Ok((0..batch.num_rows())
+            .map(|i| Kline {
+                timestamp: from_mill_seconds(timestamp.value(i)),
+                open: open.value(i),
+                high: high.value(i),
+                low: low.value(i),
+                close: close.value(i),
+                vol: volume.value(i),
+            })
+            .collect())
+    }
+}
+
+impl ExportRecord for Tick {
+    fn csv_header() -> &'static [&'static str] {
+        &["timestamp", "price"]
+    }
+
+    fn to_csv_row(&self, format: &TimestampFormat) -> Vec<String> {
+        vec![format.format(self.timestamp), self.price.to_string()]
+    }
+
+    fn from_csv_row(row: &csv::StringRecord, format: &TimestampFormat) -> Result<Self, Error> {
+        Ok(Self {
+            timestamp: format.parse(field(row, 0)?)?,
+            price: parse_field(row, 1)?,
+        })
+    }
+
+    #[cfg(feature = "arrow")]
+    fn arrow_fields() -> Vec<arrow::datatypes::Field> {
+        use arrow::datatypes::DataType;
+        vec![
+            arrow_export::timestamp_field("timestamp", false),
+            arrow_export::field("price", DataType::Float64, false),
+        ]
+    }
+
+    #[cfg(feature = "arrow")]
+    fn to_arrow_columns(records: &[Self]) -> Vec<arrow::array::ArrayRef> {
+        use arrow_export::{f64_array, timestamp_array};
+        vec![
+            timestamp_array(records.iter().map(|t| Some(t.timestamp))),
+            f64_array(records.iter().map(|t| Some(t.price))),
+        ]
+    }
+
+    #[cfg(feature = "arrow")]
+    fn from_arrow_batch(batch: &arrow::record_batch::RecordBatch) -> Result<Vec<Self>, Error> {
+        use arrow_export::{f64_column, timestamp_column};
+        let timestamp = timestamp_column(batch, "timestamp")?;
+        let price = f64_column(batch, "price")?;
+        Ok((0..batch.num_rows())
+            .map(|i| Tick {
+                timestamp: timestamp.value(i),
+                price: price.value(i),
+            })
+            .collect())
+    }
+}
+
+impl ExportRecord for Quote {
+    fn csv_header() -> &'static [&'static str] {
+        &["timestamp", "bid", "ask"]
+    }
+
+    fn to_csv_row(&self, format: &TimestampFormat) -> Vec<String> {
+        vec![
+            format.format(self.timestamp),
+            self.bid.to_string(),
+            self.ask.to_string(),
+        ]
+    }
+
+    fn from_csv_row(row: &csv::StringRecord, format: &TimestampFormat) -> Result<Self, Error> {
+        Ok(Self {
+            timestamp: format.parse(field(row, 0)?)?,
+            bid: parse_field(row, 1)?,
+            ask: parse_field(row, 2)?,
+        })
+    }
+
+    #[cfg(feature = "arrow")]
+    fn arrow_fields() -> Vec<arrow::datatypes::Field> {
+        use arrow::datatypes::DataType;
+        vec![
+            arrow_export::timestamp_field("timestamp", false),
+            arrow_export::field("bid", DataType::Float64, false),
+            arrow_export::field("ask", DataType::Float64, false),
+        ]
+    }
+
+    #[cfg(feature = "arrow")]
+    fn to_arrow_columns(records: &[Self]) -> Vec<arrow::array::ArrayRef> {
+        use arrow_export::{f64_array, timestamp_array};
+        vec![
+            timestamp_array(records.iter().map(|q| Some(q.timestamp))),
+            f64_array(records.iter().map(|q| Some(q.bid))),
+            f64_array(records.iter().map(|q| Some(q.ask))),
+        ]
+    }
+
+    #[cfg(feature = "arrow")]
+    fn from_arrow_batch(batch: &arrow::record_batch::RecordBatch) -> Result<Vec<Self>, Error> {
+        use arrow_export::{f64_column, timestamp_column};
+        let timestamp = timestamp_column(batch, "timestamp")?;
+        let bid = f64_column(batch, "bid")?;
+        let ask = f64_column(batch, "ask")?;
+        Ok((0..batch.num_rows())
+            .map(|i| Quote {
+                timestamp: timestamp.value(i),
+                bid: bid.value(i),
+                ask: ask.value(i),
+            })
+            .collect())
+    }
+}
+
+impl ExportRecord for DealRecord {
+    fn csv_header() -> &'static [&'static str] {
+        &[
+            "deal_id",
+            "order_id",
+            "position_id",
+            "symbol_id",
+            "trade_side",
+            "volume",
+            "filled_volume",
+            "execution_price",
+            "create_timestamp",
+            "execution_timestamp",
+            "deal_status",
+            "commission",
+            "entry_price",
+            "gross_profit",
+            "swap",
+            "balance",
+        ]
+    }
+
+    fn to_csv_row(&self, format: &TimestampFormat) -> Vec<String> {
+        let opt = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+        vec![
+            self.deal_id.to_string(),
+            self.order_id.to_string(),
+            self.position_id.to_string(),
+            self.symbol_id.to_string(),
+            self.trade_side.clone(),
+            self.volume.to_string(),
+            self.filled_volume.to_string(),
+            opt(self.execution_price),
+            format.format(self.create_timestamp),
+            format.format(self.execution_timestamp),
+            self.deal_status.clone(),
+            opt(self.commission),
+            opt(self.entry_price),
+            opt(self.gross_profit),
+            opt(self.swap),
+            opt(self.balance),
+        ]
+    }
+
+    fn from_csv_row(row: &csv::StringRecord, format: &TimestampFormat) -> Result<Self, Error> {
+        Ok(Self {
+            deal_id: parse_field(row, 0)?,
+            order_id: parse_field(row, 1)?,
+            position_id: parse_field(row, 2)?,
+            symbol_id: parse_field(row, 3)?,
+            trade_side: field(row, 4)?.to_string(),
+            volume: parse_field(row, 5)?,
+            filled_volume: parse_field(row, 6)?,
+            execution_price: parse_opt_field(row, 7)?,
+            create_timestamp: format.parse(field(row, 8)?)?,
+            execution_timestamp: format.parse(field(row, 9)?)?,
+            deal_status: field(row, 10)?.to_string(),
+            commission: parse_opt_field(row, 11)?,
+            entry_price: parse_opt_field(row, 12)?,
+            gross_profit: parse_opt_field(row, 13)?,
+            swap: parse_opt_field(row, 14)?,
+            balance: parse_opt_field(row, 15)?,
+        })
+    }
+
+    #[cfg(feature = "arrow")]
+    fn arrow_fields() -> Vec<arrow::datatypes::Field> {
+        use arrow::datatypes::DataType;
+        use arrow_export::{field, timestamp_field};
+        vec![
+            field("deal_id", DataType::Int64, false),
+            field("order_id", DataType::Int64, false),
+            field("position_id", DataType::Int64, false),
+            field("symbol_id", DataType::Int64, false),
+            field("trade_side", DataType::Utf8, false),
+            field("volume", DataType::Int64, false),
+            field("filled_volume", DataType::Int64, false),
+            field("execution_price", DataType::Float64, true),
+            timestamp_field("create_timestamp", false),
+            timestamp_field("execution_timestamp", false),
+            field("deal_status", DataType::Utf8, false),
+            field("commission", DataType::Float64, true),
+            field("entry_price", DataType::Float64, true),
+            field("gross_profit", DataType::Float64, true),
+            field("swap", DataType::Float64, true),
+            field("balance", DataType::Float64, true),
+        ]
+    }
+
+    #[cfg(feature = "arrow")]
+    fn to_arrow_columns(records: &[Self]) -> Vec<arrow::array::ArrayRef> {
+        use arrow_export::{f64_array, i64_array, string_array, timestamp_array};
+        let r = records;
+        vec![
+            i64_array(r.iter().map(|d| Some(d.deal_id))),
+            i64_array(r.iter().map(|d| Some(d.order_id))),
+            i64_array(r.iter().map(|d| Some(d.position_id))),
+            i64_array(r.iter().map(|d| Some(d.symbol_id))),
+            string_array(r.iter().map(|d| d.trade_side.as_str())),
+            i64_array(r.iter().map(|d| Some(d.volume))),
+            i64_array(r.iter().map(|d| Some(d.filled_volume))),
+            f64_array(r.iter().map(|d| d.execution_price)),
+            timestamp_array(r.iter().map(|d| Some(d.create_timestamp))),
+            timestamp_array(r.iter().map(|d| Some(d.execution_timestamp))),
+            string_array(r.iter().map(|d| d.deal_status.as_str())),
+            f64_array(r.iter().map(|d| d.commission)),
+            f64_array(r.iter().map(|d| d.entry_price)),
+            f64_array(r.iter().map(|d| d.gross_profit)),
+            f64_array(r.iter().map(|d| d.swap)),
+            f64_array(r.iter().map(|d| d.balance)),
+        ]
+    }
+
+    #[cfg(feature = "arrow")]
+    fn from_arrow_batch(batch: &arrow::record_batch::RecordBatch) -> Result<Vec<Self>, Error> {
+        use arrow::array::Array;
+        use arrow_export::{f64_column, i64_column, string_column, timestamp_column};
+        let deal_id = i64_column(batch, "deal_id")?;
+        let order_id = i64_column(batch, "order_id")?;
+        let position_id = i64_column(batch, "position_id")?;
+        let symbol_id = i64_column(batch, "symbol_id")?;
+        let trade_side = string_column(batch, "trade_side")?;
+        let volume = i64_column(batch, "volume")?;
+        let filled_volume = i64_column(batch, "filled_volume")?;
+        let execution_price = f64_column(batch, "execution_price")?;
+        let create_timestamp = timestamp_column(batch, "create_timestamp")?;
+        let execution_timestamp = timestamp_column(batch, "execution_timestamp")?;
+        let deal_status = string_column(batch, "deal_status")?;
+        let commission = f64_column(batch, "commission")?;
+        let entry_price = f64_column(batch, "entry_price")?;
+        let gross_profit = f64_column(batch, "gross_profit")?;
+        let swap = f64_column(batch, "swap")?;
+        let balance = f64_column(batch, "balance")?;
+
+        let opt = |a: &arrow::array::Float64Array, i: usize| a.is_valid(i).then(|| a.value(i));
+        Ok((0..batch.num_rows())
+            .map(|i| DealRecord {
+                deal_id: deal_id.value(i),
+                order_id: order_id.value(i),
+                position_id: position_id.value(i),
+                symbol_id: symbol_id.value(i),
+                trade_side: trade_side.value(i).to_string(),
+                volume: volume.value(i),
+                filled_volume: filled_volume.value(i),
+                execution_price: opt(execution_price, i),
+                create_timestamp: create_timestamp.value(i),
+                execution_timestamp: execution_timestamp.value(i),
+                deal_status: deal_status.value(i).to_string(),
+                commission: opt(commission, i),
+                entry_price: opt(entry_price, i),
+                gross_profit: opt(gross_profit, i),
+                swap: opt(swap, i),
+                balance: opt(balance, i),
+            })
+            .collect())
+    }
+}
+
+pub(crate) fn csv_error(e: csv::Error) -> Error {
+    Error::String(format!("csv error: {}", e))
+}
+
+fn field(row: &csv::StringRecord, index: usize) -> Result<&str, Error> {
+    row.get(index)
+        .ok_or(Error::String(format!("csv row without column {}", index)))
+}
+
+fn parse_field<V: std::str::FromStr>(row: &csv::StringRecord, index: usize) -> Result<V, Error> {
+    let value = field(row, index)?;
+    value
+        .parse()
+        .map_err(|_| Error::String(format!("bad csv value {:?} in column {}", value, index)))
+}
+
+fn parse_opt_field<V: std::str::FromStr>(
+    row: &csv::StringRecord,
+    index: usize,
+) -> Result<Option<V>, Error> {
+    match field(row, index)? {
+        "" => Ok(None),
+        _ => parse_field(row, index).map(Some),
+    }
+}
@@ -0,0 +1,508 @@
+//! Streaming technical indicators: SMA, EMA, RSI, ATR, MACD, Bollinger Bands and
+//! Donchian channels.
+//!
+//! Every indicator is updated in O(1) per value. Warm one up with `warm_up` from
+//! `download_asset` history, then feed it the closed bars of `BarGenerator` or the live
+//! trendbar subscription. `batch` runs the same `update` over a series, so backtests and
+//! live trading produce bit-identical values.
+use std::collections::VecDeque;
+
+use crate::domain::Bar;
+use crate::util::{bar_gen::Candle, download::Kline};
+
+pub trait Indicator {
+    type Input;
+    type Output: Copy;
+
+    // Feeds the next value, returns the indicator once it is warmed up.
+    fn update(&mut self, input: Self::Input) -> Option<Self::Output>;
+
+    // The last value, None until warmed up.
+    fn value(&self) -> Option<Self::Output>;
+
+    fn reset(&mut self);
+
+    fn warm_up<I>(&mut self, inputs: I)
+    where
+        I: IntoIterator<Item = Self::Input>,
+    {
+        for input in inputs {
+            self.update(input);
+        }
+    }
+
+    // One output per input, computed with `update` from a fresh state.
+    fn batch<I>(&mut self, inputs: I) -> Vec<Option<Self::Output>>
+    where
+        I: IntoIterator<Item = Self::Input>,
+    {
+        self.reset();
+        inputs.into_iter().map(|input| self.update(input)).collect()
+    }
+}
+
+/// High, low and close of a bar, the input of the range based indicators.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hlc {
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+impl From<&Candle> for Hlc {
+    fn from(bar: &Candle) -> Self {
+        Self {
+            high: bar.high,
+            low: bar.low,
+            close: bar.close,
+        }
+    }
+}
+
+impl From<&Kline> for Hlc {
+    fn from(bar: &Kline) -> Self {
+        Self {
+            high: bar.high,
+            low: bar.low,
+            close: bar.close,
+        }
+    }
+}
+
+impl From<&Bar> for Hlc {
+    fn from(bar: &Bar) -> Self {
+        Self {
+            high: bar.high.to_f64(),
+            low: bar.low.to_f64(),
+            close: bar.close.to_f64(),
+        }
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                              SMA                                 |
+//+------------------------------------------------------------------+
+
+/// Simple moving average
+#[derive(Debug, Clone)]
+pub struct Sma {
+    period: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+}
+
+impl Sma {
+    pub fn new(period: usize) -> Self {
+        let period = period.max(1);
+        Self {
+            period,
+            window: VecDeque::with_capacity(period + 1),
+            sum: 0.0,
+        }
+    }
+
+    pub fn period(&self) -> usize {
+        self.period
+    }
+}
+
+impl Indicator for Sma {
+    type Input = f64;
+    type Output = f64;
+
+    fn update(&mut self, input: f64) -> Option<f64> {
+        self.window.push_back(input);
+        self.sum += input;
+        if self.window.len() > self.period {
+            self.sum -= self.window.pop_front().unwrap_or_default();
+        }
+        self.value()
+    }
+
+    fn value(&self) -> Option<f64> {
+        (self.window.len() == self.period).then(|| self.sum / self.period as f64)
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+        self.sum = 0.0;
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                              EMA                                 |
+//+------------------------------------------------------------------+
+
+/// Exponential moving average, seeded with the SMA of the first period values
+#[derive(Debug, Clone)]
+pub struct Ema {
+    period: usize,
+    alpha: f64,
+    count: usize,
+    value: f64,
+}
+
+impl Ema {
+    pub fn new(period: usize) -> Self {
+        let period = period.max(1);
+        Self {
+            period,
+            alpha: 2.0 / (period as f64 + 1.0),
+            count: 0,
+            value: 0.0,
+        }
+    }
+
+    pub fn period(&self) -> usize {
+        self.period
+    }
+}
+
+impl Indicator for Ema {
+    type Input = f64;
+    type Output = f64;
+
+    fn update(&mut self, input: f64) -> Option<f64> {
+        self.count += 1;
+        if self.count <= self.period {
+            self.value += (input - self.value) / self.count as f64;
+        } else {
+            self.value += (input - self.value) * self.alpha;
+        }
+        self.value()
+    }
+
+    fn value(&self) -> Option<f64> {
+        (self.count >= self.period).then_some(self.value)
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+        self.value = 0.0;
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                              RSI                                 |
+//+------------------------------------------------------------------+
+
+/// Wilder's relative strength index, 0..=100
+#[derive(Debug, Clone)]
+pub struct Rsi {
+    period: usize,
+    prev: Option<f64>,
+    count: usize,
+    avg_gain: f64,
+    avg_loss: f64,
+}
+
+impl Rsi {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            prev: None,
+            count: 0,
+            avg_gain: 0.0,
+            avg_loss: 0.0,
+        }
+    }
+}
+
+impl Indicator for Rsi {
+    type Input = f64;
+    type Output = f64;
+
+    fn update(&mut self, input: f64) -> Option<f64> {
+        let prev = self.prev.replace(input)?;
+        let change = input - prev;
+        let (gain, loss) = (change.max(0.0), (-change).max(0.0));
+        self.count += 1;
+        // simple average over the first period changes
+        let n = self.count.min(self.period) as f64;
+        self.avg_gain += (gain - self.avg_gain) / n;
+        self.avg_loss += (loss - self.avg_loss) / n;
+        self.value()
+    }
+
+    fn value(&self) -> Option<f64> {
+        if self.count < self.period {
+            return None;
+        }
+        if self.avg_loss == 0.0 {
+            return Some(if self.avg_gain == 0.0 { 50.0 } else { 100.0 });
+        }
+        let rs = self.avg_gain / self.avg_loss;
+        Some(100.0 - 100.0 / (1.0 + rs))
+    }
+
+    fn reset(&mut self) {
+        self.prev = None;
+        self.count = 0;
+        self.avg_gain = 0.0;
+        self.avg_loss = 0.0;
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                              ATR                                 |
+//+------------------------------------------------------------------+
+
+/// Wilder's average true range
+#[derive(Debug, Clone)]
+pub struct Atr {
+    period: usize,
+    prev_close: Option<f64>,
+    count: usize,
+    value: f64,
+}
+
+impl Atr {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            prev_close: None,
+            count: 0,
+            value: 0.0,
+        }
+    }
+}
+
+impl Indicator for Atr {
+    type Input = Hlc;
+    type Output = f64;
+
+    fn update(&mut self, bar: Hlc) -> Option<f64> {
+        let tr = match self.prev_close {
+            Some(prev) => (bar.high - bar.low)
+                .max((bar.high - prev).abs())
+                .max((bar.low - prev).abs()),
+            None => bar.high - bar.low,
+        };
+        self.prev_close = Some(bar.close);
+        self.count += 1;
+        // simple average over the first period bars
+        let n = self.count.min(self.period) as f64;
+        self.value += (tr - self.value) / n;
+        self.value()
+    }
+
+    fn value(&self) -> Option<f64> {
+        (self.count >= self.period).then_some(self.value)
+    }
+
+    fn reset(&mut self) {
+        self.prev_close = None;
+        self.count = 0;
+        self.value = 0.0;
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                              MACD                                |
+//+------------------------------------------------------------------+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MacdValue {
+    pub macd: f64,
+    pub signal: f64,
+    pub histogram: f64,
+}
+
+/// Moving average convergence divergence, usually 12, 26, 9
+#[derive(Debug, Clone)]
+pub struct Macd {
+    fast: Ema,
+    slow: Ema,
+    signal: Ema,
+    value: Option<MacdValue>,
+}
+
+impl Macd {
+    pub fn new(fast: usize, slow: usize, signal: usize) -> Self {
+        Self {
+            fast: Ema::new(fast),
+            slow: Ema::new(slow),
+            signal: Ema::new(signal),
+            value: None,
+        }
+    }
+}
+
+impl Default for Macd {
+    fn default() -> Self {
+        Self::new(12, 26, 9)
+    }
+}
+
+impl Indicator for Macd {
+    type Input = f64;
+    type Output = MacdValue;
+
+    fn update(&mut self, input: f64) -> Option<MacdValue> {
+        let fast = self.fast.update(input);
+        let slow = self.slow.update(input);
+        if let (Some(fast), Some(slow)) = (fast, slow) {
+            let macd = fast - slow;
+            self.value = self.signal.update(macd).map(|signal| MacdValue {
+                macd,
+                signal,
+                histogram: macd - signal,
+            });
+        }
+        self.value
+    }
+
+    fn value(&self) -> Option<MacdValue> {
+        self.value
+    }
+
+    fn reset(&mut self) {
+        self.fast.reset();
+        self.slow.reset();
+        self.signal.reset();
+        self.value = None;
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                         Bollinger Bands                          |
+//+------------------------------------------------------------------+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandsValue {
+    pub upper: f64,
+    pub middle: f64,
+    pub lower: f64,
+}
+
+/// SMA plus and minus k population standard deviations, usually 20, 2.0
+#[derive(Debug, Clone)]
+pub struct Bollinger {
+    period: usize,
+    k: f64,
+    window: VecDeque<f64>,
+    sum: f64,
+    sum_sq: f64,
+}
+
+impl Bollinger {
+    pub fn new(period: usize, k: f64) -> Self {
+        let period = period.max(1);
+        Self {
+            period,
+            k,
+            window: VecDeque::with_capacity(period + 1),
+            sum: 0.0,
+            sum_sq: 0.0,
+        }
+    }
+}
+
+impl Indicator for Bollinger {
+    type Input = f64;
+    type Output = BandsValue;
+
+    fn update(&mut self, input: f64) -> Option<BandsValue> {
+        self.window.push_back(input);
+        self.sum += input;
+        self.sum_sq += input * input;
+        if self.window.len() > self.period {
+            let old = self.window.pop_front().unwrap_or_default();
+            self.sum -= old;
+            self.sum_sq -= old * old;
+        }
+        self.value()
+    }
+
+    fn value(&self) -> Option<BandsValue> {
+        if self.window.len() < self.period {
+            return None;
+        }
+        let n = self.period as f64;
+        let mean = self.sum / n;
+        // rounding can push the variance slightly below zero
+        let std = (self.sum_sq / n - mean * mean).max(0.0).sqrt();
+        Some(BandsValue {
+            upper: mean + self.k * std,
+            middle: mean,
+            lower: mean - self.k * std,
+        })
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+        self.sum = 0.0;
+        self.sum_sq = 0.0;
+    }
+}
+
+//+------------------------------------------------------------------+
+//|                        Donchian Channel                          |
+//+------------------------------------------------------------------+
+
+/// Highest high and lowest low of the last period bars
+#[derive(Debug, Clone)]
+pub struct Donchian {
+    period: usize,
+    count: usize,
+    // monotonic queues of (index, price), the front is the extreme of the window
+    highs: VecDeque<(usize, f64)>,
+    lows: VecDeque<(usize, f64)>,
+}
+
+impl Donchian {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            count: 0,
+            highs: VecDeque::new(),
+            lows: VecDeque::new(),
+        }
+    }
+}
+
+impl Indicator for Donchian {
+    type Input = Hlc;
+    type Output = BandsValue;
+
+    fn update(&mut self, bar: Hlc) -> Option<BandsValue> {
+        let index = self.count;
+        self.count += 1;
+        while self.highs.back().is_some_and(|(_, h)| *h <= bar.high) {
+            self.highs.pop_back();
+        }
+        self.highs.push_back((index, bar.high));
+        while self.lows.back().is_some_and(|(_, l)| *l >= bar.low) {
+            self.lows.pop_back();
+        }
+        self.lows.push_back((index, bar.low));
+        // drop what left the window
+        let first = self.count.saturating_sub(self.period);
+        while self.highs.front().is_some_and(|(i, _)| *i < first) {
+            self.highs.pop_front();
+        }
+        while self.lows.front().is_some_and(|(i, _)| *i < first) {
+            self.lows.pop_front();
+        }
+        self.value()
+    }
+
+    fn value(&self) -> Option<BandsValue> {
+        if self.count < self.period {
+            return None;
+        }
+        let upper = self.highs.front()?.1;
+        let lower = self.lows.front()?.1;
+        Some(BandsValue {
+            upper,
+            middle: (upper + lower) / 2.0,
+            lower,
+        })
+    }
+
+    fn reset(&mut self) {
+        self.count = 0;
+        self.highs.clear();
+        self.lows.clear();
+    }
+}
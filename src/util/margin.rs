@@ -0,0 +1,507 @@
+//! Margin computed locally, without an `expected_margin` round-trip per query.
+//!
+//! The margin of a volume is its notional value in the deposit currency divided by the
+//! leverage. A symbol with a `leverage_id` uses dynamic leverage: the notional, in USD, is
+//! split over the tiers of the table and each part uses the leverage of its tier, capped
+//! by the account leverage. Opposite positions on one symbol add up according to the
+//! account's `MarginMode`. Notional values use the mid rates of the `CurrencyConverter`,
+//! so results drift from the server's by the spread; `MarginCalculator::cross_check`
+//! returns both for comparison.
+use std::collections::{HashMap, HashSet};
+
+use crate::domain::{Account, MarginMode, Position, Side};
+use crate::protos::spotware_message::ProtoOaDynamicLeverage;
+use crate::util::conversion::CurrencyConverter;
+use crate::util::sizing::MarginEstimate;
+use crate::util::units::{Money, Volume};
+use crate::util::SymbolStore;
+use crate::{Error, Session};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LeverageTier {
+    /// Upper bound of the tier, notional in USD
+    pub volume: f64,
+    /// e.g. 100.0 for 1:100
+    pub leverage: f64,
+}
+
+/// A dynamic leverage table, see `Session::get_dynamic_leverage_by_id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeverageTable {
+    pub leverage_id: i64,
+    /// Sorted by volume
+    pub tiers: Vec<LeverageTier>,
+}
+
+impl From<&ProtoOaDynamicLeverage> for LeverageTable {
+    fn from(leverage: &ProtoOaDynamicLeverage) -> Self {
+        // volume and leverage are in cents, as everywhere in the Open API
+        let mut tiers: Vec<LeverageTier> = leverage
+            .tiers
+            .iter()
+            .map(|tier| LeverageTier {
+                volume: tier.volume as f64 / 100.0,
+                leverage: tier.leverage as f64 / 100.0,
+            })
+            .collect();
+        tiers.sort_by(|a, b| a.volume.total_cmp(&b.volume));
+        Self {
+            leverage_id: leverage.leverage_id,
+            tiers,
+        }
+    }
+}
+
+impl LeverageTable {
+    // Margin of a notional in USD, in USD. The last tier applies beyond its volume.
+    pub fn margin(&self, notional: f64, max_leverage: f64) -> f64 {
+        let mut margin = 0.0;
+        let mut lower = 0.0;
+        for (i, tier) in self.tiers.iter().enumerate() {
+            let upper = if i + 1 == self.tiers.len() {
+                f64::INFINITY
+            } else {
+                tier.volume
+            };
+            let part = notional.min(upper) - lower;
+            if part <= 0.0 {
+                break;
+            }
+            margin += part / effective_leverage(tier.leverage, max_leverage);
+            lower = upper;
+        }
+        margin
+    }
+}
+
+/// Volume held or ordered on one side of a symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exposure {
+    pub symbol_id: i64,
+    pub side: Side,
+    pub volume: Volume,
+}
+
+impl From<&Position> for Exposure {
+    fn from(position: &Position) -> Self {
+        Self {
+            symbol_id: position.symbol_id,
+            side: position.side,
+            volume: position.volume,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MarginSymbol {
+    base_asset_id: i64,
+    leverage_id: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MarginCalculator {
+    deposit_asset_id: i64,
+    money_digits: u32,
+    leverage: f64,
+    mode: MarginMode,
+    usd_asset_id: Option<i64>,
+    symbols: HashMap<i64, MarginSymbol>,
+    tables: HashMap<i64, LeverageTable>,
+}
+
+impl MarginCalculator {
+    // Calculator for `account` over the symbols of `store`. Symbols with dynamic leverage
+    // need their table, see `load_leverage` and `set_leverage`.
+    pub fn new(account: &Account, store: &SymbolStore) -> Self {
+        let symbols = store
+            .infos()
+            .into_iter()
+            .map(|info| {
+                (
+                    info.info.symbol_id,
+                    MarginSymbol {
+                        base_asset_id: info.base_asset_id,
+                        leverage_id: info.info.leverage_id,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            deposit_asset_id: account.deposit_asset_id,
+            money_digits: account.money_digits,
+            leverage: account.leverage.unwrap_or(1.0),
+            mode: account.margin_mode,
+            usd_asset_id: store.get_asset_by_name("USD").map(|asset| asset.asset_id),
+            symbols,
+            tables: HashMap::new(),
+        }
+    }
+
+    pub fn set_leverage(&mut self, table: LeverageTable) -> &mut Self {
+        self.tables.insert(table.leverage_id, table);
+        self
+    }
+
+    pub fn set_margin_mode(&mut self, mode: MarginMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn leverage_table(&self, leverage_id: i64) -> Option<&LeverageTable> {
+        self.tables.get(&leverage_id)
+    }
+
+    // Fetches the leverage tables of the symbols that are not loaded yet, one request
+    // per table.
+    pub async fn load_leverage(
+        &mut self,
+        session: &Session,
+        symbol_ids: &[i64],
+    ) -> Result<(), Error> {
+        let missing: HashSet<i64> = symbol_ids
+            .iter()
+            .filter_map(|id| self.symbols.get(id)?.leverage_id)
+            .filter(|id| !self.tables.contains_key(id))
+            .collect();
+        for leverage_id in missing {
+            let res = session.get_dynamic_leverage_by_id(leverage_id).await?;
+            self.set_leverage(LeverageTable::from(&res.leverage));
+        }
+        Ok(())
+    }
+
+    // Margin of a new order on its own, as `Session::expected_margin` reports it.
+    pub fn estimate(
+        &self,
+        converter: &mut CurrencyConverter,
+        symbol_id: i64,
+        volume: Volume,
+    ) -> Result<MarginEstimate, Error> {
+        let margin = self.money(self.volume_margin(converter, symbol_id, volume)?);
+        Ok(MarginEstimate {
+            volume,
+            buy: margin,
+            sell: margin,
+        })
+    }
+
+    // Margin of a whole portfolio, opposite sides netted by the margin mode.
+    pub fn portfolio_margin(
+        &self,
+        converter: &mut CurrencyConverter,
+        exposures: &[Exposure],
+    ) -> Result<Money, Error> {
+        // symbol -> (buy, sell)
+        let mut sides: HashMap<i64, (Volume, Volume)> = HashMap::new();
+        for exposure in exposures {
+            let entry = sides.entry(exposure.symbol_id).or_default();
+            match exposure.side {
+                Side::Buy => entry.0 += exposure.volume,
+                Side::Sell => entry.1 += exposure.volume,
+            }
+        }
+        let mut total = 0.0;
+        for (symbol_id, (buy, sell)) in sides {
+            let margin = |converter: &mut CurrencyConverter, volume: Volume| {
+                self.volume_margin(converter, symbol_id, volume)
+            };
+            total += match self.mode {
+                MarginMode::Max => margin(converter, buy)?.max(margin(converter, sell)?),
+                MarginMode::Sum => margin(converter, buy)? + margin(converter, sell)?,
+                MarginMode::Net => {
+                    let net = Volume::from_cents((buy.cents() - sell.cents()).abs());
+                    margin(converter, net)?
+                }
+            };
+        }
+        Ok(self.money(total))
+    }
+
+    // Margin added by `order` on top of the portfolio, negative when it hedges.
+    pub fn additional_margin(
+        &self,
+        converter: &mut CurrencyConverter,
+        exposures: &[Exposure],
+        order: Exposure,
+    ) -> Result<Money, Error> {
+        let before = self.portfolio_margin(converter, exposures)?;
+        let mut with_order = exposures.to_vec();
+        with_order.push(order);
+        let after = self.portfolio_margin(converter, &with_order)?;
+        Ok(after - before)
+    }
+
+    // The local estimate next to the server's, for the same volume.
+    pub async fn cross_check(
+        &mut self,
+        session: &Session,
+        converter: &mut CurrencyConverter,
+        symbol_id: i64,
+        volume: Volume,
+    ) -> Result<(MarginEstimate, MarginEstimate), Error> {
+        self.load_leverage(session, &[symbol_id]).await?;
+        let local = self.estimate(converter, symbol_id, volume)?;
+        let res = session.expected_margin(symbol_id, vec![volume]).await?;
+        let digits = res.money_digits.unwrap_or(self.money_digits);
+        let margin = res
+            .margin
+            .iter()
+            .find(|m| m.volume == volume.cents())
+            .ok_or_else(|| Error::String(format!("no expected margin for {}", volume)))?;
+        let server = MarginEstimate {
+            volume,
+            buy: Money::new(margin.buy_margin, digits),
+            sell: Money::new(margin.sell_margin, digits),
+        };
+        Ok((local, server))
+    }
+
+    // Margin of `volume` in the deposit currency.
+    fn volume_margin(
+        &self,
+        converter: &mut CurrencyConverter,
+        symbol_id: i64,
+        volume: Volume,
+    ) -> Result<f64, Error> {
+        if volume.cents() <= 0 {
+            return Ok(0.0);
+        }
+        let symbol = self
+            .symbols
+            .get(&symbol_id)
+            .ok_or_else(|| Error::String(format!("unknown symbol {}", symbol_id)))?;
+        let base = symbol.base_asset_id;
+        let rate = |converter: &mut CurrencyConverter, to: i64| {
+            converter
+                .rate(base, to)
+                .ok_or(Error::NoConversion(base, to))
+        };
+        let notional = volume.units() * rate(converter, self.deposit_asset_id)?;
+        let Some(leverage_id) = symbol.leverage_id else {
+            return Ok(notional / self.leverage.max(1.0));
+        };
+        let table = self.tables.get(&leverage_id).ok_or_else(|| {
+            Error::String(format!("dynamic leverage {} is not loaded", leverage_id))
+        })?;
+        let usd = self
+            .usd_asset_id
+            .ok_or_else(|| Error::String("no USD asset for dynamic leverage".into()))?;
+        let notional_usd = volume.units() * rate(converter, usd)?;
+        // the tiers give the margin in USD, it scales back to the deposit currency
+        Ok(table.margin(notional_usd, self.leverage) * notional / notional_usd)
+    }
+
+    fn money(&self, amount: f64) -> Money {
+        Money::from_f64(amount, self.money_digits)
+    }
+}
+
+fn effective_leverage(leverage: f64, max_leverage: f64) -> f64 {
+    leverage.min(max_leverage).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protos::spotware_message::{
+        ProtoOaDynamicLeverageTier, ProtoOaSpotEvent, ProtoOaSymbol,
+        ProtoOaTotalMarginCalculationType, ProtoOaTrader,
+    };
+    use crate::util::symbol_info::{AssetInfo, SpotwareSymbolInfo};
+    use crate::NotifyEvent;
+
+    const EUR: i64 = 1;
+    const USD: i64 = 2;
+    const JPY: i64 = 3;
+    const EURUSD: i64 = 1;
+    const USDJPY: i64 = 2;
+    const USDJPY_LEVERAGE: i64 = 7;
+
+    fn table() -> LeverageTable {
+        LeverageTable {
+            leverage_id: USDJPY_LEVERAGE,
+            tiers: vec![
+                LeverageTier {
+                    volume: 1_000_000.0,
+                    leverage: 200.0,
+                },
+                LeverageTier {
+                    volume: 5_000_000.0,
+                    leverage: 100.0,
+                },
+                LeverageTier {
+                    volume: 10_000_000.0,
+                    leverage: 50.0,
+                },
+            ],
+        }
+    }
+
+    fn symbol(symbol_id: i64, name: &str, base: i64, quote: i64) -> SpotwareSymbolInfo {
+        SpotwareSymbolInfo {
+            name: name.to_string(),
+            asset_class: "Forex".to_string(),
+            category: "Majors".to_string(),
+            description: None,
+            info: ProtoOaSymbol {
+                symbol_id,
+                leverage_id: (symbol_id == USDJPY).then_some(USDJPY_LEVERAGE),
+                ..Default::default()
+            },
+            base_asset_id: base,
+            quote_asset_id: quote,
+            base_decimals: 2,
+            quote_decimals: 2,
+        }
+    }
+
+    fn asset(asset_id: i64, name: &str) -> AssetInfo {
+        AssetInfo {
+            asset_id,
+            name: name.to_string(),
+            display_name: None,
+            digits: 2,
+        }
+    }
+
+    fn spot(symbol_id: i64, bid: u64, ask: u64) -> NotifyEvent {
+        NotifyEvent::SpotEvent(ProtoOaSpotEvent {
+            symbol_id,
+            bid: Some(bid),
+            ask: Some(ask),
+            ..Default::default()
+        })
+    }
+
+    // USD account at 1:100, EURUSD at 1.1 and USDJPY at 150.0 mid
+    fn setup(mode: ProtoOaTotalMarginCalculationType) -> (MarginCalculator, CurrencyConverter) {
+        let mut store = SymbolStore::build_from_symbol_infos(vec![
+            symbol(EURUSD, "EURUSD", EUR, USD),
+            symbol(USDJPY, "USDJPY", USD, JPY),
+        ]);
+        store.set_assets(vec![
+            asset(EUR, "EUR"),
+            asset(USD, "USD"),
+            asset(JPY, "JPY"),
+        ]);
+        let account = Account::from(&ProtoOaTrader {
+            deposit_asset_id: USD,
+            leverage_in_cents: Some(10_000),
+            money_digits: Some(2),
+            total_margin_calculation_type: Some(mode as i32),
+            ..Default::default()
+        });
+        let mut converter = CurrencyConverter::new(&store);
+        converter.on_event(&spot(EURUSD, 109_990, 110_010));
+        converter.on_event(&spot(USDJPY, 14_999_000, 15_001_000));
+        (MarginCalculator::new(&account, &store), converter)
+    }
+
+    fn exposure(side: Side, lots: f64) -> Exposure {
+        Exposure {
+            symbol_id: EURUSD,
+            side,
+            volume: Volume::from_cents((lots * 10_000_000.0) as i64),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn leverage_table_from_proto_is_in_cents() {
+        let table = LeverageTable::from(&ProtoOaDynamicLeverage {
+            leverage_id: USDJPY_LEVERAGE,
+            tiers: vec![
+                ProtoOaDynamicLeverageTier {
+                    volume: 500_000_000,
+                    leverage: 10_000,
+                },
+                ProtoOaDynamicLeverageTier {
+                    volume: 100_000_000,
+                    leverage: 20_000,
+                },
+            ],
+        });
+        assert_eq!(table.tiers.len(), 2);
+        assert_eq!(table.tiers[0].volume, 1_000_000.0);
+        assert_eq!(table.tiers[0].leverage, 200.0);
+        assert_eq!(table.tiers[1].volume, 5_000_000.0);
+        assert_eq!(table.tiers[1].leverage, 100.0);
+    }
+
+    #[test]
+    fn leverage_table_splits_tiers() {
+        // 1m at 1:200, 2m at 1:100
+        assert_close(table().margin(3_000_000.0, 500.0), 25_000.0);
+    }
+
+    #[test]
+    fn leverage_table_last_tier_has_no_bound() {
+        // 1m at 1:200, 4m at 1:100, 7m at 1:50
+        assert_close(table().margin(12_000_000.0, 500.0), 185_000.0);
+    }
+
+    #[test]
+    fn leverage_table_capped_by_account_leverage() {
+        assert_close(table().margin(3_000_000.0, 100.0), 30_000.0);
+    }
+
+    #[test]
+    fn estimate_uses_dynamic_leverage() {
+        let (mut calculator, mut converter) = setup(ProtoOaTotalMarginCalculationType::Max);
+        calculator.set_leverage(table());
+        // 3m USD, account leverage 1:100 caps the first tier
+        let estimate = calculator
+            .estimate(&mut converter, USDJPY, Volume::from_cents(300_000_000))
+            .unwrap();
+        assert_close(estimate.buy.to_f64(), 30_000.0);
+    }
+
+    #[test]
+    fn portfolio_margin_max() {
+        let (calculator, mut converter) = setup(ProtoOaTotalMarginCalculationType::Max);
+        let exposures = [exposure(Side::Buy, 1.0), exposure(Side::Sell, 0.5)];
+        let margin = calculator
+            .portfolio_margin(&mut converter, &exposures)
+            .unwrap();
+        // 100k EUR at 1.1 over 1:100
+        assert_close(margin.to_f64(), 1_100.0);
+    }
+
+    #[test]
+    fn portfolio_margin_sum() {
+        let (calculator, mut converter) = setup(ProtoOaTotalMarginCalculationType::Sum);
+        let exposures = [exposure(Side::Buy, 1.0), exposure(Side::Sell, 0.5)];
+        let margin = calculator
+            .portfolio_margin(&mut converter, &exposures)
+            .unwrap();
+        assert_close(margin.to_f64(), 1_650.0);
+    }
+
+    #[test]
+    fn portfolio_margin_net() {
+        let (calculator, mut converter) = setup(ProtoOaTotalMarginCalculationType::Net);
+        let exposures = [exposure(Side::Buy, 1.0), exposure(Side::Sell, 0.5)];
+        let margin = calculator
+            .portfolio_margin(&mut converter, &exposures)
+            .unwrap();
+        assert_close(margin.to_f64(), 550.0);
+    }
+
+    #[test]
+    fn additional_margin_of_hedge() {
+        let (calculator, mut converter) = setup(ProtoOaTotalMarginCalculationType::Net);
+        let exposures = [exposure(Side::Buy, 1.0)];
+        let margin = calculator
+            .additional_margin(&mut converter, &exposures, exposure(Side::Sell, 0.5))
+            .unwrap();
+        assert_close(margin.to_f64(), -550.0);
+    }
+}
@@ -0,0 +1,405 @@
+//! Client side margin level alerts.
+//!
+//! The server fires `MarginCallTriggerEvent` for its own thresholds, at most once every
+//! 10 minutes. The `MarginMonitor` keeps the balance, the open positions and their used
+//! margin from the events, values the positions at the live spots and computes the margin
+//! level on every event. A threshold fires once when the level falls below it and is
+//! armed again when the level recovers above it plus its hysteresis, optionally running a
+//! protective action. Call `reconcile` after connecting, then feed it every `NotifyEvent`.
+//! A position opened on a symbol without a price counts without profit and is reported in
+//! `MarginStatus::unpriced_positions` until `subscribe_unpriced` fetches its prices.
+use std::collections::HashMap;
+
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+use crate::domain::{Account, Position, PositionStatus, Side};
+use crate::util::conversion::CurrencyConverter;
+use crate::util::units::{Money, SymbolUnits, Volume};
+use crate::util::SymbolStore;
+use crate::{Error, NotifyEvent, Session};
+
+// deadline of the FlattenAll action
+const FLATTEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run when a threshold is breached.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProtectiveAction {
+    /// Close `fraction` (0.5 for half) of the position with the largest loss
+    ReduceLargestLoser {
+        fraction: f64,
+    },
+    CloseLargestLoser,
+    /// Lock trading and close everything, see `Session::flatten_all`
+    FlattenAll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarginThreshold {
+    /// Margin level in percent, e.g. 150.0
+    pub level: f64,
+    /// Points above `level` the margin level has to recover to re-arm the threshold
+    pub hysteresis: f64,
+    pub action: Option<ProtectiveAction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarginStatus {
+    pub balance: Money,
+    /// Balance plus the unrealized net profit of the open positions
+    pub equity: Money,
+    pub used_margin: Money,
+    pub free_margin: Money,
+    /// Equity over used margin in percent, None without used margin
+    pub margin_level: Option<f64>,
+    /// Positions counted without profit, their price or conversion is not known. The
+    /// margin level is overstated while it is not 0, see `MarginMonitor::subscribe_unpriced`
+    pub unpriced_positions: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarginAlert {
+    Breached {
+        threshold: MarginThreshold,
+        status: MarginStatus,
+    },
+    Recovered {
+        threshold: MarginThreshold,
+        status: MarginStatus,
+    },
+    /// A protective action was sent, `position_id` is None for FlattenAll
+    ActionTaken {
+        action: ProtectiveAction,
+        position_id: Option<i64>,
+    },
+    ActionFailed {
+        action: ProtectiveAction,
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MonitoredSymbol {
+    quote_asset_id: i64,
+    units: SymbolUnits,
+}
+
+#[derive(Debug)]
+pub struct MarginMonitor {
+    deposit_asset_id: i64,
+    money_digits: u32,
+    balance: Money,
+    positions: HashMap<i64, Position>,
+    symbols: HashMap<i64, MonitoredSymbol>,
+    converter: CurrencyConverter,
+    // threshold, breached
+    thresholds: Vec<(MarginThreshold, bool)>,
+    tx: broadcast::Sender<MarginAlert>,
+}
+
+impl MarginMonitor {
+    pub fn new(account: &Account, store: &SymbolStore) -> Self {
+        let symbols = store
+            .infos()
+            .into_iter()
+            .map(|info| {
+                (
+                    info.info.symbol_id,
+                    MonitoredSymbol {
+                        quote_asset_id: info.quote_asset_id,
+                        units: SymbolUnits::from(&info.info),
+                    },
+                )
+            })
+            .collect();
+        let (tx, _) = broadcast::channel(100);
+        Self {
+            deposit_asset_id: account.deposit_asset_id,
+            money_digits: account.money_digits,
+            balance: account.balance,
+            positions: HashMap::new(),
+            symbols,
+            converter: CurrencyConverter::new(store),
+            thresholds: Vec::new(),
+            tx,
+        }
+    }
+
+    // Alerts when the margin level falls below `level`, re-armed above level + hysteresis.
+    pub fn add_threshold(
+        &mut self,
+        level: f64,
+        hysteresis: f64,
+        action: Option<ProtectiveAction>,
+    ) -> &mut Self {
+        self.thresholds.push((
+            MarginThreshold {
+                level,
+                hysteresis: hysteresis.max(0.0),
+                action,
+            },
+            false,
+        ));
+        // highest first, so the most severe breach acts last
+        self.thresholds
+            .sort_by(|a, b| b.0.level.total_cmp(&a.0.level));
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<MarginAlert> {
+        self.tx.subscribe()
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = &Position> {
+        self.positions.values()
+    }
+
+    // Reloads the balance and positions and subscribes the spots needed to value them.
+    pub async fn reconcile(&mut self, session: &mut Session) -> Result<(), Error> {
+        let account = session.account_info().await?;
+        self.balance = account.balance;
+        self.money_digits = account.money_digits;
+        self.positions = session
+            .open_positions()
+            .await?
+            .into_iter()
+            .map(|p| (p.position_id, p))
+            .collect();
+
+        let symbol_ids = self.positions.values().map(|p| p.symbol_id).collect();
+        self.subscribe_symbols(session, symbol_ids).await
+    }
+
+    // Symbols of the open positions that cannot be valued yet.
+    pub fn unpriced_symbols(&mut self) -> Vec<i64> {
+        let position_ids: Vec<i64> = self.positions.keys().copied().collect();
+        let mut symbol_ids = Vec::new();
+        for position_id in position_ids {
+            if self.net_profit(position_id).is_none() {
+                symbol_ids.push(self.positions[&position_id].symbol_id);
+            }
+        }
+        symbol_ids.sort_unstable();
+        symbol_ids.dedup();
+        symbol_ids
+    }
+
+    // Subscribes the spots of the positions opened since `reconcile` on symbols without
+    // a price, and prices their conversion to the deposit currency.
+    pub async fn subscribe_unpriced(&mut self, session: &mut Session) -> Result<(), Error> {
+        let symbol_ids = self.unpriced_symbols();
+        self.subscribe_symbols(session, symbol_ids).await
+    }
+
+    // Updates the state from the event, then checks the thresholds. Returns the alerts
+    // raised, they are also published to the subscribers.
+    pub async fn on_event(
+        &mut self,
+        session: &Session,
+        event: &NotifyEvent,
+    ) -> Result<Vec<MarginAlert>, Error> {
+        self.converter.on_event(event);
+        match event {
+            NotifyEvent::AccoutDataUpdateEvent(event) => {
+                self.set_balance(event.trader.balance, event.trader.money_digits);
+            }
+            NotifyEvent::ExecutionEvent(event) => {
+                if let Some(position) = &event.position {
+                    let position = Position::from(position);
+                    if position.status == PositionStatus::Closed {
+                        self.positions.remove(&position.position_id);
+                    } else {
+                        if !session.subscribed_spots().contains(&position.symbol_id) {
+                            warn!(
+                                "position {} has no spot subscription, see subscribe_unpriced",
+                                position.position_id
+                            );
+                        }
+                        self.positions.insert(position.position_id, position);
+                    }
+                }
+                if let Some(detail) = event
+                    .deal
+                    .as_ref()
+                    .and_then(|d| d.close_position_detail.as_ref())
+                {
+                    self.set_balance(detail.balance, detail.money_digits);
+                }
+                if let Some(deposit) = &event.deposit_withdraw {
+                    self.set_balance(deposit.balance, deposit.money_digits);
+                }
+            }
+            NotifyEvent::MarginChangedEvent(event) => {
+                if let Some(position) = self.positions.get_mut(&(event.position_id as i64)) {
+                    let digits = event.money_digits.unwrap_or(self.money_digits);
+                    position.used_margin = Some(Money::new(event.used_margin as i64, digits));
+                }
+            }
+            NotifyEvent::SpotEvent(_) => {}
+            _ => return Ok(Vec::new()),
+        }
+        self.check(session).await
+    }
+
+    // Balance, equity and margin level at the last known prices. Positions whose price
+    // or conversion is not known yet count without profit.
+    pub fn status(&mut self) -> MarginStatus {
+        let mut profit = 0.0;
+        let mut used_margin = 0.0;
+        let mut unpriced_positions = 0;
+        let position_ids: Vec<i64> = self.positions.keys().copied().collect();
+        for position_id in position_ids {
+            match self.net_profit(position_id) {
+                Some(net_profit) => profit += net_profit,
+                None => unpriced_positions += 1,
+            }
+            let position = &self.positions[&position_id];
+            used_margin += position.used_margin.map(Money::to_f64).unwrap_or(0.0);
+        }
+        let balance = self.balance.to_f64();
+        let equity = balance + profit;
+        let money = |amount: f64| Money::from_f64(amount, self.money_digits);
+        MarginStatus {
+            balance: self.balance,
+            equity: money(equity),
+            used_margin: money(used_margin),
+            free_margin: money(equity - used_margin),
+            margin_level: (used_margin > 0.0).then(|| equity / used_margin * 100.0),
+            unpriced_positions,
+        }
+    }
+
+    async fn subscribe_symbols(
+        &mut self,
+        session: &mut Session,
+        mut symbol_ids: Vec<i64>,
+    ) -> Result<(), Error> {
+        symbol_ids.sort_unstable();
+        symbol_ids.dedup();
+        let missing: Vec<i64> = symbol_ids
+            .iter()
+            .copied()
+            .filter(|id| !session.subscribed_spots().contains(id))
+            .collect();
+        if !missing.is_empty() {
+            session.subscribe_spot(missing).await?;
+        }
+        for symbol_id in symbol_ids {
+            if let Some(symbol) = self.symbols.get(&symbol_id).copied() {
+                self.converter
+                    .live_rate(session, symbol.quote_asset_id, self.deposit_asset_id)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn check(&mut self, session: &Session) -> Result<Vec<MarginAlert>, Error> {
+        let status = self.status();
+        let Some(level) = status.margin_level else {
+            return Ok(Vec::new());
+        };
+        let mut alerts = Vec::new();
+        let mut actions = Vec::new();
+        for (threshold, breached) in self.thresholds.iter_mut() {
+            if !*breached && level < threshold.level {
+                *breached = true;
+                warn!("margin level {:.2}% below {:.2}%", level, threshold.level);
+                alerts.push(MarginAlert::Breached {
+                    threshold: *threshold,
+                    status,
+                });
+                actions.extend(threshold.action);
+            } else if *breached && level >= threshold.level + threshold.hysteresis {
+                *breached = false;
+                info!(
+                    "margin level {:.2}% recovered above {:.2}%",
+                    level, threshold.level
+                );
+                alerts.push(MarginAlert::Recovered {
+                    threshold: *threshold,
+                    status,
+                });
+            }
+        }
+        for action in actions {
+            alerts.push(match self.run_action(session, action).await {
+                Ok(position_id) => MarginAlert::ActionTaken {
+                    action,
+                    position_id,
+                },
+                Err(e) => MarginAlert::ActionFailed {
+                    action,
+                    error: e.to_string(),
+                },
+            });
+        }
+        for alert in &alerts {
+            // no receivers is fine
+            let _ = self.tx.send(alert.clone());
+        }
+        Ok(alerts)
+    }
+
+    async fn run_action(
+        &mut self,
+        session: &Session,
+        action: ProtectiveAction,
+    ) -> Result<Option<i64>, Error> {
+        let fraction = match action {
+            ProtectiveAction::FlattenAll => {
+                session.flatten_all(FLATTEN_TIMEOUT).await?;
+                return Ok(None);
+            }
+            ProtectiveAction::CloseLargestLoser => 1.0,
+            ProtectiveAction::ReduceLargestLoser { fraction } => fraction.clamp(0.0, 1.0),
+        };
+        let Some(position_id) = self.largest_loser() else {
+            return Ok(None);
+        };
+        let position = &self.positions[&position_id];
+        let step = self
+            .symbols
+            .get(&position.symbol_id)
+            .map(|s| s.units.step_volume)
+            .unwrap_or(1);
+        let volume = Volume::from_cents((position.volume.cents() as f64 * fraction) as i64)
+            .round_to_step(step)
+            .max(Volume::from_cents(step))
+            .min(position.volume);
+        warn!("closing {} of position {}", volume, position_id);
+        session.close_position(position_id, volume).await?;
+        Ok(Some(position_id))
+    }
+
+    fn largest_loser(&mut self) -> Option<i64> {
+        let position_ids: Vec<i64> = self.positions.keys().copied().collect();
+        position_ids
+            .into_iter()
+            .filter_map(|id| Some((id, self.net_profit(id)?)))
+            .filter(|(_, profit)| *profit < 0.0)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id)
+    }
+
+    // Unrealized profit at the closing price, with swap and commission.
+    fn net_profit(&mut self, position_id: i64) -> Option<f64> {
+        let position = self.positions.get(&position_id)?;
+        let symbol = self.symbols.get(&position.symbol_id)?;
+        let entry = position.entry_price?.to_f64();
+        let gross = match position.side {
+            Side::Buy => self.converter.bid(position.symbol_id)?.to_f64() - entry,
+            Side::Sell => entry - self.converter.ask(position.symbol_id)?.to_f64(),
+        } * position.volume.units();
+        let (swap, commission) = (position.swap.to_f64(), position.commission.to_f64());
+        let rate = self
+            .converter
+            .rate(symbol.quote_asset_id, self.deposit_asset_id)?;
+        Some(gross * rate + swap + commission)
+    }
+
+    fn set_balance(&mut self, balance: i64, money_digits: Option<u32>) {
+        self.balance = Money::new(balance, money_digits.unwrap_or(self.money_digits));
+    }
+}
@@ -1,14 +1,54 @@
+pub mod algo;
+pub mod analytics;
+#[cfg(feature = "arrow")]
+pub mod arrow_export;
+pub mod bar_cache;
 pub mod bar_gen;
+pub mod bulk_download;
+pub mod calendar;
+pub mod conversion;
 pub mod download;
+pub mod export;
+pub mod indicators;
+pub mod margin;
+pub mod margin_monitor;
+pub mod order_book;
+pub mod order_group;
+pub mod position_manager;
+pub mod resample;
 pub mod session_config;
+pub mod sizing;
+pub mod statement;
+pub mod symbol_cache;
 pub mod symbol_info;
 pub mod symbol_store;
+pub mod tick_download;
 pub mod time_util;
+pub mod units;
 
+pub use algo::{Iceberg, LimitChase, Twap};
+pub use analytics::PerformanceReport;
+pub use bar_cache::BarCache;
 pub use bar_gen::BarGenerator;
 pub use bar_gen::Candle;
 pub use bar_gen::Quote;
-pub use download::download_asset;
+pub use bulk_download::BulkDownloader;
+pub use calendar::TradingCalendar;
+pub use conversion::CurrencyConverter;
+pub use download::{download_asset, download_asset_to_file};
+pub use export::{ExportFormat, ExportMetadata, TimestampFormat};
+pub use indicators::Indicator;
+pub use margin::MarginCalculator;
+pub use margin_monitor::MarginMonitor;
+pub use order_book::{OrderBook, OrderBookManager};
+pub use order_group::OrderGroupManager;
+pub use position_manager::PositionManager;
+pub use resample::{BarSpec, HeikinAshi, Resampler};
+pub use sizing::PositionSizer;
+pub use statement::Statement;
+pub use symbol_cache::SymbolLoading;
 pub use symbol_info::get_symbol_infos;
-pub use symbol_info::SpotwareSymbolInfo;
-pub use symbol_store::SymbolStore;
+pub use symbol_info::{AssetInfo, SpotwareSymbolInfo};
+pub use symbol_store::{SymbolChange, SymbolStore, SymbolUpdate};
+pub use tick_download::{download_quotes, download_ticks, Tick};
+pub use units::{Money, Price, SymbolUnits, Volume};
@@ -0,0 +1,329 @@
+//! Level-2 order books maintained from depth events.
+//!
+//! Depth events carry quotes by id: `new_quotes` adds or replaces a quote, `deleted_quotes`
+//! removes it. An `OrderBook` aggregates the quotes into price levels, the
+//! `OrderBookManager` keeps one book per symbol and publishes snapshots or diffs on a
+//! broadcast channel. Feed it every `NotifyEvent`, and call `reset` before
+//! `resume_subscribe`: the server sends the full book again after a resubscription.
+use std::collections::{BTreeMap, HashMap};
+
+use tokio::sync::broadcast;
+
+use crate::domain::Side;
+use crate::protos::spotware_message::ProtoOaDepthEvent;
+use crate::util::units::{Price, Volume};
+use crate::NotifyEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookLevel {
+    pub price: Price,
+    /// Total size of the quotes at the price
+    pub size: Volume,
+}
+
+/// A level whose size changed, a size of zero means the level was removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelChange {
+    pub side: Side,
+    pub price: Price,
+    pub size: Volume,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookSnapshot {
+    pub symbol_id: i64,
+    /// Best first
+    pub bids: Vec<BookLevel>,
+    /// Best first
+    pub asks: Vec<BookLevel>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookDiff {
+    pub symbol_id: i64,
+    pub changes: Vec<LevelChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookUpdate {
+    Snapshot(BookSnapshot),
+    Diff(BookDiff),
+    /// The book was cleared, e.g. before a resubscription
+    Reset {
+        symbol_id: i64,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BookQuote {
+    side: Side,
+    price: u64,
+    size: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    symbol_id: i64,
+    quotes: HashMap<u64, BookQuote>,
+    // price -> aggregated size
+    bids: BTreeMap<u64, u64>,
+    asks: BTreeMap<u64, u64>,
+}
+
+impl OrderBook {
+    pub fn new(symbol_id: i64) -> Self {
+        Self {
+            symbol_id,
+            ..Default::default()
+        }
+    }
+
+    pub fn symbol_id(&self) -> i64 {
+        self.symbol_id
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.quotes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.quotes.clear();
+        self.bids.clear();
+        self.asks.clear();
+    }
+
+    // Applies the deletions then the new quotes, returns the levels that changed.
+    pub fn apply(&mut self, event: &ProtoOaDepthEvent) -> BookDiff {
+        let mut touched: Vec<(Side, u64)> = Vec::new();
+        for id in &event.deleted_quotes {
+            if let Some(quote) = self.remove_quote(*id) {
+                touched.push((quote.side, quote.price));
+            }
+        }
+        for quote in &event.new_quotes {
+            let (side, price) = match (quote.bid, quote.ask) {
+                (Some(bid), _) => (Side::Buy, bid),
+                (None, Some(ask)) => (Side::Sell, ask),
+                (None, None) => continue,
+            };
+            // a known id replaces the previous quote
+            if let Some(old) = self.remove_quote(quote.id) {
+                touched.push((old.side, old.price));
+            }
+            *self.levels_mut(side).entry(price).or_default() += quote.size;
+            self.quotes.insert(
+                quote.id,
+                BookQuote {
+                    side,
+                    price,
+                    size: quote.size,
+                },
+            );
+            touched.push((side, price));
+        }
+
+        touched.sort_by_key(|(side, price)| (*side == Side::Sell, *price));
+        touched.dedup();
+        let changes = touched
+            .into_iter()
+            .map(|(side, price)| {
+                let size = self.levels(side).get(&price).copied().unwrap_or(0);
+                LevelChange {
+                    side,
+                    price: Price::from_raw(price as i64),
+                    size: Volume::from_cents(size as i64),
+                }
+            })
+            .collect();
+        BookDiff {
+            symbol_id: self.symbol_id,
+            changes,
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<BookLevel> {
+        self.bids.iter().next_back().map(to_level)
+    }
+
+    pub fn best_ask(&self) -> Option<BookLevel> {
+        self.asks.iter().next().map(to_level)
+    }
+
+    pub fn spread(&self) -> Option<Price> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    pub fn mid(&self) -> Option<Price> {
+        let bid = self.best_bid()?.price.raw();
+        let ask = self.best_ask()?.price.raw();
+        Some(Price::from_raw((bid + ask) / 2))
+    }
+
+    // Levels of a side, best first.
+    pub fn levels_of(&self, side: Side) -> Box<dyn Iterator<Item = BookLevel> + '_> {
+        match side {
+            Side::Buy => Box::new(self.bids.iter().rev().map(to_level)),
+            Side::Sell => Box::new(self.asks.iter().map(to_level)),
+        }
+    }
+
+    // The best `depth` levels of each side.
+    pub fn top(&self, depth: usize) -> BookSnapshot {
+        BookSnapshot {
+            symbol_id: self.symbol_id,
+            bids: self.levels_of(Side::Buy).take(depth).collect(),
+            asks: self.levels_of(Side::Sell).take(depth).collect(),
+        }
+    }
+
+    pub fn snapshot(&self) -> BookSnapshot {
+        self.top(usize::MAX)
+    }
+
+    // Size quoted on a side at prices as good as or better than `price`.
+    pub fn cumulative_size(&self, side: Side, price: Price) -> Volume {
+        let price = price.raw().max(0) as u64;
+        let size: u64 = match side {
+            Side::Buy => self.bids.range(price..).map(|(_, size)| size).sum(),
+            Side::Sell => self.asks.range(..=price).map(|(_, size)| size).sum(),
+        };
+        Volume::from_cents(size as i64)
+    }
+
+    // Average price of filling `size` against the book as a taker on `side`, i.e. a buy
+    // walks the asks. None when the book is not deep enough.
+    pub fn vwap(&self, side: Side, size: Volume) -> Option<Price> {
+        let size = size.cents();
+        if size <= 0 {
+            return None;
+        }
+        let mut remaining = size as i128;
+        let mut notional: i128 = 0;
+        for level in self.levels_of(side.opposite()) {
+            let take = remaining.min(level.size.cents() as i128);
+            notional += take * level.price.raw() as i128;
+            remaining -= take;
+            if remaining == 0 {
+                return Some(Price::from_raw((notional / size as i128) as i64));
+            }
+        }
+        None
+    }
+
+    fn remove_quote(&mut self, id: u64) -> Option<BookQuote> {
+        let quote = self.quotes.remove(&id)?;
+        let levels = self.levels_mut(quote.side);
+        if let Some(size) = levels.get_mut(&quote.price) {
+            *size = size.saturating_sub(quote.size);
+            if *size == 0 {
+                levels.remove(&quote.price);
+            }
+        }
+        Some(quote)
+    }
+
+    fn levels(&self, side: Side) -> &BTreeMap<u64, u64> {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+
+    fn levels_mut(&mut self, side: Side) -> &mut BTreeMap<u64, u64> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+}
+
+fn to_level((price, size): (&u64, &u64)) -> BookLevel {
+    BookLevel {
+        price: Price::from_raw(*price as i64),
+        size: Volume::from_cents(*size as i64),
+    }
+}
+
+#[derive(Debug)]
+pub struct OrderBookManager {
+    books: HashMap<i64, OrderBook>,
+    // publish top-N snapshots instead of diffs
+    snapshot_depth: Option<usize>,
+    tx: broadcast::Sender<BookUpdate>,
+}
+
+impl Default for OrderBookManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderBookManager {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(100);
+        Self {
+            books: HashMap::new(),
+            snapshot_depth: None,
+            tx,
+        }
+    }
+
+    // Publish the best `depth` levels after every event instead of the changed levels.
+    pub fn set_snapshot_depth(&mut self, depth: Option<usize>) -> &mut Self {
+        self.snapshot_depth = depth;
+        self
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<BookUpdate> {
+        self.tx.subscribe()
+    }
+
+    pub fn book(&self, symbol_id: i64) -> Option<&OrderBook> {
+        self.books.get(&symbol_id)
+    }
+
+    pub fn on_event(&mut self, event: &NotifyEvent) {
+        match event {
+            NotifyEvent::DepthEvent(depth) => {
+                let symbol_id = depth.symbol_id as i64;
+                let book = self
+                    .books
+                    .entry(symbol_id)
+                    .or_insert_with(|| OrderBook::new(symbol_id));
+                let diff = book.apply(depth);
+                let update = match self.snapshot_depth {
+                    Some(n) => BookUpdate::Snapshot(book.top(n)),
+                    None if diff.changes.is_empty() => return,
+                    None => BookUpdate::Diff(diff),
+                };
+                // no receivers is fine
+                let _ = self.tx.send(update);
+            }
+            // the quote ids are not valid across connections
+            NotifyEvent::ClientDisconnectEvent(_) | NotifyEvent::AccountDisconnectEvent(_) => {
+                self.reset_all();
+            }
+            _ => {}
+        }
+    }
+
+    // Clears a book, call before resubscribing its depth quotes.
+    pub fn reset(&mut self, symbol_id: i64) {
+        if let Some(book) = self.books.get_mut(&symbol_id) {
+            book.clear();
+            let _ = self.tx.send(BookUpdate::Reset { symbol_id });
+        }
+    }
+
+    pub fn reset_all(&mut self) {
+        let ids: Vec<i64> = self.books.keys().copied().collect();
+        for symbol_id in ids {
+            self.reset(symbol_id);
+        }
+    }
+
+    // Drops a book after unsubscribing.
+    pub fn remove(&mut self, symbol_id: i64) -> Option<OrderBook> {
+        self.books.remove(&symbol_id)
+    }
+}
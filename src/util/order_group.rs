@@ -0,0 +1,745 @@
+//! Client side order groups: one-cancels-other pairs, brackets with several
+//! take profit levels and if-done chains.
+//!
+//! The Open API only knows single orders with one SL/TP pair, so the
+//! `OrderGroupManager` watches execution events and places/cancels the
+//! other legs itself. Feed it every `NotifyEvent` and call `reconcile`
+//! after each reconnect, so that legs missed while disconnected are resolved.
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use crate::client::order::new_client_order_id;
+use crate::domain::Side;
+use crate::util::units::{Price, Volume};
+use crate::{
+    protos::spotware_message::*, Error, NewOrderParams, NotifyEvent, Session, SubmitOutcome,
+};
+
+// planning rounds per event, every round either changes a leg or stops
+const MAX_PLAN_ROUNDS: usize = 8;
+// submissions per leg, a leg is only resubmitted when it provably never reached the server
+const SUBMIT_ATTEMPTS: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LegType {
+    Market,
+    Limit(Price),
+    Stop(Price),
+}
+
+/// What to send for one leg of a group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegSpec {
+    pub symbol_id: i64,
+    pub side: Side,
+    pub leg_type: LegType,
+    pub volume: Volume,
+    pub label: Option<String>,
+}
+
+impl LegSpec {
+    fn to_params(&self, client_order_id: &str, position_id: Option<i64>) -> NewOrderParams {
+        let side = self.side.into();
+        let volume = self.volume;
+        let mut params = match self.leg_type {
+            LegType::Market => NewOrderParams::market(self.symbol_id, side, volume),
+            LegType::Limit(price) => NewOrderParams::limit(self.symbol_id, side, volume, price),
+            LegType::Stop(price) => NewOrderParams::stop(self.symbol_id, side, volume, price),
+        };
+        params.set_client_order_id(client_order_id);
+        if let Some(label) = &self.label {
+            params.set_label(label);
+        }
+        if let Some(position_id) = position_id {
+            params.set_position_id(position_id);
+        }
+        params
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LegState {
+    /// Not sent yet
+    Pending,
+    /// Recorded before the submission, the server answer is not known yet
+    Sent,
+    /// Accepted by the server and working
+    Working,
+    Filled,
+    Cancelled,
+    Rejected,
+}
+
+impl LegState {
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            LegState::Filled | LegState::Cancelled | LegState::Rejected
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Leg {
+    pub spec: LegSpec,
+    pub client_order_id: String,
+    pub order_id: Option<i64>,
+    pub position_id: Option<i64>,
+    pub state: LegState,
+    pub filled_volume: Volume,
+    /// Unix time in milliseconds of the last submission
+    #[serde(default)]
+    pub sent_at: Option<i64>,
+    // a cancel request was sent for the current working order
+    #[serde(default)]
+    cancel_requested: bool,
+}
+
+impl Leg {
+    fn new(spec: LegSpec) -> Self {
+        Self {
+            spec,
+            client_order_id: new_client_order_id(),
+            order_id: None,
+            position_id: None,
+            state: LegState::Pending,
+            filled_volume: Volume::ZERO,
+            sent_at: None,
+            cancel_requested: false,
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        matches!(self.state, LegState::Sent | LegState::Working)
+    }
+
+    // update the leg from the server side view of its order
+    fn apply_order(&mut self, order: &ProtoOaOrder) {
+        self.order_id = Some(order.order_id);
+        if order.position_id.is_some() {
+            self.position_id = order.position_id;
+        }
+        if let Some(executed) = order.executed_volume {
+            self.filled_volume = Volume::from_cents(executed);
+        }
+        self.state = match order.order_status() {
+            ProtoOaOrderStatus::OrderStatusAccepted => LegState::Working,
+            ProtoOaOrderStatus::OrderStatusFilled => LegState::Filled,
+            ProtoOaOrderStatus::OrderStatusRejected => LegState::Rejected,
+            ProtoOaOrderStatus::OrderStatusExpired | ProtoOaOrderStatus::OrderStatusCancelled => {
+                LegState::Cancelled
+            }
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GroupKind {
+    /// The first leg to fill or be cancelled cancels the others
+    Oco,
+    /// legs[0] is the entry, the stop loss is set on the resulting position
+    /// and the other legs are take profit orders closing part of it
+    Bracket { stop_loss: Price },
+    /// Each leg is placed once the previous one filled
+    IfDone,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderGroup {
+    pub id: String,
+    pub kind: GroupKind,
+    pub legs: Vec<Leg>,
+    pub done: bool,
+    // bracket only
+    #[serde(default)]
+    stop_loss_applied: bool,
+    #[serde(default)]
+    position_closed: bool,
+    // cancel_group was called, legs still in flight are cancelled once working
+    #[serde(default)]
+    cancelled: bool,
+}
+
+impl OrderGroup {
+    fn new(kind: GroupKind, legs: Vec<LegSpec>) -> Self {
+        Self {
+            id: Uuid::new_v4().simple().to_string(),
+            kind,
+            legs: legs.into_iter().map(Leg::new).collect(),
+            done: false,
+            stop_loss_applied: false,
+            position_closed: false,
+            cancelled: false,
+        }
+    }
+
+    fn leg_mut(&mut self, order_id: i64, client_order_id: Option<&str>) -> Option<&mut Leg> {
+        self.legs.iter_mut().find(|l| {
+            l.order_id == Some(order_id)
+                || (client_order_id.is_some()
+                    && client_order_id == Some(l.client_order_id.as_str()))
+        })
+    }
+
+    // Decide what to send next, legs that need no request are updated in place.
+    fn plan(&mut self) -> Vec<Action> {
+        let mut actions = Vec::new();
+        if self.done {
+            return actions;
+        }
+        if self.cancelled {
+            for leg in self.legs.iter_mut() {
+                match leg.state {
+                    LegState::Pending => leg.state = LegState::Cancelled,
+                    LegState::Working => cancel_leg(leg, &mut actions),
+                    _ => {}
+                }
+            }
+            self.done = !self.legs.iter().any(|l| l.is_live());
+            return actions;
+        }
+
+        match self.kind {
+            GroupKind::Oco => {
+                let triggered = self
+                    .legs
+                    .iter()
+                    .any(|l| l.state.is_terminal() || l.filled_volume > Volume::ZERO);
+                for (i, leg) in self.legs.iter_mut().enumerate() {
+                    match leg.state {
+                        LegState::Pending if triggered => leg.state = LegState::Cancelled,
+                        LegState::Pending => actions.push(Action::Place(i, None)),
+                        LegState::Working if triggered && leg.filled_volume == Volume::ZERO => {
+                            cancel_leg(leg, &mut actions)
+                        }
+                        _ => {}
+                    }
+                }
+                self.done = self
+                    .legs
+                    .iter()
+                    .all(|l| l.state.is_terminal() || l.filled_volume > Volume::ZERO)
+                    && !self.legs.iter().any(|l| l.state == LegState::Sent);
+            }
+            GroupKind::Bracket { stop_loss } => {
+                let (entry, take_profits) = self.legs.split_at_mut(1);
+                let entry = &mut entry[0];
+                match entry.state {
+                    LegState::Pending => actions.push(Action::Place(0, None)),
+                    LegState::Cancelled | LegState::Rejected => {
+                        take_profits
+                            .iter_mut()
+                            .for_each(|l| l.state = LegState::Cancelled);
+                    }
+                    LegState::Filled if !self.position_closed => {
+                        let position_id = entry.position_id;
+                        if let Some(position_id) = position_id {
+                            if !self.stop_loss_applied {
+                                actions.push(Action::SetStopLoss(position_id, stop_loss));
+                            }
+                        }
+                        for (i, leg) in take_profits.iter_mut().enumerate() {
+                            if leg.state == LegState::Pending && position_id.is_some() {
+                                actions.push(Action::Place(i + 1, position_id));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                if self.position_closed {
+                    for leg in take_profits.iter_mut() {
+                        match leg.state {
+                            LegState::Pending => leg.state = LegState::Cancelled,
+                            LegState::Working => cancel_leg(leg, &mut actions),
+                            _ => {}
+                        }
+                    }
+                }
+                self.done = self.legs.iter().all(|l| l.state.is_terminal());
+            }
+            GroupKind::IfDone => match self.legs.iter().position(|l| l.state != LegState::Filled) {
+                None => self.done = true,
+                Some(i) => match self.legs[i].state {
+                    LegState::Pending => actions.push(Action::Place(i, None)),
+                    LegState::Cancelled | LegState::Rejected => {
+                        self.legs[i..]
+                            .iter_mut()
+                            .filter(|l| l.state == LegState::Pending)
+                            .for_each(|l| l.state = LegState::Cancelled);
+                        self.done = true;
+                    }
+                    _ => {}
+                },
+            },
+        }
+        actions
+    }
+}
+
+fn cancel_leg(leg: &mut Leg, actions: &mut Vec<Action>) {
+    if let (Some(order_id), false) = (leg.order_id, leg.cancel_requested) {
+        leg.cancel_requested = true;
+        actions.push(Action::Cancel(order_id));
+    }
+}
+
+#[derive(Debug)]
+enum Action {
+    // leg index, position the order is linked to
+    Place(usize, Option<i64>),
+    Cancel(i64),
+    SetStopLoss(i64, Price),
+}
+
+/// Persistence hook, called with all unfinished groups after every change.
+pub trait OrderGroupStore: Send {
+    fn load(&mut self) -> Result<Vec<OrderGroup>, Error>;
+    fn save(&mut self, groups: &[OrderGroup]) -> Result<(), Error>;
+}
+
+/// Keeps the groups in a json file, replaced atomically on every save.
+#[derive(Debug, Clone)]
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+impl JsonFileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl OrderGroupStore for JsonFileStore {
+    fn load(&mut self) -> Result<Vec<OrderGroup>, Error> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let data = fs::read(&self.path)?;
+        serde_json::from_slice(&data).map_err(|e| Error::String(e.to_string()))
+    }
+
+    fn save(&mut self, groups: &[OrderGroup]) -> Result<(), Error> {
+        let data = serde_json::to_vec_pretty(groups).map_err(|e| Error::String(e.to_string()))?;
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+pub struct OrderGroupManager {
+    groups: Vec<OrderGroup>,
+    store: Option<Box<dyn OrderGroupStore>>,
+}
+
+impl std::fmt::Debug for OrderGroupManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OrderGroupManager")
+            .field("groups", &self.groups)
+            .finish()
+    }
+}
+
+impl Default for OrderGroupManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OrderGroupManager {
+    pub fn new() -> Self {
+        Self {
+            groups: Vec::new(),
+            store: None,
+        }
+    }
+
+    /// Restore the groups saved in `store`, call `reconcile` before trading.
+    pub fn with_store(mut store: Box<dyn OrderGroupStore>) -> Result<Self, Error> {
+        let groups = store.load()?;
+        Ok(Self {
+            groups,
+            store: Some(store),
+        })
+    }
+
+    pub fn groups(&self) -> &[OrderGroup] {
+        &self.groups
+    }
+
+    pub fn get(&self, group_id: &str) -> Option<&OrderGroup> {
+        self.groups.iter().find(|g| g.id == group_id)
+    }
+
+    /// Drop finished groups, returns them.
+    pub fn remove_finished(&mut self) -> Vec<OrderGroup> {
+        let (done, active) = self.groups.drain(..).partition(|g| g.done);
+        self.groups = active;
+        done
+    }
+
+    // Two working orders, the first one to fill cancels the other.
+    pub async fn place_oco(
+        &mut self,
+        session: &Session,
+        first: LegSpec,
+        second: LegSpec,
+    ) -> Result<String, Error> {
+        self.add_group(
+            session,
+            OrderGroup::new(GroupKind::Oco, vec![first, second]),
+        )
+        .await
+    }
+
+    // Entry order, stop loss on the position and take profit levels given as (price, volume).
+    // The take profit volumes must not exceed the entry volume.
+    pub async fn place_bracket(
+        &mut self,
+        session: &Session,
+        entry: LegSpec,
+        stop_loss: impl Into<Price>,
+        take_profits: Vec<(Price, Volume)>,
+    ) -> Result<String, Error> {
+        let stop_loss = stop_loss.into();
+        let tp_volume: Volume = take_profits.iter().map(|(_, v)| *v).sum();
+        if take_profits.is_empty() || tp_volume > entry.volume {
+            return Err(Error::String(format!(
+                "bracket take profit volume {} does not fit entry volume {}",
+                tp_volume, entry.volume
+            )));
+        }
+        let mut legs = vec![entry.clone()];
+        for (price, volume) in take_profits {
+            legs.push(LegSpec {
+                symbol_id: entry.symbol_id,
+                side: entry.side.opposite(),
+                leg_type: LegType::Limit(price),
+                volume,
+                label: entry.label.clone(),
+            });
+        }
+        self.add_group(
+            session,
+            OrderGroup::new(GroupKind::Bracket { stop_loss }, legs),
+        )
+        .await
+    }
+
+    // Each leg is placed after the previous one filled.
+    pub async fn place_if_done(
+        &mut self,
+        session: &Session,
+        legs: Vec<LegSpec>,
+    ) -> Result<String, Error> {
+        if legs.is_empty() {
+            return Err(Error::String("if-done chain without legs".into()));
+        }
+        self.add_group(session, OrderGroup::new(GroupKind::IfDone, legs))
+            .await
+    }
+
+    // Cancel every working leg of the group and stop managing it. Legs whose order is in
+    // flight are cancelled once the server accepts them. On failure the legs cancelled so
+    // far are still persisted and the first error is returned.
+    pub async fn cancel_group(&mut self, session: &Session, group_id: &str) -> Result<(), Error> {
+        let group = self
+            .groups
+            .iter_mut()
+            .find(|g| g.id == group_id)
+            .ok_or(Error::String(format!("unknown order group {}", group_id)))?;
+        group.cancelled = true;
+        let mut errors = Vec::new();
+        for leg in group.legs.iter_mut() {
+            match leg.state {
+                LegState::Pending => leg.state = LegState::Cancelled,
+                LegState::Working => {
+                    if let Some(order_id) = leg.order_id {
+                        leg.cancel_requested = true;
+                        match session.cancel_order(order_id).await {
+                            Ok(_) => leg.state = LegState::Cancelled,
+                            Err(e) => {
+                                warn!("order group {} cancel {} failed: {}", group.id, order_id, e);
+                                leg.cancel_requested = false;
+                                errors.push(e);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        group.done = !group.legs.iter().any(|l| l.is_live());
+        self.persist()?;
+        match errors.into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn on_event(&mut self, session: &Session, event: &NotifyEvent) -> Result<(), Error> {
+        if let NotifyEvent::ExecutionEvent(event) = event {
+            if self.apply_execution(event) {
+                self.step_all(session).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Resolve every leg against the server state, e.g. after a reconnect or a restart,
+    // then cancel legs left orphaned by fills that happened meanwhile.
+    pub async fn reconcile(&mut self, session: &Session) -> Result<(), Error> {
+        let res = session.get_open_position_and_pending_orders().await?;
+
+        for group in self.groups.iter_mut().filter(|g| !g.done) {
+            for leg in group.legs.iter_mut() {
+                leg.cancel_requested = false;
+                match leg.state {
+                    LegState::Working | LegState::Sent => {}
+                    _ => continue,
+                }
+
+                let pending = res.order.iter().find(|o| {
+                    Some(o.order_id) == leg.order_id
+                        || o.client_order_id.as_deref() == Some(leg.client_order_id.as_str())
+                });
+                if let Some(order) = pending {
+                    leg.apply_order(order);
+                    continue;
+                }
+
+                // no longer pending, ask the server what happened to it
+                let order_id = match (leg.order_id, leg.sent_at) {
+                    (Some(id), _) => Some(id),
+                    (None, Some(sent_at)) => {
+                        session
+                            .find_order_by_client_order_id(&leg.client_order_id, sent_at)
+                            .await?
+                    }
+                    // sent without a recorded time, it cannot be looked up safely
+                    (None, None) => {
+                        warn!(
+                            "order group {} leg {} sent at an unknown time, left unresolved",
+                            group.id, leg.client_order_id
+                        );
+                        continue;
+                    }
+                };
+                match order_id {
+                    Some(order_id) => {
+                        let details = session.order_details(order_id).await?;
+                        leg.apply_order(&details.order);
+                    }
+                    // provably never reached the server
+                    None => {
+                        leg.state = LegState::Pending;
+                        leg.sent_at = None;
+                    }
+                }
+            }
+
+            if let GroupKind::Bracket { .. } = group.kind {
+                if let Some(position_id) = group.legs[0].position_id {
+                    group.position_closed = group.legs[0].state == LegState::Filled
+                        && !res.position.iter().any(|p| p.position_id == position_id);
+                }
+            }
+        }
+
+        self.step_all(session).await
+    }
+
+    async fn add_group(&mut self, session: &Session, group: OrderGroup) -> Result<String, Error> {
+        let id = group.id.clone();
+        self.groups.push(group);
+        self.persist()?;
+        self.step_all(session).await?;
+        Ok(id)
+    }
+
+    // Returns true if a leg of a managed group was updated.
+    fn apply_execution(&mut self, event: &ProtoOaExecutionEvent) -> bool {
+        let mut changed = false;
+
+        if let Some(order) = &event.order {
+            let client_order_id = order.client_order_id.as_deref();
+            for group in self.groups.iter_mut().filter(|g| !g.done) {
+                let leg = match group.leg_mut(order.order_id, client_order_id) {
+                    Some(leg) => leg,
+                    None => continue,
+                };
+                leg.order_id = Some(order.order_id);
+                if let Some(position) = &event.position {
+                    leg.position_id = Some(position.position_id);
+                } else if order.position_id.is_some() {
+                    leg.position_id = order.position_id;
+                }
+                match event.execution_type() {
+                    ProtoOaExecutionType::OrderAccepted | ProtoOaExecutionType::OrderReplaced
+                        if !leg.state.is_terminal() =>
+                    {
+                        leg.state = LegState::Working;
+                    }
+                    ProtoOaExecutionType::OrderFilled => {
+                        leg.state = LegState::Filled;
+                        leg.filled_volume = order
+                            .executed_volume
+                            .map(Volume::from_cents)
+                            .unwrap_or(leg.spec.volume);
+                    }
+                    ProtoOaExecutionType::OrderPartialFill => {
+                        leg.state = LegState::Working;
+                        leg.filled_volume = order
+                            .executed_volume
+                            .map(Volume::from_cents)
+                            .unwrap_or(leg.filled_volume);
+                    }
+                    ProtoOaExecutionType::OrderCancelled | ProtoOaExecutionType::OrderExpired => {
+                        leg.state = LegState::Cancelled
+                    }
+                    ProtoOaExecutionType::OrderRejected => leg.state = LegState::Rejected,
+                    ProtoOaExecutionType::OrderCancelRejected => leg.cancel_requested = false,
+                    _ => {}
+                }
+                changed = true;
+            }
+        }
+
+        if let Some(position) = &event.position {
+            if position.position_status() == ProtoOaPositionStatus::PositionStatusClosed {
+                for group in self.groups.iter_mut().filter(|g| !g.done) {
+                    if let GroupKind::Bracket { .. } = group.kind {
+                        if group.legs[0].position_id == Some(position.position_id) {
+                            group.position_closed = true;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    async fn step_all(&mut self, session: &Session) -> Result<(), Error> {
+        for index in 0..self.groups.len() {
+            for _ in 0..MAX_PLAN_ROUNDS {
+                let actions = self.groups[index].plan();
+                if actions.is_empty() {
+                    break;
+                }
+                let mut progress = false;
+                for action in actions {
+                    progress |= self.execute(session, index, action).await;
+                }
+                // failed requests are retried on the next event or reconcile
+                if !progress {
+                    break;
+                }
+            }
+        }
+        self.persist()
+    }
+
+    // Returns false if the request failed and the group state is unchanged.
+    async fn execute(&mut self, session: &Session, index: usize, action: Action) -> bool {
+        let group = &mut self.groups[index];
+        debug!("order group {}: {:?}", group.id, action);
+        match action {
+            Action::Place(leg_index, position_id) => {
+                let leg = &mut group.legs[leg_index];
+                let params = leg.spec.to_params(&leg.client_order_id, position_id);
+                leg.state = LegState::Sent;
+                leg.sent_at = Some(Utc::now().timestamp_millis());
+                // write-ahead: after a crash during the submission the leg is reloaded as
+                // Sent and looked up by reconcile instead of being placed twice
+                if let Err(e) = self.persist() {
+                    let group = &mut self.groups[index];
+                    error!("order group {} leg not persisted: {}", group.id, e);
+                    let leg = &mut group.legs[leg_index];
+                    leg.state = LegState::Pending;
+                    leg.sent_at = None;
+                    return false;
+                }
+                let group = &mut self.groups[index];
+                let leg = &mut group.legs[leg_index];
+                match session.submit_order(params, SUBMIT_ATTEMPTS).await {
+                    Ok(SubmitOutcome::Placed(event)) => {
+                        if let Some(order) = &event.order {
+                            leg.apply_order(order);
+                        }
+                        if let Some(position) = &event.position {
+                            leg.position_id = Some(position.position_id);
+                        }
+                    }
+                    Ok(SubmitOutcome::AlreadyExists(details)) => leg.apply_order(&details.order),
+                    Ok(SubmitOutcome::Unknown { error, .. }) => {
+                        warn!("order group {} leg state unknown: {}", group.id, error)
+                    }
+                    // never reached the server, placed again on the next event or reconcile
+                    Ok(SubmitOutcome::NotPlaced { error, .. }) => {
+                        warn!("order group {} leg not placed: {}", group.id, error);
+                        leg.state = LegState::Pending;
+                        leg.sent_at = None;
+                        return false;
+                    }
+                    Err(e) => {
+                        error!("order group {} leg rejected: {}", group.id, e);
+                        leg.state = LegState::Rejected;
+                    }
+                }
+                true
+            }
+            Action::Cancel(order_id) => match session.cancel_order(order_id).await {
+                Ok(_) => true,
+                Err(e) => {
+                    warn!("order group {} cancel {} failed: {}", group.id, order_id, e);
+                    if let Some(leg) = group.leg_mut(order_id, None) {
+                        leg.cancel_requested = false;
+                    }
+                    false
+                }
+            },
+            Action::SetStopLoss(position_id, stop_loss) => {
+                match session
+                    .modify_position_sltp(
+                        position_id,
+                        Some(stop_loss),
+                        None,
+                        None,
+                        None,
+                        None,
+                    )
+                    .await
+                {
+                    Ok(_) => {
+                        group.stop_loss_applied = true;
+                        true
+                    }
+                    Err(e) => {
+                        warn!("order group {} stop loss failed: {}", group.id, e);
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    fn persist(&mut self) -> Result<(), Error> {
+        if let Some(store) = self.store.as_mut() {
+            let active = self
+                .groups
+                .iter()
+                .filter(|g| !g.done)
+                .cloned()
+                .collect::<Vec<_>>();
+            store.save(&active)?;
+        }
+        Ok(())
+    }
+}
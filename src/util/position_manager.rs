@@ -0,0 +1,434 @@
+//! Client side stop management: break-even, ATR trailing, step trailing and timed exit.
+//!
+//! The server side `trailing_stop_loss` only trails at a fixed distance. The
+//! `PositionManager` computes the stop of every managed position from its rules on each
+//! spot and amends it with `modify_position_sltp`. Feed it every `NotifyEvent`, call
+//! `on_timer` about once a second and `on_bar` with closed bars when ATR trailing is used.
+use std::collections::HashMap;
+
+use chrono::Utc;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::util::algo::RequestPacer;
+use crate::util::bar_gen::Candle;
+use crate::util::indicators::{Atr, Hlc, Indicator};
+use crate::util::units::{Price, Volume};
+use crate::util::SymbolStore;
+use crate::{protos::spotware_message::*, Error, NotifyEvent, Session};
+
+// minimal pause between two amendments of the same position
+const DEFAULT_AMEND_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopRule {
+    /// Move the stop to entry + offset_pips once the profit reaches trigger_pips
+    BreakEven { trigger_pips: f64, offset_pips: f64 },
+    /// Trail the stop multiplier * ATR(period) behind the price, needs `on_bar`
+    AtrTrailing { period: usize, multiplier: f64 },
+    /// Every step_pips of profit move the stop by step_pips, keeping it distance_pips behind
+    Step { step_pips: f64, distance_pips: f64 },
+    /// Close the position once it is open for longer than the duration
+    TimedExit { after: Duration },
+}
+
+#[derive(Debug, Clone)]
+pub struct ManagedPosition {
+    pub position_id: i64,
+    pub symbol_id: i64,
+    pub trade_side: ProtoOaTradeSide,
+    pub volume: Volume,
+    pub entry_price: Price,
+    pub stop_loss: Option<Price>,
+    pub take_profit: Option<Price>,
+    /// Unix time in milliseconds
+    pub open_timestamp: i64,
+    pub rules: Vec<StopRule>,
+    pip_size: f64,
+    digits: i32,
+    last_amend: Option<Instant>,
+    exit_sent: bool,
+}
+
+impl ManagedPosition {
+    fn is_buy(&self) -> bool {
+        self.trade_side == ProtoOaTradeSide::Buy
+    }
+
+    fn update(&mut self, position: &ProtoOaPosition) {
+        self.volume = Volume::from_cents(position.trade_data.volume);
+        self.stop_loss = position.stop_loss.map(Price::from_f64);
+        self.take_profit = position.take_profit.map(Price::from_f64);
+        if let Some(price) = position.price {
+            self.entry_price = Price::from_f64(price);
+        }
+    }
+
+    // profit in pips at the closing price
+    fn profit_pips(&self, price: f64) -> f64 {
+        let entry_price = self.entry_price.to_f64();
+        let diff = if self.is_buy() {
+            price - entry_price
+        } else {
+            entry_price - price
+        };
+        diff / self.pip_size
+    }
+
+    // price `pips` away from the entry in the profit direction
+    fn entry_plus(&self, pips: f64) -> f64 {
+        let entry_price = self.entry_price.to_f64();
+        if self.is_buy() {
+            entry_price + pips * self.pip_size
+        } else {
+            entry_price - pips * self.pip_size
+        }
+    }
+
+    // price `distance` behind the closing price
+    fn behind(&self, price: f64, distance: f64) -> f64 {
+        if self.is_buy() {
+            price - distance
+        } else {
+            price + distance
+        }
+    }
+
+    // the tighter of two stops
+    fn tighter(&self, a: f64, b: f64) -> f64 {
+        if self.is_buy() {
+            a.max(b)
+        } else {
+            a.min(b)
+        }
+    }
+
+    // the stop wanted by the rules, None if no rule applies yet
+    fn wanted_stop(&self, price: f64, atr: &HashMap<(i64, usize), Atr>) -> Option<f64> {
+        let profit = self.profit_pips(price);
+        let mut wanted: Option<f64> = None;
+
+        for rule in self.rules.iter() {
+            let stop = match *rule {
+                StopRule::BreakEven {
+                    trigger_pips,
+                    offset_pips,
+                } if profit >= trigger_pips => self.entry_plus(offset_pips),
+                StopRule::AtrTrailing { period, multiplier } => {
+                    match atr.get(&(self.symbol_id, period)).and_then(|a| a.value()) {
+                        Some(value) => self.behind(price, value * multiplier),
+                        None => continue,
+                    }
+                }
+                StopRule::Step {
+                    step_pips,
+                    distance_pips,
+                } if step_pips > 0.0 && profit >= step_pips => {
+                    let steps = (profit / step_pips).floor();
+                    self.entry_plus(steps * step_pips - distance_pips)
+                }
+                _ => continue,
+            };
+            wanted = Some(match wanted {
+                Some(w) => self.tighter(w, stop),
+                None => stop,
+            });
+        }
+
+        wanted.map(|stop| Price::from_f64(stop).round_to_digits(self.digits).to_f64())
+    }
+
+    // a stop only moves in the profit direction and must stay behind the price
+    fn improves(&self, stop: f64, price: f64) -> bool {
+        let point = Price::from_points(1, self.digits).to_f64();
+        let behind_price = if self.is_buy() {
+            stop < price
+        } else {
+            stop > price
+        };
+        let tighter = match self.stop_loss.map(Price::to_f64) {
+            Some(current) if self.is_buy() => stop >= current + point,
+            Some(current) => stop <= current - point,
+            None => true,
+        };
+        behind_price && tighter
+    }
+
+    fn exit_due(&self, now: i64) -> bool {
+        self.rules.iter().any(|rule| match rule {
+            StopRule::TimedExit { after } => now - self.open_timestamp >= after.as_millis() as i64,
+            _ => false,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PositionManager {
+    positions: HashMap<i64, ManagedPosition>,
+    // (symbol id, period) => ATR
+    atr: HashMap<(i64, usize), Atr>,
+    // latest closing price of buy (bid) and sell (ask) positions per symbol
+    quotes: HashMap<i64, (Option<f64>, Option<f64>)>,
+    amend_interval: Duration,
+    pacer: RequestPacer,
+}
+
+impl Default for PositionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PositionManager {
+    pub fn new() -> Self {
+        Self {
+            positions: HashMap::new(),
+            atr: HashMap::new(),
+            quotes: HashMap::new(),
+            amend_interval: DEFAULT_AMEND_INTERVAL,
+            pacer: RequestPacer::default(),
+        }
+    }
+
+    // minimal pause between two amendments of the same position
+    pub fn set_amend_interval(&mut self, amend_interval: Duration) -> &mut Self {
+        self.amend_interval = amend_interval;
+        self
+    }
+
+    // pacer for all amendments and closes, can be shared with the execution algos
+    pub fn set_pacer(&mut self, pacer: RequestPacer) -> &mut Self {
+        self.pacer = pacer;
+        self
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = &ManagedPosition> {
+        self.positions.values()
+    }
+
+    pub fn get(&self, position_id: i64) -> Option<&ManagedPosition> {
+        self.positions.get(&position_id)
+    }
+
+    // Start managing an open position, replaces the rules if it is managed already.
+    // The symbol must be in the store for its pip size and digits.
+    pub fn manage(
+        &mut self,
+        position: &ProtoOaPosition,
+        symbols: &SymbolStore,
+        rules: Vec<StopRule>,
+    ) -> Result<(), Error> {
+        let symbol_id = position.trade_data.symbol_id;
+        let info = symbols
+            .get_info_by_id(symbol_id)
+            .ok_or(Error::String(format!("unknown symbol {}", symbol_id)))?;
+        let entry_price = position.price.ok_or(Error::String(format!(
+            "position {} without price",
+            position.position_id
+        )))?;
+
+        for rule in rules.iter() {
+            if let StopRule::AtrTrailing { period, .. } = rule {
+                self.atr
+                    .entry((symbol_id, *period))
+                    .or_insert_with(|| Atr::new(*period));
+            }
+        }
+
+        self.positions.insert(
+            position.position_id,
+            ManagedPosition {
+                position_id: position.position_id,
+                symbol_id,
+                trade_side: position.trade_data.trade_side(),
+                volume: Volume::from_cents(position.trade_data.volume),
+                entry_price: Price::from_f64(entry_price),
+                stop_loss: position.stop_loss.map(Price::from_f64),
+                take_profit: position.take_profit.map(Price::from_f64),
+                open_timestamp: position
+                    .trade_data
+                    .open_timestamp
+                    .unwrap_or_else(|| Utc::now().timestamp_millis()),
+                rules,
+                pip_size: Price::from_pips(1.0, info.info.pip_position).to_f64(),
+                digits: info.info.digits,
+                last_amend: None,
+                exit_sent: false,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn unmanage(&mut self, position_id: i64) -> Option<ManagedPosition> {
+        self.positions.remove(&position_id)
+    }
+
+    // Feed a closed bar of the symbol, drives the AtrTrailing rules.
+    pub fn on_bar(&mut self, symbol_id: i64, bar: &Candle) {
+        for ((id, _), atr) in self.atr.iter_mut() {
+            if *id == symbol_id {
+                atr.update(Hlc::from(bar));
+            }
+        }
+    }
+
+    pub async fn on_event(&mut self, session: &Session, event: &NotifyEvent) -> Result<(), Error> {
+        match event {
+            NotifyEvent::SpotEvent(spot) => {
+                let quote = self.quotes.entry(spot.symbol_id).or_default();
+                if let Some(bid) = spot.bid {
+                    quote.0 = Some(Price::from_raw(bid as i64).to_f64());
+                }
+                if let Some(ask) = spot.ask {
+                    quote.1 = Some(Price::from_raw(ask as i64).to_f64());
+                }
+                self.step_symbol(session, Some(spot.symbol_id)).await
+            }
+            NotifyEvent::ExecutionEvent(event) => {
+                if let Some(position) = &event.position {
+                    self.apply_position(position);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Runs timed exits and amendments held back by the amend interval.
+    pub async fn on_timer(&mut self, session: &Session) -> Result<(), Error> {
+        self.step_symbol(session, None).await
+    }
+
+    // Drop positions closed meanwhile, e.g. after a reconnect.
+    pub async fn reconcile(&mut self, session: &Session) -> Result<(), Error> {
+        let res = session.get_open_position_and_pending_orders().await?;
+        self.positions
+            .retain(|id, _| res.position.iter().any(|p| p.position_id == *id));
+        for position in res.position.iter() {
+            self.apply_position(position);
+        }
+        Ok(())
+    }
+
+    fn apply_position(&mut self, position: &ProtoOaPosition) {
+        if position.position_status() == ProtoOaPositionStatus::PositionStatusClosed {
+            if self.positions.remove(&position.position_id).is_some() {
+                debug!(
+                    "position {} closed, no longer managed",
+                    position.position_id
+                );
+            }
+            return;
+        }
+        if let Some(managed) = self.positions.get_mut(&position.position_id) {
+            managed.update(position);
+        }
+    }
+
+    async fn step_symbol(
+        &mut self,
+        session: &Session,
+        symbol_id: Option<i64>,
+    ) -> Result<(), Error> {
+        let now = Utc::now().timestamp_millis();
+        let position_ids = self
+            .positions
+            .values()
+            .filter(|p| symbol_id.is_none_or(|id| id == p.symbol_id))
+            .map(|p| p.position_id)
+            .collect::<Vec<_>>();
+
+        for position_id in position_ids {
+            let position = &self.positions[&position_id];
+
+            if position.exit_due(now) {
+                if !position.exit_sent {
+                    self.close(session, position_id).await?;
+                }
+                continue;
+            }
+
+            let quote = self
+                .quotes
+                .get(&position.symbol_id)
+                .copied()
+                .unwrap_or_default();
+            let price = match if position.is_buy() { quote.0 } else { quote.1 } {
+                Some(price) => price,
+                None => continue,
+            };
+            let stop = match position.wanted_stop(price, &self.atr) {
+                Some(stop) if position.improves(stop, price) => stop,
+                _ => continue,
+            };
+            if position
+                .last_amend
+                .is_some_and(|t| t.elapsed() < self.amend_interval)
+            {
+                continue;
+            }
+            self.amend(session, position_id, stop).await?;
+        }
+        Ok(())
+    }
+
+    async fn amend(&mut self, session: &Session, position_id: i64, stop: f64) -> Result<(), Error> {
+        let take_profit = match self.positions.get_mut(&position_id) {
+            Some(position) => {
+                position.last_amend = Some(Instant::now());
+                position.take_profit
+            }
+            None => return Ok(()),
+        };
+
+        self.pacer.wait().await;
+        debug!("position {} stop loss -> {}", position_id, stop);
+        match session
+            .modify_position_sltp(
+                position_id,
+                Some(Price::from_f64(stop)),
+                take_profit,
+                None,
+                None,
+                None,
+            )
+            .await
+        {
+            Ok(event) => {
+                if let Some(position) = &event.position {
+                    self.apply_position(position);
+                }
+                Ok(())
+            }
+            Err(e @ Error::SpotwareError(_)) => {
+                // e.g. position closed meanwhile or stop too close, next spot tries again
+                warn!("amend position {} failed: {}", position_id, e);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn close(&mut self, session: &Session, position_id: i64) -> Result<(), Error> {
+        let volume = match self.positions.get_mut(&position_id) {
+            Some(position) => {
+                position.exit_sent = true;
+                position.volume
+            }
+            None => return Ok(()),
+        };
+
+        self.pacer.wait().await;
+        debug!("position {} timed exit", position_id);
+        if let Err(e) = session
+            .close_position(position_id, volume)
+            .await
+        {
+            if let Some(position) = self.positions.get_mut(&position_id) {
+                position.exit_sent = false;
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,414 @@
+//! Builds bars the server does not serve from M1 bars, ticks or live spots:
+//! any time frame (M2, M7, H2, H8, ...), tick, volume, range and Renko bars,
+//! plus Heikin-Ashi over any bar series.
+//!
+//! Bars fed in are walked open, low/high, close (low first on an up bar), so
+//! price based bars built from M1 bars are an approximation of the tick path.
+use chrono::{NaiveTime, Timelike};
+
+use crate::{
+    protos::spotware_message::ProtoOaSpotEvent,
+    util::{download::Kline, tick_download::Tick, time_util::from_mill_seconds, units::Price},
+};
+
+const MILLIS_PER_DAY: i64 = 86_400_000;
+
+/// Where time buckets start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Alignment {
+    /// Buckets restart at 00:00 UTC every day
+    #[default]
+    Utc,
+    /// Buckets restart at the session open every day, given in UTC (e.g. 22:00)
+    SessionOpen(NaiveTime),
+}
+
+/// The kind of bar to build.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarSpec {
+    /// Time bars of any number of minutes. Up to one day, the last bucket of the day is
+    /// cut at the next day (or session) start, longer ones count from the epoch.
+    Time { minutes: u32, alignment: Alignment },
+    /// A bar every n price updates
+    Tick(u64),
+    /// A bar once the volume reaches the threshold
+    Volume(u64),
+    /// Bars of exactly this high - low
+    Range(f64),
+    /// Bricks of this size, a reversal needs two bricks
+    Renko(f64),
+}
+
+impl BarSpec {
+    pub fn minutes(minutes: u32) -> Self {
+        BarSpec::Time {
+            minutes,
+            alignment: Alignment::Utc,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Turns bars, ticks or spot events into bars of `BarSpec`, in an online (streaming) manner
+pub struct Resampler {
+    spec: BarSpec,
+    current: Option<Bar>,
+    updates: u64,
+    last_bid: Option<f64>,
+    /// open and close of the last Renko brick, or the first price before any brick
+    renko: Option<(f64, f64)>,
+}
+
+// bar under construction, timestamps in milliseconds
+#[derive(Debug, Clone, Copy)]
+struct Bar {
+    timestamp: i64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    vol: u64,
+}
+
+impl Bar {
+    fn new(timestamp: i64, price: f64) -> Self {
+        Self {
+            timestamp,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            vol: 0,
+        }
+    }
+
+    fn update(&mut self, price: f64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+    }
+
+    fn kline(&self) -> Kline {
+        Kline {
+            timestamp: from_mill_seconds(self.timestamp),
+            open: self.open,
+            high: self.high,
+            low: self.low,
+            close: self.close,
+            vol: self.vol,
+        }
+    }
+}
+
+impl Resampler {
+    pub fn new(spec: BarSpec) -> Self {
+        Self {
+            spec,
+            current: None,
+            updates: 0,
+            last_bid: None,
+            renko: None,
+        }
+    }
+
+    pub fn spec(&self) -> BarSpec {
+        self.spec
+    }
+
+    // The bar under construction, for Renko the move since the last brick.
+    pub fn current(&self) -> Option<Kline> {
+        self.current.as_ref().map(Bar::kline)
+    }
+
+    // Feed a closed bar (e.g. M1). Returns the bars completed by it.
+    pub fn update_bar(&mut self, kline: &Kline) -> Vec<Kline> {
+        let timestamp = kline.timestamp.timestamp_millis();
+        if let BarSpec::Time { .. } = self.spec {
+            let bucket = self.bucket_start(timestamp);
+            let mut closed = Vec::new();
+            match self.current.as_mut() {
+                Some(bar) if bar.timestamp == bucket => {
+                    bar.high = bar.high.max(kline.high);
+                    bar.low = bar.low.min(kline.low);
+                    bar.close = kline.close;
+                    bar.vol += kline.vol;
+                }
+                _ => {
+                    closed.extend(self.current.take().map(|bar| bar.kline()));
+                    self.current = Some(Bar {
+                        timestamp: bucket,
+                        open: kline.open,
+                        high: kline.high,
+                        low: kline.low,
+                        close: kline.close,
+                        vol: kline.vol,
+                    });
+                }
+            }
+            return closed;
+        }
+
+        // one update per bar, the volume goes with the close
+        let path = if kline.close >= kline.open {
+            [kline.open, kline.low, kline.high]
+        } else {
+            [kline.open, kline.high, kline.low]
+        };
+        let mut closed = Vec::new();
+        for price in path {
+            closed.extend(self.apply(timestamp, price, 0, false));
+        }
+        closed.extend(self.apply(timestamp, kline.close, kline.vol, true));
+        closed
+    }
+
+    // Feed a price change with its volume (0 when unknown). Returns the bars completed by it.
+    pub fn update_tick(&mut self, timestamp: i64, price: f64, vol: u64) -> Vec<Kline> {
+        self.apply(timestamp, price, vol, true)
+    }
+
+    // Feed a live spot event, bars follow the bid like BarGenerator. Spot events carry no
+    // volume, so volume bars need bars or ticks.
+    pub fn update_spot(&mut self, event: &ProtoOaSpotEvent) -> Vec<Kline> {
+        if let Some(bid) = event.bid {
+            self.last_bid = Some(Price::from_raw(bid as i64).to_f64());
+        }
+        match (self.last_bid, event.timestamp) {
+            (Some(bid), Some(timestamp)) => self.update_tick(timestamp, bid, 0),
+            _ => Vec::new(),
+        }
+    }
+
+    // Close the current time bar once its bucket has ended, so a quiet market still
+    // completes bars. Call it from a timer with the current time in milliseconds.
+    pub fn poll(&mut self, now: i64) -> Option<Kline> {
+        let minutes = match self.spec {
+            BarSpec::Time { minutes, .. } => minutes.max(1),
+            _ => return None,
+        };
+        let bar = self.current?;
+        let length = minutes as i64 * 60_000;
+        let mut end = bar.timestamp + length;
+        // buckets of a day or more count from the epoch, not from the session day
+        if length < MILLIS_PER_DAY {
+            end = end.min(self.next_day_start(bar.timestamp));
+        }
+        if now >= end {
+            self.current = None;
+            return Some(bar.kline());
+        }
+        None
+    }
+
+    // Take the unfinished bar, e.g. at the end of a batch. Renko has none, the
+    // move since the last brick is not a brick.
+    pub fn flush(&mut self) -> Option<Kline> {
+        self.updates = 0;
+        let bar = self.current.take()?;
+        match self.spec {
+            BarSpec::Renko(_) => None,
+            _ => Some(bar.kline()),
+        }
+    }
+
+    fn apply(&mut self, timestamp: i64, price: f64, vol: u64, counted: bool) -> Vec<Kline> {
+        let mut closed = Vec::new();
+        match self.spec {
+            BarSpec::Time { .. } => {
+                let bucket = self.bucket_start(timestamp);
+                if self.current.is_some_and(|bar| bar.timestamp != bucket) {
+                    closed.extend(self.current.take().map(|bar| bar.kline()));
+                }
+                let bar = self.current.get_or_insert(Bar::new(bucket, price));
+                bar.update(price);
+                bar.vol += vol;
+            }
+            BarSpec::Tick(count) => {
+                let bar = self.current.get_or_insert(Bar::new(timestamp, price));
+                bar.update(price);
+                bar.vol += vol;
+                if counted {
+                    self.updates += 1;
+                    if self.updates >= count.max(1) {
+                        self.updates = 0;
+                        closed.extend(self.current.take().map(|bar| bar.kline()));
+                    }
+                }
+            }
+            BarSpec::Volume(threshold) => {
+                let bar = self.current.get_or_insert(Bar::new(timestamp, price));
+                bar.update(price);
+                bar.vol += vol;
+                if bar.vol >= threshold.max(1) {
+                    closed.extend(self.current.take().map(|bar| bar.kline()));
+                }
+            }
+            BarSpec::Range(range) => self.apply_range(timestamp, price, vol, range, &mut closed),
+            BarSpec::Renko(size) => self.apply_renko(timestamp, price, vol, size, &mut closed),
+        }
+        closed
+    }
+
+    // a bar closes as soon as its high - low reaches the range, a gap closes several
+    fn apply_range(
+        &mut self,
+        timestamp: i64,
+        price: f64,
+        vol: u64,
+        range: f64,
+        closed: &mut Vec<Kline>,
+    ) {
+        let mut bar = self.current.unwrap_or(Bar::new(timestamp, price));
+        if range > 0.0 {
+            loop {
+                let edge = if price - bar.low >= range {
+                    bar.low + range
+                } else if bar.high - price >= range {
+                    bar.high - range
+                } else {
+                    break;
+                };
+                bar.update(edge);
+                closed.push(bar.kline());
+                bar = Bar::new(timestamp, edge);
+            }
+        }
+        bar.update(price);
+        bar.vol += vol;
+        self.current = Some(bar);
+    }
+
+    fn apply_renko(
+        &mut self,
+        timestamp: i64,
+        price: f64,
+        vol: u64,
+        size: f64,
+        closed: &mut Vec<Kline>,
+    ) {
+        let bar = self.current.get_or_insert(Bar::new(timestamp, price));
+        bar.update(price);
+        bar.vol += vol;
+        let (mut open, mut close) = *self.renko.get_or_insert((price, price));
+        if size <= 0.0 {
+            return;
+        }
+
+        loop {
+            let up = close > open;
+            let down = close < open;
+            let brick = if price >= close + size && !down {
+                (close, close + size)
+            } else if price <= close - size && !up {
+                (close, close - size)
+            } else if up && price <= open - size {
+                (open, open - size)
+            } else if down && price >= open + size {
+                (open, open + size)
+            } else {
+                break;
+            };
+            let moving = self.current.take().unwrap_or(Bar::new(timestamp, price));
+            closed.push(
+                Bar {
+                    timestamp: moving.timestamp,
+                    open: brick.0,
+                    high: brick.0.max(brick.1),
+                    low: brick.0.min(brick.1),
+                    close: brick.1,
+                    vol: moving.vol,
+                }
+                .kline(),
+            );
+            (open, close) = brick;
+            self.current = Some(Bar::new(timestamp, price));
+        }
+        self.renko = Some((open, close));
+    }
+
+    fn session_offset(&self) -> i64 {
+        match self.spec {
+            BarSpec::Time {
+                alignment: Alignment::SessionOpen(open),
+                ..
+            } => open.num_seconds_from_midnight() as i64 * 1000,
+            _ => 0,
+        }
+    }
+
+    fn next_day_start(&self, timestamp: i64) -> i64 {
+        let offset = self.session_offset();
+        offset + (timestamp - offset).div_euclid(MILLIS_PER_DAY) * MILLIS_PER_DAY + MILLIS_PER_DAY
+    }
+
+    fn bucket_start(&self, timestamp: i64) -> i64 {
+        let minutes = match self.spec {
+            BarSpec::Time { minutes, .. } => minutes.max(1),
+            _ => return timestamp,
+        };
+        let length = minutes as i64 * 60_000;
+        let offset = self.session_offset();
+        if length >= MILLIS_PER_DAY {
+            return offset + (timestamp - offset).div_euclid(length) * length;
+        }
+        let day_start = offset + (timestamp - offset).div_euclid(MILLIS_PER_DAY) * MILLIS_PER_DAY;
+        day_start + (timestamp - day_start) / length * length
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Heikin-Ashi bars in an online (streaming) manner
+pub struct HeikinAshi {
+    last: Option<(f64, f64)>,
+}
+
+impl HeikinAshi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, kline: &Kline) -> Kline {
+        let close = (kline.open + kline.high + kline.low + kline.close) / 4.0;
+        let open = match self.last {
+            Some((last_open, last_close)) => (last_open + last_close) / 2.0,
+            None => (kline.open + kline.close) / 2.0,
+        };
+        self.last = Some((open, close));
+        Kline {
+            timestamp: kline.timestamp,
+            open,
+            high: kline.high.max(open).max(close),
+            low: kline.low.min(open).min(close),
+            close,
+            vol: kline.vol,
+        }
+    }
+}
+
+// Resample closed bars, the last unfinished bar is included (except for Renko).
+pub fn resample(bars: &[Kline], spec: BarSpec) -> Vec<Kline> {
+    let mut resampler = Resampler::new(spec);
+    let mut result = Vec::new();
+    for bar in bars {
+        result.extend(resampler.update_bar(bar));
+    }
+    result.extend(resampler.flush());
+    result
+}
+
+// Build bars from ticks, the last unfinished bar is included (except for Renko).
+// Each tick counts as volume 1, like the tick volume of the server's trend bars.
+pub fn resample_ticks(ticks: &[Tick], spec: BarSpec) -> Vec<Kline> {
+    let mut resampler = Resampler::new(spec);
+    let mut result = Vec::new();
+    for tick in ticks {
+        result.extend(resampler.update_tick(tick.timestamp, tick.price, 1));
+    }
+    result.extend(resampler.flush());
+    result
+}
+
+pub fn heikin_ashi(bars: &[Kline]) -> Vec<Kline> {
+    let mut ha = HeikinAshi::new();
+    bars.iter().map(|bar| ha.update(bar)).collect()
+}
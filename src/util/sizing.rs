@@ -0,0 +1,135 @@
+//! Pip value and position size in the deposit currency.
+//!
+//! A `PositionSizer` holds the scaling values and volume limits of one symbol with the
+//! rate of its quote asset in the deposit currency, so "how many lots for 1% risk with a
+//! 25 pip stop" is plain arithmetic. `PositionSizer::for_symbol` takes the rate from a
+//! `CurrencyConverter`. Sizes are rounded down to the volume step and kept within the
+//! symbol's minimum and maximum volume.
+use crate::domain::Account;
+use crate::util::conversion::CurrencyConverter;
+use crate::util::symbol_info::SpotwareSymbolInfo;
+use crate::util::units::{Money, SymbolUnits, Volume};
+use crate::{Error, Session};
+
+/// Margin the server expects for a volume, see `Session::expected_margin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarginEstimate {
+    pub volume: Volume,
+    pub buy: Money,
+    pub sell: Money,
+}
+
+#[derive(Debug, Clone)]
+pub struct PositionSizer {
+    pub symbol_id: i64,
+    pub units: SymbolUnits,
+    pub min_volume: Option<Volume>,
+    pub max_volume: Option<Volume>,
+    /// Deposit currency per unit of the symbol's quote asset
+    pub quote_rate: f64,
+    pub money_digits: u32,
+}
+
+impl PositionSizer {
+    pub fn new(info: &SpotwareSymbolInfo, quote_rate: f64, money_digits: u32) -> Self {
+        Self {
+            symbol_id: info.info.symbol_id,
+            units: SymbolUnits::from(&info.info),
+            min_volume: info.info.min_volume.map(Volume::from_cents),
+            max_volume: info.info.max_volume.map(Volume::from_cents),
+            quote_rate,
+            money_digits,
+        }
+    }
+
+    // Sizer of a symbol in the session store, valued in the deposit currency of `account`
+    // at the live rate.
+    pub async fn for_symbol(
+        session: &mut Session,
+        converter: &mut CurrencyConverter,
+        account: &Account,
+        symbol_id: i64,
+    ) -> Result<Self, Error> {
+        let info = session
+            .store
+            .get_info_by_id(symbol_id)
+            .cloned()
+            .ok_or_else(|| Error::String(format!("unknown symbol {}", symbol_id)))?;
+        let quote_rate = converter
+            .live_rate(session, info.quote_asset_id, account.deposit_asset_id)
+            .await?;
+        Ok(Self::new(&info, quote_rate, account.money_digits))
+    }
+
+    // Value of one pip of `volume`.
+    pub fn pip_value(&self, volume: Volume) -> Money {
+        Money::from_f64(self.pip_value_f64(volume), self.money_digits)
+    }
+
+    pub fn pip_value_per_lot(&self) -> Money {
+        self.pip_value(Volume::from_cents(self.units.lot_size))
+    }
+
+    // Largest valid volume losing at most `risk` when the stop `stop_pips` away is hit.
+    // None when even the minimum volume risks more.
+    pub fn size_for_risk(&self, risk: Money, stop_pips: f64) -> Option<Volume> {
+        let per_unit = stop_pips * self.units.pip().to_f64() * self.quote_rate;
+        if per_unit <= 0.0 || risk.to_f64() <= 0.0 {
+            return None;
+        }
+        self.normalize_volume(Volume::from_units(risk.to_f64() / per_unit))
+    }
+
+    // As `size_for_risk`, risking `percent` (1.0 for 1%) of `equity`.
+    pub fn size_for_risk_percent(
+        &self,
+        equity: Money,
+        percent: f64,
+        stop_pips: f64,
+    ) -> Option<Volume> {
+        let risk = Money::from_f64(equity.to_f64() * percent / 100.0, equity.digits());
+        self.size_for_risk(risk, stop_pips)
+    }
+
+    // Stop distance in pips at which `volume` loses `risk`.
+    pub fn stop_pips_for_size(&self, volume: Volume, risk: Money) -> Option<f64> {
+        let pip_value = self.pip_value_f64(volume);
+        (pip_value > 0.0).then(|| risk.to_f64() / pip_value)
+    }
+
+    // Down to the volume step and at most the maximum volume, None below the minimum.
+    pub fn normalize_volume(&self, volume: Volume) -> Option<Volume> {
+        let mut volume = volume.round_to_step(self.units.step_volume);
+        if let Some(max) = self.max_volume {
+            volume = volume.min(max.round_to_step(self.units.step_volume));
+        }
+        let min = self.min_volume.unwrap_or(Volume::from_cents(1));
+        (volume >= min && volume > Volume::ZERO).then_some(volume)
+    }
+
+    // Margin of `volume` as computed by the server.
+    pub async fn margin_required(
+        &self,
+        session: &Session,
+        volume: Volume,
+    ) -> Result<MarginEstimate, Error> {
+        let res = session
+            .expected_margin(self.symbol_id, vec![volume])
+            .await?;
+        let digits = res.money_digits.unwrap_or(self.money_digits);
+        let margin = res
+            .margin
+            .iter()
+            .find(|m| m.volume == volume.cents())
+            .ok_or_else(|| Error::String(format!("no expected margin for {}", volume)))?;
+        Ok(MarginEstimate {
+            volume,
+            buy: Money::new(margin.buy_margin, digits),
+            sell: Money::new(margin.sell_margin, digits),
+        })
+    }
+
+    fn pip_value_f64(&self, volume: Volume) -> f64 {
+        volume.units() * self.units.pip().to_f64() * self.quote_rate
+    }
+}
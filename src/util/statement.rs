@@ -0,0 +1,632 @@
+//! Account statements for a period, as CSV or as a self-contained HTML report.
+//!
+//! `Statement::build` fetches the deals and cash flows from the start of the period up to
+//! now, paginated, with the account and its open positions. The balances come from the
+//! balance after each deal or cash flow: the closing balance is the one after the last
+//! operation of the period, or rolled back from the first one after it (or the current
+//! balance) when the period has none. Positions open at the end of the period are the
+//! current positions opened before it, corrected by the deals executed since.
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::domain::{Account, Deal, Position, PositionStatus, Side, DEFAULT_MONEY_DIGITS};
+use crate::protos::spotware_message::ProtoOaDepositWithdraw;
+use crate::util::export::csv_error;
+use crate::util::time_util::from_mill_seconds;
+use crate::util::units::{Money, Price, Volume};
+use crate::util::SymbolStore;
+use crate::{Error, Session};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementTrade {
+    pub deal_id: i64,
+    pub position_id: i64,
+    pub symbol: String,
+    /// Side of the closed position
+    pub side: Side,
+    pub volume: Volume,
+    pub entry_price: String,
+    pub close_price: String,
+    pub close_time: DateTime<Utc>,
+    pub gross_profit: Money,
+    pub swap: Money,
+    pub commission: Money,
+    pub net_profit: Money,
+    pub balance: Money,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementCashFlow {
+    pub balance_history_id: i64,
+    pub time: DateTime<Utc>,
+    /// Negative for withdrawals
+    pub amount: Money,
+    pub balance: Money,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementPosition {
+    pub position_id: i64,
+    pub symbol: String,
+    pub side: Side,
+    /// Volume at the end of the period
+    pub volume: Volume,
+    pub entry_price: String,
+    pub open_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub account_id: i64,
+    pub login: Option<i64>,
+    pub broker_name: Option<String>,
+    pub currency: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub generated_at: DateTime<Utc>,
+    pub opening_balance: Money,
+    pub closing_balance: Money,
+    pub deposits: Money,
+    /// Negative
+    pub withdrawals: Money,
+    /// Gross profit of the closed trades
+    pub realized_pnl: Money,
+    pub commission: Money,
+    pub swap: Money,
+    /// Realized profit with commission and swap
+    pub net_pnl: Money,
+    pub trades: Vec<StatementTrade>,
+    pub cash_flows: Vec<StatementCashFlow>,
+    pub open_positions: Vec<StatementPosition>,
+}
+
+// a balance change: (time, amount, balance after)
+type BalanceChange = (DateTime<Utc>, Money, Money);
+
+impl Statement {
+    // Statement of [from, to).
+    pub async fn build(
+        session: &Session,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Self, Error> {
+        if from >= to {
+            return Err(Error::TimeRangeError(
+                from.timestamp_millis(),
+                to.timestamp_millis(),
+            ));
+        }
+        let now = Utc::now();
+        let account = session.account_info().await?;
+        let deals = session.deal_history(from, now.max(to)).await?;
+        let cash_flows = session
+            .get_historical_cash_flow_list_all(
+                from.timestamp_millis(),
+                now.max(to).timestamp_millis(),
+            )
+            .await?;
+        let positions = session.open_positions().await?;
+        Ok(Self::from_history(
+            &account,
+            &session.store,
+            from,
+            to,
+            &deals,
+            &cash_flows,
+            &positions,
+        ))
+    }
+
+    // Statement of [from, to) from the history since `from` and the current positions.
+    pub fn from_history(
+        account: &Account,
+        store: &SymbolStore,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        deals: &[Deal],
+        cash_flows: &[ProtoOaDepositWithdraw],
+        positions: &[Position],
+    ) -> Self {
+        let digits = account.money_digits;
+        let zero = Money::new(0, digits);
+        let in_period = |time: DateTime<Utc>| from <= time && time < to;
+        let symbol_name = |symbol_id: i64| {
+            store
+                .resolve_name(symbol_id)
+                .map(str::to_string)
+                .unwrap_or_else(|| symbol_id.to_string())
+        };
+        let format_price = |symbol_id: i64, price: Option<Price>| match price {
+            Some(price) => match store.get_units_by_id(symbol_id) {
+                Some(units) => units.format_price(price),
+                None => price.to_string(),
+            },
+            None => String::new(),
+        };
+
+        let mut changes: Vec<BalanceChange> = Vec::new();
+        let mut trades = Vec::new();
+        for deal in deals {
+            let Some(close) = &deal.close else {
+                continue;
+            };
+            let net = close.gross_profit + close.swap + close.commission;
+            changes.push((deal.execution_time, net, close.balance));
+            if in_period(deal.execution_time) {
+                trades.push(StatementTrade {
+                    deal_id: deal.deal_id,
+                    position_id: deal.position_id,
+                    symbol: symbol_name(deal.symbol_id),
+                    side: deal.side.opposite(),
+                    volume: close.closed_volume.unwrap_or(deal.filled_volume),
+                    entry_price: format_price(deal.symbol_id, Some(close.entry_price)),
+                    close_price: format_price(deal.symbol_id, deal.execution_price),
+                    close_time: deal.execution_time,
+                    gross_profit: close.gross_profit,
+                    swap: close.swap,
+                    commission: close.commission,
+                    net_profit: net,
+                    balance: close.balance,
+                });
+            }
+        }
+        trades.sort_by_key(|trade| (trade.close_time, trade.deal_id));
+
+        let mut flows = Vec::new();
+        for flow in cash_flows {
+            let flow_digits = flow.money_digits.unwrap_or(DEFAULT_MONEY_DIGITS);
+            let time = from_mill_seconds(flow.change_balance_timestamp);
+            let amount = Money::new(flow.delta, flow_digits);
+            let balance = Money::new(flow.balance, flow_digits);
+            changes.push((time, amount, balance));
+            if in_period(time) {
+                flows.push(StatementCashFlow {
+                    balance_history_id: flow.balance_history_id,
+                    time,
+                    amount,
+                    balance,
+                    note: flow.external_note.clone(),
+                });
+            }
+        }
+        flows.sort_by_key(|flow| (flow.time, flow.balance_history_id));
+        changes.sort_by_key(|change| change.0);
+
+        let closing_balance = changes
+            .iter()
+            .rev()
+            .find(|change| change.0 < to)
+            .filter(|change| change.0 >= from)
+            .map(|change| change.2)
+            .or_else(|| {
+                changes
+                    .iter()
+                    .find(|change| change.0 >= to)
+                    .map(|(_, amount, balance)| *balance - *amount)
+            })
+            .unwrap_or(account.balance)
+            .rescale(digits);
+        let opening_balance = changes
+            .iter()
+            .find(|change| in_period(change.0))
+            .map(|(_, amount, balance)| *balance - *amount)
+            .unwrap_or(closing_balance)
+            .rescale(digits);
+
+        let sum = |values: &mut dyn Iterator<Item = Money>| {
+            values
+                .fold(zero, |total, value| total + value)
+                .rescale(digits)
+        };
+        let deposits = sum(&mut flows.iter().map(|f| f.amount).filter(|a| *a > zero));
+        let withdrawals = sum(&mut flows.iter().map(|f| f.amount).filter(|a| *a < zero));
+        let realized_pnl = sum(&mut trades.iter().map(|t| t.gross_profit));
+        let commission = sum(&mut trades.iter().map(|t| t.commission));
+        let swap = sum(&mut trades.iter().map(|t| t.swap));
+
+        let open_positions = positions_at(to, deals, positions)
+            .into_iter()
+            .map(|p| StatementPosition {
+                position_id: p.position_id,
+                symbol: symbol_name(p.symbol_id),
+                side: p.side,
+                volume: p.volume,
+                entry_price: format_price(p.symbol_id, p.entry_price),
+                open_time: p.open_time,
+            })
+            .collect();
+
+        Self {
+            account_id: account.account_id,
+            login: account.login,
+            broker_name: account.broker_name.clone(),
+            currency: store
+                .get_asset(account.deposit_asset_id)
+                .map(|asset| asset.name.clone())
+                .unwrap_or_else(|| account.deposit_asset_id.to_string()),
+            from,
+            to,
+            generated_at: Utc::now(),
+            opening_balance,
+            closing_balance,
+            deposits,
+            withdrawals,
+            realized_pnl,
+            commission,
+            swap,
+            net_pnl: (realized_pnl + commission + swap).rescale(digits),
+            trades,
+            cash_flows: flows,
+            open_positions,
+        }
+    }
+
+    fn summary(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("account", self.account_id.to_string()),
+            (
+                "login",
+                self.login.map(|l| l.to_string()).unwrap_or_default(),
+            ),
+            ("broker", self.broker_name.clone().unwrap_or_default()),
+            ("currency", self.currency.clone()),
+            ("from", format_time(self.from)),
+            ("to", format_time(self.to)),
+            ("generated_at", format_time(self.generated_at)),
+            ("opening_balance", self.opening_balance.to_string()),
+            ("deposits", self.deposits.to_string()),
+            ("withdrawals", self.withdrawals.to_string()),
+            ("realized_pnl", self.realized_pnl.to_string()),
+            ("commission", self.commission.to_string()),
+            ("swap", self.swap.to_string()),
+            ("net_pnl", self.net_pnl.to_string()),
+            ("closing_balance", self.closing_balance.to_string()),
+        ]
+    }
+
+    // The summary as `# key: value` lines, then one record per trade, cash flow and open
+    // position with a `type` column, like the files of `export`.
+    pub fn to_csv(&self) -> Result<String, Error> {
+        let mut out = String::new();
+        for (key, value) in self.summary() {
+            let _ = writeln!(out, "# {}: {}", key, value);
+        }
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer
+            .write_record([
+                "type",
+                "time",
+                "id",
+                "position_id",
+                "symbol",
+                "side",
+                "volume",
+                "entry_price",
+                "close_price",
+                "gross_profit",
+                "swap",
+                "commission",
+                "net_profit",
+                "amount",
+                "balance",
+            ])
+            .map_err(csv_error)?;
+        for t in &self.trades {
+            writer
+                .write_record([
+                    "trade".to_string(),
+                    format_time(t.close_time),
+                    t.deal_id.to_string(),
+                    t.position_id.to_string(),
+                    t.symbol.clone(),
+                    side_name(t.side).to_string(),
+                    t.volume.to_string(),
+                    t.entry_price.clone(),
+                    t.close_price.clone(),
+                    t.gross_profit.to_string(),
+                    t.swap.to_string(),
+                    t.commission.to_string(),
+                    t.net_profit.to_string(),
+                    String::new(),
+                    t.balance.to_string(),
+                ])
+                .map_err(csv_error)?;
+        }
+        for f in &self.cash_flows {
+            writer
+                .write_record([
+                    flow_kind(f).to_string(),
+                    format_time(f.time),
+                    f.balance_history_id.to_string(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    f.amount.to_string(),
+                    f.balance.to_string(),
+                ])
+                .map_err(csv_error)?;
+        }
+        for p in &self.open_positions {
+            writer
+                .write_record([
+                    "open_position".to_string(),
+                    p.open_time.map(format_time).unwrap_or_default(),
+                    p.position_id.to_string(),
+                    p.position_id.to_string(),
+                    p.symbol.clone(),
+                    side_name(p.side).to_string(),
+                    p.volume.to_string(),
+                    p.entry_price.clone(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                ])
+                .map_err(csv_error)?;
+        }
+        let data = writer
+            .into_inner()
+            .map_err(|e| Error::String(format!("csv error: {}", e)))?;
+        out.push_str(&String::from_utf8_lossy(&data));
+        Ok(out)
+    }
+
+    // A single HTML page with inline styles, no external resources.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        let title = format!(
+            "Statement {} {} - {}",
+            self.account_id,
+            self.from.format("%Y-%m-%d"),
+            self.to.format("%Y-%m-%d")
+        );
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>\n{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            escape(&title),
+            STYLE,
+            escape(&title)
+        );
+
+        html.push_str("<h2>Summary</h2>\n<table class=\"summary\">\n");
+        for (key, value) in self.summary() {
+            let _ = writeln!(
+                html,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                escape(&key.replace('_', " ")),
+                escape(&value)
+            );
+        }
+        html.push_str("</table>\n");
+
+        let trades = self
+            .trades
+            .iter()
+            .map(|t| {
+                vec![
+                    format_time(t.close_time),
+                    t.deal_id.to_string(),
+                    t.position_id.to_string(),
+                    t.symbol.clone(),
+                    side_name(t.side).to_string(),
+                    t.volume.to_string(),
+                    t.entry_price.clone(),
+                    t.close_price.clone(),
+                    t.gross_profit.to_string(),
+                    t.swap.to_string(),
+                    t.commission.to_string(),
+                    t.net_profit.to_string(),
+                    t.balance.to_string(),
+                ]
+            })
+            .collect();
+        write_table(
+            &mut html,
+            "Closed trades",
+            &[
+                "Time",
+                "Deal",
+                "Position",
+                "Symbol",
+                "Side",
+                "Volume",
+                "Entry",
+                "Close",
+                "Gross",
+                "Swap",
+                "Commission",
+                "Net",
+                "Balance",
+            ],
+            trades,
+        );
+
+        let flows = self
+            .cash_flows
+            .iter()
+            .map(|f| {
+                vec![
+                    format_time(f.time),
+                    flow_kind(f).to_string(),
+                    f.amount.to_string(),
+                    f.balance.to_string(),
+                    f.note.clone().unwrap_or_default(),
+                ]
+            })
+            .collect();
+        write_table(
+            &mut html,
+            "Deposits and withdrawals",
+            &["Time", "Type", "Amount", "Balance", "Note"],
+            flows,
+        );
+
+        let positions = self
+            .open_positions
+            .iter()
+            .map(|p| {
+                vec![
+                    p.open_time.map(format_time).unwrap_or_default(),
+                    p.position_id.to_string(),
+                    p.symbol.clone(),
+                    side_name(p.side).to_string(),
+                    p.volume.to_string(),
+                    p.entry_price.clone(),
+                ]
+            })
+            .collect();
+        write_table(
+            &mut html,
+            "Open positions at period end",
+            &["Opened", "Position", "Symbol", "Side", "Volume", "Entry"],
+            positions,
+        );
+
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        fs::write(path, self.to_csv()?)?;
+        Ok(())
+    }
+
+    pub fn write_html(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        fs::write(path, self.to_html())?;
+        Ok(())
+    }
+}
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 4px 8px; text-align: right; }
+th { background: #f0f0f0; }
+table.summary th { text-align: left; text-transform: capitalize; }
+";
+
+// Positions open at `at`: the current ones opened before, with the volume closed since
+// added back and the volume opened since taken out.
+fn positions_at(at: DateTime<Utc>, deals: &[Deal], positions: &[Position]) -> Vec<Position> {
+    let mut open: BTreeMap<i64, Position> = positions
+        .iter()
+        .filter(|p| p.open_time.is_none_or(|time| time < at))
+        .map(|p| (p.position_id, p.clone()))
+        .collect();
+    let mut opened: BTreeMap<i64, DateTime<Utc>> = BTreeMap::new();
+    for deal in deals {
+        if deal.close.is_none() && deal.execution_time < at {
+            let time = opened
+                .entry(deal.position_id)
+                .or_insert(deal.execution_time);
+            *time = (*time).min(deal.execution_time);
+        }
+    }
+    // volume change since `at`, per position
+    let mut since: BTreeMap<i64, i64> = BTreeMap::new();
+    for deal in deals.iter().filter(|deal| deal.execution_time >= at) {
+        match &deal.close {
+            Some(close) => {
+                let volume = close.closed_volume.unwrap_or(deal.filled_volume);
+                *since.entry(deal.position_id).or_default() += volume.cents();
+                open.entry(deal.position_id).or_insert_with(|| Position {
+                    position_id: deal.position_id,
+                    symbol_id: deal.symbol_id,
+                    side: deal.side.opposite(),
+                    volume: Volume::ZERO,
+                    status: PositionStatus::Open,
+                    entry_price: Some(close.entry_price),
+                    stop_loss: None,
+                    take_profit: None,
+                    guaranteed_stop_loss: false,
+                    trailing_stop_loss: false,
+                    swap: Money::default(),
+                    commission: Money::default(),
+                    used_margin: None,
+                    label: None,
+                    comment: None,
+                    open_time: opened.get(&deal.position_id).copied(),
+                    last_update_time: None,
+                });
+            }
+            None => *since.entry(deal.position_id).or_default() -= deal.filled_volume.cents(),
+        }
+    }
+    open.into_values()
+        .filter_map(|mut p| {
+            p.volume =
+                Volume::from_cents(p.volume.cents() + since.get(&p.position_id).unwrap_or(&0));
+            (p.volume > Volume::ZERO).then_some(p)
+        })
+        .collect()
+}
+
+fn write_table(html: &mut String, title: &str, header: &[&str], rows: Vec<Vec<String>>) {
+    let _ = writeln!(html, "<h2>{}</h2>", escape(title));
+    if rows.is_empty() {
+        html.push_str("<p>None</p>\n");
+        return;
+    }
+    html.push_str("<table>\n<tr>");
+    for column in header {
+        let _ = write!(html, "<th>{}</th>", escape(column));
+    }
+    html.push_str("</tr>\n");
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            let _ = write!(html, "<td>{}</td>", escape(&cell));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Buy => "buy",
+        Side::Sell => "sell",
+    }
+}
+
+fn flow_kind(flow: &StatementCashFlow) -> &'static str {
+    if flow.amount.value() < 0 {
+        "withdrawal"
+    } else {
+        "deposit"
+    }
+}
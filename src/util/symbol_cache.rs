@@ -0,0 +1,196 @@
+//! On-disk copy of the `SymbolStore`, so `Session::connect` does not have to fetch every
+//! symbol on each start.
+//!
+//! The file is JSON: the server version and save time, then one entry per symbol with the
+//! full `ProtoOaSymbol` protobuf-encoded. Symbols differ between brokers, use one file per
+//! broker (or per account when in doubt).
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::protos::spotware_message::{ProtoOaArchivedSymbol, ProtoOaSymbol};
+use crate::util::bar_cache::replace_file;
+use crate::util::symbol_info::{AssetInfo, SpotwareSymbolInfo};
+use crate::util::time_util::from_mill_seconds;
+use crate::util::SymbolStore;
+use crate::Error;
+
+// bumped when the file layout changes, older files are ignored
+const SYMBOL_CACHE_FORMAT: u32 = 2;
+
+/// How `Session::connect` fills the symbol store.
+#[derive(Debug, Clone, Default)]
+pub enum SymbolLoading {
+    /// Fetch every symbol before connect returns
+    #[default]
+    Eager,
+    /// Use the cache file when it was written for the same server version and is not
+    /// older than `max_age`, and revalidate it in the background on every connect. An
+    /// older file, or none usable, is replaced by fetching the symbols as with `Eager`.
+    Cached { path: PathBuf, max_age: Duration },
+    /// Connect with an empty store and fetch the symbols in the background. The store is
+    /// filled by `Session::dispatch_event` once the fetch is done, or by awaiting
+    /// `Session::wait_symbol_refresh`
+    Deferred,
+    /// Never fetch the symbols, `Session::load_symbols` can still be called
+    Skip,
+}
+
+/// A store read back from disk.
+#[derive(Debug, Clone)]
+pub struct CachedSymbolStore {
+    pub store: SymbolStore,
+    pub server_version: u32,
+    pub saved_at: DateTime<Utc>,
+}
+
+impl CachedSymbolStore {
+    pub fn age(&self) -> Duration {
+        (Utc::now() - self.saved_at).to_std().unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SymbolCacheFile {
+    format: u32,
+    server_version: u32,
+    /// Unix time in milliseconds
+    saved_at: i64,
+    symbols: Vec<CachedSymbol>,
+    assets: Vec<CachedAsset>,
+    /// Encoded `ProtoOaArchivedSymbol`s
+    archived: Vec<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedSymbol {
+    name: String,
+    asset_class: String,
+    category: String,
+    description: Option<String>,
+    base_asset_id: i64,
+    quote_asset_id: i64,
+    base_decimals: u8,
+    quote_decimals: u8,
+    /// Encoded `ProtoOaSymbol`
+    info: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedAsset {
+    asset_id: i64,
+    name: String,
+    display_name: Option<String>,
+    digits: i32,
+}
+
+impl SymbolStore {
+    // Writes the store to `path`, replacing the file atomically.
+    pub fn save(&self, path: &Path, server_version: u32) -> Result<(), Error> {
+        let symbols = self
+            .infos()
+            .into_iter()
+            .map(|info| CachedSymbol {
+                name: info.name.clone(),
+                asset_class: info.asset_class.clone(),
+                category: info.category.clone(),
+                description: info.description.clone(),
+                base_asset_id: info.base_asset_id,
+                quote_asset_id: info.quote_asset_id,
+                base_decimals: info.base_decimals,
+                quote_decimals: info.quote_decimals,
+                info: info.info.encode_to_vec(),
+            })
+            .collect();
+        let assets = self
+            .assets()
+            .into_iter()
+            .map(|asset| CachedAsset {
+                asset_id: asset.asset_id,
+                name: asset.name.clone(),
+                display_name: asset.display_name.clone(),
+                digits: asset.digits,
+            })
+            .collect();
+        let archived = self
+            .archived()
+            .into_iter()
+            .map(|symbol| symbol.encode_to_vec())
+            .collect();
+        let file = SymbolCacheFile {
+            format: SYMBOL_CACHE_FORMAT,
+            server_version,
+            saved_at: Utc::now().timestamp_millis(),
+            symbols,
+            assets,
+            archived,
+        };
+        let data = serde_json::to_vec(&file).map_err(|e| Error::String(e.to_string()))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        replace_file(path, &data)
+    }
+
+    pub fn load(path: &Path) -> Result<CachedSymbolStore, Error> {
+        let data = fs::read(path)?;
+        let file: SymbolCacheFile = serde_json::from_slice(&data)
+            .map_err(|e| Error::String(format!("{}: {}", path.display(), e)))?;
+        if file.format != SYMBOL_CACHE_FORMAT {
+            return Err(Error::String(format!(
+                "{}: unsupported symbol cache format {}",
+                path.display(),
+                file.format
+            )));
+        }
+        let infos = file
+            .symbols
+            .into_iter()
+            .map(|symbol| {
+                let info = ProtoOaSymbol::decode(symbol.info.as_slice())
+                    .map_err(|_| Error::DecodeProtoMessageError)?;
+                Ok(SpotwareSymbolInfo {
+                    name: symbol.name,
+                    asset_class: symbol.asset_class,
+                    category: symbol.category,
+                    description: symbol.description,
+                    info,
+                    base_asset_id: symbol.base_asset_id,
+                    quote_asset_id: symbol.quote_asset_id,
+                    base_decimals: symbol.base_decimals,
+                    quote_decimals: symbol.quote_decimals,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let archived = file
+            .archived
+            .iter()
+            .map(|data| {
+                ProtoOaArchivedSymbol::decode(data.as_slice())
+                    .map_err(|_| Error::DecodeProtoMessageError)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut store = SymbolStore::build_from_symbol_infos(infos);
+        store.set_assets(
+            file.assets
+                .into_iter()
+                .map(|asset| AssetInfo {
+                    asset_id: asset.asset_id,
+                    name: asset.name,
+                    display_name: asset.display_name,
+                    digits: asset.digits,
+                })
+                .collect(),
+        );
+        store.set_archived(archived);
+        Ok(CachedSymbolStore {
+            store,
+            server_version: file.server_version,
+            saved_at: from_mill_seconds(file.saved_at),
+        })
+    }
+}
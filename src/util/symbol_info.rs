@@ -1,23 +1,55 @@
 use std::collections::HashMap;
 
-use crate::{
-    protos::spotware_message::{ProtoOaAsset, ProtoOaSymbol},
-    Error, Session,
-};
+use crate::{io::Requester, protos::spotware_message::*, util::SymbolStore, Error, Session};
 
 #[derive(Debug, Clone)]
 pub struct SpotwareSymbolInfo {
     pub name: String,
     pub asset_class: String,
+    pub category: String,
+    pub description: Option<String>,
     pub info: ProtoOaSymbol,
+    pub base_asset_id: i64,
+    pub quote_asset_id: i64,
     pub base_decimals: u8,
     pub quote_decimals: u8,
 }
 
+/// An asset (currency, metal, index, ...) symbols are quoted in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetInfo {
+    pub asset_id: i64,
+    pub name: String,
+    pub display_name: Option<String>,
+    pub digits: i32,
+}
+
+impl From<&ProtoOaAsset> for AssetInfo {
+    fn from(asset: &ProtoOaAsset) -> Self {
+        Self {
+            asset_id: asset.asset_id,
+            name: asset.name.clone(),
+            display_name: asset.display_name.clone(),
+            digits: asset.digits(),
+        }
+    }
+}
+
 pub async fn get_symbol_infos(client: &Session) -> Result<Vec<SpotwareSymbolInfo>, Error> {
+    let store = fetch_symbol_store(&client.requester()?, client.account.account_id, false).await?;
+    Ok(store.infos().into_iter().cloned().collect())
+}
+
+// Fetches the enabled symbols with their assets, and the archived symbols when asked.
+// Does not borrow the session, so it also runs in background refreshes.
+pub(crate) async fn fetch_symbol_store(
+    requester: &Requester,
+    account_id: i64,
+    include_archived: bool,
+) -> Result<SymbolStore, Error> {
     let mut symbol_infos: Vec<SpotwareSymbolInfo> = Vec::new();
 
-    let asset_class_list = client.asset_class_list().await?.asset_class;
+    let asset_class_list = requester.asset_class_list(account_id).await?.asset_class;
 
     let mut asset_class_id_to_asset_class_name: HashMap<i64, String> = HashMap::new();
 
@@ -29,22 +61,30 @@ pub async fn get_symbol_infos(client: &Session) -> Result<Vec<SpotwareSymbolInfo
 
     let mut asset_id_to_asset_info: HashMap<i64, ProtoOaAsset> = HashMap::new();
 
-    let asset_list = client.asset_list().await?.asset;
+    let asset_list = requester.asset_list(account_id).await?.asset;
 
     for asset in asset_list {
         let id = asset.asset_id;
         asset_id_to_asset_info.insert(id, asset);
     }
 
-    let symbol_category_list = client.symbol_category_list().await?.symbol_category;
+    let symbol_category_list = requester
+        .symbol_category_list(account_id)
+        .await?
+        .symbol_category;
 
     let mut category_id_to_assert_class_id: HashMap<i64, i64> = HashMap::new();
+    let mut category_id_to_name: HashMap<i64, String> = HashMap::new();
 
     for category in &symbol_category_list {
         category_id_to_assert_class_id.insert(category.id, category.asset_class_id);
+        category_id_to_name.insert(category.id, category.name.clone());
     }
 
-    let symbol_list = client.symbol_list().await?.symbol;
+    let symbols_res = requester
+        .symbol_list(account_id, Some(include_archived))
+        .await?;
+    let symbol_list = symbols_res.symbol;
 
     let enabled_symbol_list = symbol_list
         .iter()
@@ -56,7 +96,7 @@ pub async fn get_symbol_infos(client: &Session) -> Result<Vec<SpotwareSymbolInfo
         .map(|s| s.symbol_id)
         .collect::<Vec<_>>();
 
-    let symbol_full_infos = client.symbol_by_id(symbol_ids).await?.symbol;
+    let symbol_full_infos = requester.symbol_by_id(account_id, symbol_ids).await?.symbol;
 
     let mut full_info_by_id: HashMap<i64, ProtoOaSymbol> = HashMap::new();
 
@@ -73,17 +113,32 @@ pub async fn get_symbol_infos(client: &Session) -> Result<Vec<SpotwareSymbolInfo
         let base_decimals = asset_id_to_asset_info[&symbol.base_asset_id()].digits();
         let quote_decimals = asset_id_to_asset_info[&symbol.quote_asset_id()].digits();
 
+        let category = category_id_to_name
+            .get(&category_id)
+            .cloned()
+            .unwrap_or_default();
+
         let symbol_info = SpotwareSymbolInfo {
             name: symbol.symbol_name().to_string(),
             asset_class: asset_class_name,
+            category,
+            description: symbol.description.clone(),
             info: full_info,
+            base_asset_id: symbol.base_asset_id(),
+            quote_asset_id: symbol.quote_asset_id(),
             base_decimals: base_decimals as u8,
             quote_decimals: quote_decimals as u8,
         };
         symbol_infos.push(symbol_info);
     }
 
-    symbol_infos.sort_by(|a, b| a.info.symbol_id.cmp(&b.info.symbol_id));
-
-    Ok(symbol_infos)
+    let mut store = SymbolStore::build_from_symbol_infos(symbol_infos);
+    store.set_assets(
+        asset_id_to_asset_info
+            .values()
+            .map(AssetInfo::from)
+            .collect(),
+    );
+    store.set_archived(symbols_res.archived_symbol);
+    Ok(store)
 }
@@ -1,11 +1,153 @@
-use crate::util::symbol_info::SpotwareSymbolInfo;
+use crate::protos::spotware_message::{ProtoOaArchivedSymbol, ProtoOaSymbol, ProtoOaTradingMode};
+use crate::util::calendar::TradingCalendar;
+use crate::util::symbol_info::{AssetInfo, SpotwareSymbolInfo};
+use crate::util::units::SymbolUnits;
+use crate::Error;
 use std::collections::HashMap;
 
+/// A field of a symbol that changed on the server, with the old and new value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolChange {
+    TradingMode {
+        old: ProtoOaTradingMode,
+        new: ProtoOaTradingMode,
+    },
+    Digits {
+        old: i32,
+        new: i32,
+    },
+    PipPosition {
+        old: i32,
+        new: i32,
+    },
+    LotSize {
+        old: Option<i64>,
+        new: Option<i64>,
+    },
+    MinVolume {
+        old: Option<i64>,
+        new: Option<i64>,
+    },
+    MaxVolume {
+        old: Option<i64>,
+        new: Option<i64>,
+    },
+    StepVolume {
+        old: Option<i64>,
+        new: Option<i64>,
+    },
+    /// The dynamic leverage profile
+    LeverageId {
+        old: Option<i64>,
+        new: Option<i64>,
+    },
+    SwapLong {
+        old: Option<f64>,
+        new: Option<f64>,
+    },
+    SwapShort {
+        old: Option<f64>,
+        new: Option<f64>,
+    },
+    /// Commission base, 10^8 scaled (10^5 for percentage of value commissions)
+    CommissionRate {
+        old: Option<i64>,
+        new: Option<i64>,
+    },
+    SlDistance {
+        old: Option<u32>,
+        new: Option<u32>,
+    },
+    TpDistance {
+        old: Option<u32>,
+        new: Option<u32>,
+    },
+    EnableShortSelling {
+        old: Option<bool>,
+        new: Option<bool>,
+    },
+    GuaranteedStopLoss {
+        old: Option<bool>,
+        new: Option<bool>,
+    },
+    /// Weekly sessions or their time zone
+    Schedule,
+    Holidays,
+    /// Any other field
+    Other,
+}
+
+impl SymbolChange {
+    // Changed fields between two versions of a symbol, Other when only untracked ones differ.
+    pub fn diff(old: &ProtoOaSymbol, new: &ProtoOaSymbol) -> Vec<SymbolChange> {
+        let mut changes = Vec::new();
+        macro_rules! field {
+            ($variant:ident, $old:expr, $new:expr) => {
+                if $old != $new {
+                    changes.push(SymbolChange::$variant {
+                        old: $old,
+                        new: $new,
+                    });
+                }
+            };
+        }
+        field!(TradingMode, old.trading_mode(), new.trading_mode());
+        field!(Digits, old.digits, new.digits);
+        field!(PipPosition, old.pip_position, new.pip_position);
+        field!(LotSize, old.lot_size, new.lot_size);
+        field!(MinVolume, old.min_volume, new.min_volume);
+        field!(MaxVolume, old.max_volume, new.max_volume);
+        field!(StepVolume, old.step_volume, new.step_volume);
+        field!(LeverageId, old.leverage_id, new.leverage_id);
+        field!(SwapLong, old.swap_long, new.swap_long);
+        field!(SwapShort, old.swap_short, new.swap_short);
+        field!(
+            CommissionRate,
+            old.precise_trading_commission_rate,
+            new.precise_trading_commission_rate
+        );
+        field!(SlDistance, old.sl_distance, new.sl_distance);
+        field!(TpDistance, old.tp_distance, new.tp_distance);
+        field!(
+            EnableShortSelling,
+            old.enable_short_selling,
+            new.enable_short_selling
+        );
+        field!(
+            GuaranteedStopLoss,
+            old.guaranteed_stop_loss,
+            new.guaranteed_stop_loss
+        );
+        if old.schedule != new.schedule || old.schedule_time_zone != new.schedule_time_zone {
+            changes.push(SymbolChange::Schedule);
+        }
+        if old.holiday != new.holiday {
+            changes.push(SymbolChange::Holidays);
+        }
+        if changes.is_empty() && old != new {
+            changes.push(SymbolChange::Other);
+        }
+        changes
+    }
+}
+
+/// Changes applied to one symbol of the store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolUpdate {
+    pub symbol_id: i64,
+    pub name: String,
+    pub changes: Vec<SymbolChange>,
+}
+
 #[derive(Debug, Clone)]
 pub struct SymbolStore {
     symbol_to_id: HashMap<String, i64>,
     id_to_symbol: HashMap<i64, String>,
     symbol_to_info: HashMap<String, SpotwareSymbolInfo>,
+    // lower case name -> name
+    lower_to_symbol: HashMap<String, String>,
+    assets: HashMap<i64, AssetInfo>,
+    archived: HashMap<i64, ProtoOaArchivedSymbol>,
 }
 
 impl SymbolStore {
@@ -14,6 +156,9 @@ impl SymbolStore {
             symbol_to_id: HashMap::new(),
             id_to_symbol: HashMap::new(),
             symbol_to_info: HashMap::new(),
+            lower_to_symbol: HashMap::new(),
+            assets: HashMap::new(),
+            archived: HashMap::new(),
         }
     }
 
@@ -25,9 +170,26 @@ impl SymbolStore {
             self.symbol_to_id.insert(symbol.to_string(), id);
             self.id_to_symbol.insert(id, symbol.to_string());
             self.symbol_to_info.insert(symbol.to_string(), info.clone());
+            self.lower_to_symbol
+                .insert(symbol.to_lowercase(), symbol.to_string());
         }
     }
 
+    pub(crate) fn set_assets(&mut self, assets: Vec<AssetInfo>) {
+        self.assets = assets.into_iter().map(|a| (a.asset_id, a)).collect();
+    }
+
+    pub(crate) fn set_archived(&mut self, archived: Vec<ProtoOaArchivedSymbol>) {
+        self.archived = archived.into_iter().map(|a| (a.symbol_id, a)).collect();
+    }
+
+    // All symbols sorted by id.
+    pub fn infos(&self) -> Vec<&SpotwareSymbolInfo> {
+        let mut infos: Vec<&SpotwareSymbolInfo> = self.symbol_to_info.values().collect();
+        infos.sort_by_key(|info| info.info.symbol_id);
+        infos
+    }
+
     pub fn build_from_symbol_infos(infos: Vec<SpotwareSymbolInfo>) -> Self {
         let mut result = Self::new();
         result.from_symbol_infos(&infos);
@@ -57,4 +219,197 @@ impl SymbolStore {
         }
         None
     }
+
+    // digits, pip position and lot size for price and volume conversions
+    pub fn get_units_by_id(&self, id: i64) -> Option<SymbolUnits> {
+        self.get_info_by_id(id)
+            .map(|info| SymbolUnits::from(&info.info))
+    }
+
+    pub fn get_units_by_name(&self, symbol_name: &str) -> Option<SymbolUnits> {
+        self.get_info_by_name(symbol_name)
+            .map(|info| SymbolUnits::from(&info.info))
+    }
+
+    // trading sessions and holidays from the symbol schedule
+    pub fn get_calendar_by_id(&self, id: i64) -> Result<TradingCalendar, Error> {
+        let info = self
+            .get_info_by_id(id)
+            .ok_or_else(|| Error::String(format!("unknown symbol id {}", id)))?;
+        TradingCalendar::try_from(info)
+    }
+
+    pub fn get_calendar_by_name(&self, symbol_name: &str) -> Result<TradingCalendar, Error> {
+        let info = self
+            .get_info_by_name(symbol_name)
+            .ok_or_else(|| Error::String(format!("unknown symbol {}", symbol_name)))?;
+        TradingCalendar::try_from(info)
+    }
+
+    // Replaces the details of known symbols in one go, returns what changed per symbol.
+    // Unknown symbols are skipped, unchanged ones are not reported.
+    pub fn apply_symbols(&mut self, symbols: Vec<ProtoOaSymbol>) -> Vec<SymbolUpdate> {
+        let mut updates = Vec::new();
+        for symbol in symbols {
+            let Some(name) = self.id_to_symbol.get(&symbol.symbol_id) else {
+                continue;
+            };
+            let Some(info) = self.symbol_to_info.get_mut(name) else {
+                continue;
+            };
+            let changes = SymbolChange::diff(&info.info, &symbol);
+            if changes.is_empty() {
+                continue;
+            }
+            info.info = symbol;
+            updates.push(SymbolUpdate {
+                symbol_id: info.info.symbol_id,
+                name: name.clone(),
+                changes,
+            });
+        }
+        updates
+    }
+
+    //+------------------------------------------------------------------+
+    //|                        Search & Assets                           |
+    //+------------------------------------------------------------------+
+
+    pub fn get_info_by_name_ignore_case(&self, symbol_name: &str) -> Option<&SpotwareSymbolInfo> {
+        let name = self.lower_to_symbol.get(&symbol_name.to_lowercase())?;
+        self.symbol_to_info.get(name)
+    }
+
+    // Symbols matching `query`, best first: same name ignoring case and punctuation, then
+    // names starting with it (broker suffixes like EURUSD.x or EURUSDm), then names
+    // containing it. Shorter names come first within a rank.
+    pub fn search(&self, query: &str) -> Vec<&SpotwareSymbolInfo> {
+        self.ranked(query)
+            .into_iter()
+            .map(|(_, info)| info)
+            .collect()
+    }
+
+    // The single best match of `search`, None when there is none or the best is a tie.
+    pub fn resolve(&self, query: &str) -> Option<&SpotwareSymbolInfo> {
+        match self.ranked(query).as_slice() {
+            [(_, info)] => Some(*info),
+            [(best, info), (next, _), ..] if best < next => Some(*info),
+            _ => None,
+        }
+    }
+
+    // (rank, normalized length) and symbol of the matches, sorted
+    fn ranked(&self, query: &str) -> Vec<((u8, usize), &SpotwareSymbolInfo)> {
+        let query = normalize_name(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let mut found: Vec<((u8, usize), &SpotwareSymbolInfo)> = self
+            .symbol_to_info
+            .values()
+            .filter_map(|info| {
+                let name = normalize_name(&info.name);
+                let rank = if name == query {
+                    0
+                } else if name.starts_with(&query) {
+                    1
+                } else if name.contains(&query) {
+                    2
+                } else {
+                    return None;
+                };
+                Some(((rank, name.len()), info))
+            })
+            .collect();
+        found.sort_by(|a, b| (a.0, &a.1.name).cmp(&(b.0, &b.1.name)));
+        found
+    }
+
+    pub fn find_by_base_asset(&self, asset_id: i64) -> Vec<&SpotwareSymbolInfo> {
+        self.filter(|info| info.base_asset_id == asset_id)
+    }
+
+    pub fn find_by_quote_asset(&self, asset_id: i64) -> Vec<&SpotwareSymbolInfo> {
+        self.filter(|info| info.quote_asset_id == asset_id)
+    }
+
+    // The symbol quoting `base` in `quote`, e.g. EURUSD for EUR and USD.
+    pub fn find_by_assets(
+        &self,
+        base_asset_id: i64,
+        quote_asset_id: i64,
+    ) -> Option<&SpotwareSymbolInfo> {
+        self.filter(|info| {
+            info.base_asset_id == base_asset_id && info.quote_asset_id == quote_asset_id
+        })
+        .into_iter()
+        .next()
+    }
+
+    pub fn find_by_asset_class(&self, asset_class: &str) -> Vec<&SpotwareSymbolInfo> {
+        self.filter(|info| info.asset_class.eq_ignore_ascii_case(asset_class))
+    }
+
+    pub fn find_by_category(&self, category: &str) -> Vec<&SpotwareSymbolInfo> {
+        self.filter(|info| info.category.eq_ignore_ascii_case(category))
+    }
+
+    pub fn get_asset(&self, asset_id: i64) -> Option<&AssetInfo> {
+        self.assets.get(&asset_id)
+    }
+
+    pub fn get_asset_by_name(&self, name: &str) -> Option<&AssetInfo> {
+        self.assets
+            .values()
+            .find(|asset| asset.name.eq_ignore_ascii_case(name))
+    }
+
+    // All assets sorted by id.
+    pub fn assets(&self) -> Vec<&AssetInfo> {
+        let mut assets: Vec<&AssetInfo> = self.assets.values().collect();
+        assets.sort_by_key(|asset| asset.asset_id);
+        assets
+    }
+
+    // Archived symbols are only known when the store was loaded with them, see
+    // `ClientBuilder::set_include_archived_symbols`.
+    pub fn get_archived_by_id(&self, id: i64) -> Option<&ProtoOaArchivedSymbol> {
+        self.archived.get(&id)
+    }
+
+    pub fn archived(&self) -> Vec<&ProtoOaArchivedSymbol> {
+        let mut archived: Vec<&ProtoOaArchivedSymbol> = self.archived.values().collect();
+        archived.sort_by_key(|symbol| symbol.symbol_id);
+        archived
+    }
+
+    // Name of an active or archived symbol, e.g. to label historical deals.
+    pub fn resolve_name(&self, id: i64) -> Option<&str> {
+        self.get_name_by_id(id)
+            .map(String::as_str)
+            .or_else(|| self.archived.get(&id).map(|symbol| symbol.name.as_str()))
+    }
+
+    // Symbols passing `keep`, sorted by id.
+    fn filter<F>(&self, keep: F) -> Vec<&SpotwareSymbolInfo>
+    where
+        F: Fn(&SpotwareSymbolInfo) -> bool,
+    {
+        let mut infos: Vec<&SpotwareSymbolInfo> = self
+            .symbol_to_info
+            .values()
+            .filter(|info| keep(info))
+            .collect();
+        infos.sort_by_key(|info| info.info.symbol_id);
+        infos
+    }
+}
+
+// upper case letters and digits only, EUR/USD and eurusd.x become EURUSD and EURUSDX
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_uppercase)
+        .collect()
 }
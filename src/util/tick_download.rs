@@ -0,0 +1,275 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream};
+use tracing::{info, warn};
+
+use crate::client::historical::MAX_HISTORY_RANGE_MS;
+use crate::{
+    client::Session,
+    protos::spotware_message::{ProtoOaQuoteType, ProtoOaTickData},
+    util::{bar_gen::Quote, time_util::from_mill_seconds, units::Price},
+    Error,
+};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+/// One bid or ask price change
+pub struct Tick {
+    /// Unix time in milliseconds
+    pub timestamp: i64,
+    pub price: f64,
+}
+
+impl std::fmt::Display for Tick {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "(ts: {:?}, p: {:.5})",
+            from_mill_seconds(self.timestamp),
+            self.price
+        )
+    }
+}
+
+// Decode a tick data page: the first entry is absolute, every other entry is
+// relative to the one before it, newest first. Returns the ticks oldest first.
+pub fn decode_tick_data(tick_data: &[ProtoOaTickData]) -> Vec<Tick> {
+    let mut timestamp = 0;
+    let mut price = 0;
+    let mut ticks = tick_data
+        .iter()
+        .map(|t| {
+            timestamp += t.timestamp;
+            price += t.tick;
+            Tick {
+                timestamp,
+                price: Price::from_raw(price).to_f64(),
+            }
+        })
+        .collect::<Vec<_>>();
+    ticks.reverse();
+    ticks
+}
+
+// Download the bid or ask ticks of a symbol, oldest first.
+pub async fn download_ticks(
+    client: &Session,
+    symbol: &str,
+    quote_type: ProtoOaQuoteType,
+    start: &DateTime<Utc>,
+    end: &DateTime<Utc>,
+) -> Result<Vec<Tick>, Error> {
+    let symbol_id = find_symbol_id(client, symbol)?;
+    info!(
+        "Try to download {} {:?} ticks : from:{} -> to:{} ",
+        symbol, quote_type, start, end
+    );
+
+    let mut ticks = Vec::new();
+    for (from, to) in tick_windows(start, end) {
+        ticks.extend(download_window(client, symbol_id, quote_type, from, to).await?);
+    }
+    Ok(ticks)
+}
+
+// Download bid and ask ticks of a symbol and merge them into quotes, oldest first.
+// Quotes start once both sides are known.
+pub async fn download_quotes(
+    client: &Session,
+    symbol: &str,
+    start: &DateTime<Utc>,
+    end: &DateTime<Utc>,
+) -> Result<Vec<Quote>, Error> {
+    let symbol_id = find_symbol_id(client, symbol)?;
+    info!(
+        "Try to download {} quotes : from:{} -> to:{} ",
+        symbol, start, end
+    );
+
+    let mut merger = QuoteMerger::default();
+    let mut quotes = Vec::new();
+    for (from, to) in tick_windows(start, end) {
+        let bids = download_window(client, symbol_id, ProtoOaQuoteType::Bid, from, to).await?;
+        let asks = download_window(client, symbol_id, ProtoOaQuoteType::Ask, from, to).await?;
+        quotes.extend(merger.merge(&bids, &asks));
+    }
+    Ok(quotes)
+}
+
+// Same as download_ticks, one week is downloaded at a time and yielded oldest first.
+// The stream ends after the first error.
+pub fn tick_stream<'a>(
+    client: &'a Session,
+    symbol: &str,
+    quote_type: ProtoOaQuoteType,
+    start: &DateTime<Utc>,
+    end: &DateTime<Utc>,
+) -> Result<impl Stream<Item = Result<Tick, Error>> + 'a, Error> {
+    let symbol_id = find_symbol_id(client, symbol)?;
+    let windows = tick_windows(start, end);
+
+    Ok(stream::unfold(
+        (windows, VecDeque::new()),
+        move |(mut windows, mut buffer)| async move {
+            loop {
+                if let Some(tick) = buffer.pop_front() {
+                    return Some((Ok(tick), (windows, buffer)));
+                }
+                let (from, to) = windows.pop_front()?;
+                match download_window(client, symbol_id, quote_type, from, to).await {
+                    Ok(ticks) => buffer.extend(ticks),
+                    Err(e) => {
+                        windows.clear();
+                        return Some((Err(e), (windows, buffer)));
+                    }
+                }
+            }
+        },
+    ))
+}
+
+// Same as download_quotes, one week is downloaded at a time and yielded oldest first.
+// The stream ends after the first error.
+pub fn quote_stream<'a>(
+    client: &'a Session,
+    symbol: &str,
+    start: &DateTime<Utc>,
+    end: &DateTime<Utc>,
+) -> Result<impl Stream<Item = Result<Quote, Error>> + 'a, Error> {
+    let symbol_id = find_symbol_id(client, symbol)?;
+    let windows = tick_windows(start, end);
+
+    Ok(stream::unfold(
+        (windows, VecDeque::new(), QuoteMerger::default()),
+        move |(mut windows, mut buffer, mut merger)| async move {
+            loop {
+                if let Some(quote) = buffer.pop_front() {
+                    return Some((Ok(quote), (windows, buffer, merger)));
+                }
+                let (from, to) = windows.pop_front()?;
+                let result = async {
+                    let bids =
+                        download_window(client, symbol_id, ProtoOaQuoteType::Bid, from, to).await?;
+                    let asks =
+                        download_window(client, symbol_id, ProtoOaQuoteType::Ask, from, to).await?;
+                    Ok::<_, Error>(merger.merge(&bids, &asks))
+                }
+                .await;
+                match result {
+                    Ok(quotes) => buffer.extend(quotes),
+                    Err(e) => {
+                        windows.clear();
+                        return Some((Err(e), (windows, buffer, merger)));
+                    }
+                }
+            }
+        },
+    ))
+}
+
+fn find_symbol_id(client: &Session, symbol: &str) -> Result<i64, Error> {
+    client
+        .store
+        .get_id_by_name(symbol)
+        .ok_or(Error::String(format!(
+            "Not Found SymbolId for Symbol:{}",
+            symbol
+        )))
+}
+
+// [from, to] windows of at most one week, not overlapping
+fn tick_windows(start: &DateTime<Utc>, end: &DateTime<Utc>) -> VecDeque<(i64, i64)> {
+    let mut windows = VecDeque::new();
+    let mut from = start.timestamp_millis();
+    let to_timestamp = end.timestamp_millis();
+    while from <= to_timestamp {
+        let to = (from + MAX_HISTORY_RANGE_MS - 1).min(to_timestamp);
+        windows.push_back((from, to));
+        from = to + 1;
+    }
+    windows
+}
+
+// All ticks of [from, to], oldest first. Pages come newest first, so the window is
+// walked backward, moving `to` to the oldest tick of the page while has_more is set.
+async fn download_window(
+    client: &Session,
+    symbol_id: i64,
+    quote_type: ProtoOaQuoteType,
+    from: i64,
+    mut to: i64,
+) -> Result<Vec<Tick>, Error> {
+    // pages oldest first, each page oldest first
+    let mut pages: VecDeque<Vec<Tick>> = VecDeque::new();
+    loop {
+        let res = client
+            .get_tick_data(symbol_id, quote_type as i32, from, to)
+            .await?;
+        let page = decode_tick_data(&res.tick_data);
+        let oldest = page.first().map(|t| t.timestamp);
+
+        // a page ends at the oldest millisecond of the previous page again, which
+        // may have been cut, so the ticks of that millisecond are taken from this page
+        if let Some(previous) = pages.front_mut() {
+            let boundary = previous.first().map(|t| t.timestamp);
+            if boundary.is_some() && page.last().map(|t| t.timestamp) == boundary {
+                previous.retain(|t| Some(t.timestamp) != boundary);
+            }
+        }
+        pages.push_front(page);
+
+        match oldest {
+            Some(oldest) if res.has_more => {
+                if oldest <= from || oldest >= to {
+                    warn!(
+                        "tick data page without progress at {}, stop paging window",
+                        oldest
+                    );
+                    break;
+                }
+                to = oldest;
+            }
+            _ => break,
+        }
+    }
+    Ok(pages.into_iter().flatten().collect())
+}
+
+// Tracks the latest bid and ask across windows.
+#[derive(Default, Debug, Clone, Copy)]
+struct QuoteMerger {
+    bid: Option<f64>,
+    ask: Option<f64>,
+}
+
+impl QuoteMerger {
+    // bids and asks oldest first, bid and ask changes on the same millisecond make one quote
+    fn merge(&mut self, bids: &[Tick], asks: &[Tick]) -> Vec<Quote> {
+        let mut quotes = Vec::with_capacity(bids.len().max(asks.len()));
+        let (mut i, mut j) = (0, 0);
+        while i < bids.len() || j < asks.len() {
+            let timestamp = match (bids.get(i), asks.get(j)) {
+                (Some(b), Some(a)) => b.timestamp.min(a.timestamp),
+                (Some(b), None) => b.timestamp,
+                (None, Some(a)) => a.timestamp,
+                (None, None) => break,
+            };
+            if bids.get(i).is_some_and(|b| b.timestamp == timestamp) {
+                self.bid = Some(bids[i].price);
+                i += 1;
+            }
+            if asks.get(j).is_some_and(|a| a.timestamp == timestamp) {
+                self.ask = Some(asks[j].price);
+                j += 1;
+            }
+            if let (Some(bid), Some(ask)) = (self.bid, self.ask) {
+                quotes.push(Quote {
+                    timestamp,
+                    ask,
+                    bid,
+                });
+            }
+        }
+        quotes
+    }
+}
@@ -1,10 +1,15 @@
 use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
 
-pub fn from_mill_seconds(mill_seconds: i64) -> chrono::DateTime<Utc> {
-    chrono::DateTime::<Utc>::from_naive_utc_and_offset(
-        NaiveDateTime::from_timestamp_millis(mill_seconds).unwrap(),
-        Utc,
-    )
+// Out of range timestamps map to the Unix epoch.
+pub fn from_mill_seconds(mill_seconds: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(mill_seconds)
+        .single()
+        .unwrap_or_default()
+}
+
+// 0 and missing timestamps are unset.
+pub fn from_opt_mill_seconds(mill_seconds: Option<i64>) -> Option<DateTime<Utc>> {
+    mill_seconds.filter(|ms| *ms > 0).map(from_mill_seconds)
 }
 
 pub fn timestamp_to_datetime(timestamp: i64) -> DateTime<Utc> {
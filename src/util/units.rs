@@ -0,0 +1,411 @@
+//! Exact price, volume and money amounts.
+//!
+//! The Open API sends prices as integers in 1/100000 of the quote currency, volumes in
+//! 0.01 of a unit (cents) and money in 10^-moneyDigits of the deposit currency. These
+//! types keep that integer representation and do the scaling in one place.
+//!
+//! Raw wire values go through `from_raw`/`from_cents`, `From<f64>` takes the decimal price.
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+
+use serde::{Deserialize, Serialize};
+
+use crate::protos::spotware_message::ProtoOaSymbol;
+
+/// Raw price units per 1.0
+pub const PRICE_SCALE: i64 = 100_000;
+/// Decimal places of a raw price
+pub const PRICE_DIGITS: i32 = 5;
+
+/// A price or a price distance in 1/100000.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub struct Price(i64);
+
+impl Price {
+    pub const ZERO: Price = Price(0);
+
+    pub const fn from_raw(raw: i64) -> Self {
+        Self(raw)
+    }
+
+    pub const fn raw(self) -> i64 {
+        self.0
+    }
+
+    // Rounded to the nearest 1/100000.
+    pub fn from_f64(price: f64) -> Self {
+        Self((price * PRICE_SCALE as f64).round() as i64)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / PRICE_SCALE as f64
+    }
+
+    // A distance of `pips`, a pip is 10^-pip_position.
+    pub fn from_pips(pips: f64, pip_position: i32) -> Self {
+        Self((pips * pip_raw(pip_position) as f64).round() as i64)
+    }
+
+    pub fn to_pips(self, pip_position: i32) -> f64 {
+        self.0 as f64 / pip_raw(pip_position) as f64
+    }
+
+    // A distance of `points`, a point is the last digit of the symbol, 10^-digits.
+    pub fn from_points(points: i64, digits: i32) -> Self {
+        Self(points * pip_raw(digits))
+    }
+
+    // Whole points, rounded.
+    pub fn to_points(self, digits: i32) -> i64 {
+        let point = pip_raw(digits);
+        (self.0 as f64 / point as f64).round() as i64
+    }
+
+    // Nearest price the symbol can quote.
+    pub fn round_to_digits(self, digits: i32) -> Self {
+        Self::from_points(self.to_points(digits), digits)
+    }
+
+    pub fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+
+    // With exactly `digits` decimals.
+    pub fn format(self, digits: i32) -> String {
+        let digits = digits.clamp(0, PRICE_DIGITS);
+        let rounded = self.round_to_digits(digits).0;
+        format_scaled(rounded / pip_raw(digits), digits as u32)
+    }
+}
+
+// raw units of 10^-position, positions past 5 digits round down to 1
+fn pip_raw(position: i32) -> i64 {
+    10i64.pow((PRICE_DIGITS - position.clamp(0, PRICE_DIGITS)) as u32)
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(PRICE_DIGITS))
+    }
+}
+
+impl From<f64> for Price {
+    fn from(price: f64) -> Self {
+        Self::from_f64(price)
+    }
+}
+
+impl From<Price> for f64 {
+    fn from(price: Price) -> Self {
+        price.to_f64()
+    }
+}
+
+impl Add for Price {
+    type Output = Price;
+    fn add(self, rhs: Price) -> Price {
+        Price(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Price {
+    type Output = Price;
+    fn sub(self, rhs: Price) -> Price {
+        Price(self.0 - rhs.0)
+    }
+}
+
+impl Neg for Price {
+    type Output = Price;
+    fn neg(self) -> Price {
+        Price(-self.0)
+    }
+}
+
+impl AddAssign for Price {
+    fn add_assign(&mut self, rhs: Price) {
+        self.0 += rhs.0;
+    }
+}
+
+impl SubAssign for Price {
+    fn sub_assign(&mut self, rhs: Price) {
+        self.0 -= rhs.0;
+    }
+}
+
+/// A volume in 0.01 of a unit, as used by the Open API.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub struct Volume(i64);
+
+impl Volume {
+    pub const ZERO: Volume = Volume(0);
+
+    pub const fn from_cents(cents: i64) -> Self {
+        Self(cents)
+    }
+
+    pub const fn cents(self) -> i64 {
+        self.0
+    }
+
+    pub fn from_units(units: f64) -> Self {
+        Self((units * 100.0).round() as i64)
+    }
+
+    pub fn units(self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    // `lot_size` in cents, as in ProtoOaSymbol.
+    pub fn from_lots(lots: f64, lot_size: i64) -> Self {
+        Self((lots * lot_size as f64).round() as i64)
+    }
+
+    pub fn lots(self, lot_size: i64) -> f64 {
+        if lot_size == 0 {
+            return 0.0;
+        }
+        self.0 as f64 / lot_size as f64
+    }
+
+    // Down to a multiple of `step` (cents).
+    pub fn round_to_step(self, step: i64) -> Self {
+        if step <= 0 {
+            return self;
+        }
+        Self(self.0 / step * step)
+    }
+}
+
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_scaled(self.0, 2))
+    }
+}
+
+impl From<Volume> for i64 {
+    fn from(volume: Volume) -> Self {
+        volume.0
+    }
+}
+
+impl Add for Volume {
+    type Output = Volume;
+    fn add(self, rhs: Volume) -> Volume {
+        Volume(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Volume {
+    type Output = Volume;
+    fn sub(self, rhs: Volume) -> Volume {
+        Volume(self.0 - rhs.0)
+    }
+}
+
+impl AddAssign for Volume {
+    fn add_assign(&mut self, rhs: Volume) {
+        self.0 += rhs.0;
+    }
+}
+
+impl SubAssign for Volume {
+    fn sub_assign(&mut self, rhs: Volume) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Sum for Volume {
+    fn sum<I: Iterator<Item = Volume>>(iter: I) -> Volume {
+        Volume(iter.map(|v| v.0).sum())
+    }
+}
+
+/// An amount of the deposit currency in 10^-digits, e.g. a balance with its money_digits.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Money {
+    value: i64,
+    digits: u32,
+}
+
+impl Money {
+    pub const fn new(value: i64, digits: u32) -> Self {
+        Self { value, digits }
+    }
+
+    pub fn from_f64(amount: f64, digits: u32) -> Self {
+        Self {
+            value: (amount * 10f64.powi(digits as i32)).round() as i64,
+            digits,
+        }
+    }
+
+    pub const fn value(self) -> i64 {
+        self.value
+    }
+
+    pub const fn digits(self) -> u32 {
+        self.digits
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.value as f64 / 10f64.powi(self.digits as i32)
+    }
+
+    // Same amount with more digits, or rounded to fewer.
+    pub fn rescale(self, digits: u32) -> Self {
+        let value = if digits >= self.digits {
+            self.value * 10i64.pow(digits - self.digits)
+        } else {
+            let div = 10i64.pow(self.digits - digits);
+            (self.value as f64 / div as f64).round() as i64
+        };
+        Self { value, digits }
+    }
+
+    pub fn abs(self) -> Self {
+        Self {
+            value: self.value.abs(),
+            digits: self.digits,
+        }
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_scaled(self.value, self.digits))
+    }
+}
+
+// 1.5 with 1 digit equals 1.50 with 2 digits
+impl PartialEq for Money {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Money {}
+
+impl Ord for Money {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let digits = self.digits.max(other.digits);
+        self.rescale(digits).value.cmp(&other.rescale(digits).value)
+    }
+}
+
+impl PartialOrd for Money {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// amounts with different digits are added at the finer scale
+impl Add for Money {
+    type Output = Money;
+    fn add(self, rhs: Money) -> Money {
+        let digits = self.digits.max(rhs.digits);
+        Money::new(
+            self.rescale(digits).value + rhs.rescale(digits).value,
+            digits,
+        )
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+    fn sub(self, rhs: Money) -> Money {
+        self + (-rhs)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+    fn neg(self) -> Money {
+        Money::new(-self.value, self.digits)
+    }
+}
+
+/// The scaling values of one symbol, see `SymbolStore::get_units_by_id`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymbolUnits {
+    pub digits: i32,
+    pub pip_position: i32,
+    /// Lot size in cents
+    pub lot_size: i64,
+    /// Minimum volume step in cents
+    pub step_volume: i64,
+}
+
+impl From<&ProtoOaSymbol> for SymbolUnits {
+    fn from(symbol: &ProtoOaSymbol) -> Self {
+        Self {
+            digits: symbol.digits,
+            pip_position: symbol.pip_position,
+            lot_size: symbol.lot_size.unwrap_or(100_000 * 100),
+            step_volume: symbol.step_volume.unwrap_or(1),
+        }
+    }
+}
+
+impl SymbolUnits {
+    pub fn pip(&self) -> Price {
+        Price::from_pips(1.0, self.pip_position)
+    }
+
+    pub fn point(&self) -> Price {
+        Price::from_points(1, self.digits)
+    }
+
+    pub fn pips(&self, distance: Price) -> f64 {
+        distance.to_pips(self.pip_position)
+    }
+
+    pub fn points(&self, distance: Price) -> i64 {
+        distance.to_points(self.digits)
+    }
+
+    pub fn from_pips(&self, pips: f64) -> Price {
+        Price::from_pips(pips, self.pip_position)
+    }
+
+    pub fn round_price(&self, price: Price) -> Price {
+        price.round_to_digits(self.digits)
+    }
+
+    pub fn format_price(&self, price: Price) -> String {
+        price.format(self.digits)
+    }
+
+    pub fn lots(&self, volume: Volume) -> f64 {
+        volume.lots(self.lot_size)
+    }
+
+    // Volume of `lots`, down to the volume step.
+    pub fn volume_from_lots(&self, lots: f64) -> Volume {
+        Volume::from_lots(lots, self.lot_size).round_to_step(self.step_volume)
+    }
+}
+
+// an integer of 10^-digits as a decimal string
+fn format_scaled(value: i64, digits: u32) -> String {
+    if digits == 0 {
+        return value.to_string();
+    }
+    let scale = 10u64.pow(digits);
+    let sign = if value < 0 { "-" } else { "" };
+    let abs = value.unsigned_abs();
+    format!(
+        "{}{}.{:0width$}",
+        sign,
+        abs / scale,
+        abs % scale,
+        width = digits as usize
+    )
+}
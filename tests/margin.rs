@@ -0,0 +1,69 @@
+//! Local margin against `expected_margin` on a live demo account.
+//!
+//! Reads the session config from the environment or a `.env` file, see `SessionConfig`.
+//! Run with `cargo test --test margin -- --ignored`.
+use ctrader_rs::util::{CurrencyConverter, MarginCalculator};
+use ctrader_rs::{ClientBuilder, Event, SessionConfig, Volume};
+use tokio::time::{timeout, Duration};
+
+const SYMBOL: &str = "EURUSD";
+const SPOT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test]
+#[ignore = "needs demo account credentials"]
+async fn cross_check_against_demo_account() {
+    dotenv::dotenv().ok();
+    let config = SessionConfig::load_from_env();
+    let mut session = ClientBuilder::default()
+        .set_url_string(&config.server_url)
+        .unwrap()
+        .set_application_credentials(config.application_credentials)
+        .set_account_credentials(config.account_credentials)
+        .build()
+        .unwrap();
+    session.connect().await.unwrap();
+
+    let account = session.account_info().await.unwrap();
+    let info = session.store.get_info_by_name(SYMBOL).unwrap().clone();
+    let symbol_id = info.info.symbol_id;
+    let mut calculator = MarginCalculator::new(&account, &session.store);
+    let mut converter = CurrencyConverter::new(&session.store);
+    converter
+        .live_rate(&mut session, info.base_asset_id, account.deposit_asset_id)
+        .await
+        .unwrap();
+
+    // the spread bounds the difference between the mid rates and the server's
+    let (bid, ask) = timeout(SPOT_TIMEOUT, async {
+        loop {
+            if let (Some(bid), Some(ask)) = (converter.bid(symbol_id), converter.ask(symbol_id)) {
+                return (bid.to_f64(), ask.to_f64());
+            }
+            match session.listen().await {
+                Some(Event::Message(msg)) => converter.on_event(&session.dispatch_event(msg).await),
+                Some(Event::Control(_)) => {}
+                None => panic!("connection closed"),
+            }
+        }
+    })
+    .await
+    .expect("no spot received");
+    let spread = (ask - bid) / ((ask + bid) / 2.0);
+
+    let volume = Volume::from_cents(info.info.min_volume.unwrap_or(100_000).max(100_000));
+    let (local, server) = calculator
+        .cross_check(&session, &mut converter, symbol_id, volume)
+        .await
+        .unwrap();
+    for (local, server) in [(local.buy, server.buy), (local.sell, server.sell)] {
+        let (local, server) = (local.to_f64(), server.to_f64());
+        assert!(
+            (local - server).abs() <= server * spread + 0.01,
+            "local {} server {} spread {}",
+            local,
+            server,
+            spread
+        );
+    }
+    session.shutdown().await.unwrap();
+}